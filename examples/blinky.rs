@@ -2,7 +2,7 @@
 #![no_main]
 
 use bl702_hal as hal;
-use embedded_hal::digital::OutputPin;
+use embedded_hal::digital::StatefulOutputPin;
 use hal::{
     clock::{board_clock_init, system_init, ClockConfig},
     delay::McycleDelay,
@@ -30,10 +30,7 @@ fn main() -> ! {
     let mut d = McycleDelay::new(clocks.sysclk().0);
 
     loop {
-        led.set_high().unwrap();
-        d.delay_ms(1000);
-
-        led.set_low().unwrap();
+        led.toggle().unwrap();
         d.delay_ms(1000);
     }
 }
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+//! Measures `delay::cycles` with a `Stopwatch` at 144 MHz and 32 MHz, for the WS2812 edges of
+//! 350 ns and 700 ns. The requested and measured cycle counts are printed on UART0.
+
+use bl702_hal as hal;
+use core::fmt::Write;
+use embedded_hal::delay::DelayNs;
+use hal::{
+    clock::{self, board_clock_init, system_init, ClockConfig, SysclkFreq},
+    delay::{cycles, ns_to_cycles, McycleDelay, Stopwatch},
+    pac,
+    prelude::*,
+    uart::*,
+};
+#[cfg(not(feature = "panic_serial"))]
+use panic_halt as _;
+
+const EDGES_NS: [u32; 2] = [350, 700];
+
+/// Requested and measured cycles of every edge at `cpu_hz`
+fn measure(cpu_hz: u32) -> [(u32, u64); 2] {
+    EDGES_NS.map(|ns| {
+        let n = ns_to_cycles(ns, cpu_hz);
+        let ((), taken) = riscv::interrupt::free(|| Stopwatch::measure(|| cycles(n)));
+        (n, taken)
+    })
+}
+
+#[riscv_rt::entry]
+fn main() -> ! {
+    // This *MUST* be called first
+    system_init();
+    // Set up default board clock config
+    board_clock_init();
+    let dp = pac::Peripherals::take().unwrap();
+    let mut parts = dp.GLB.split();
+    let clocks = ClockConfig::new().freeze(&mut parts.clk_cfg);
+
+    let tx = parts.pin14.into_uart0_tx(parts.uart_mux6);
+    let rx = parts.pin15.into_uart0_rx(parts.uart_mux7);
+    let mut serial = Serial::uart0(
+        dp.UART,
+        Config::default().baudrate(2_000_000.Bd()),
+        (tx, rx),
+        clocks,
+    );
+
+    let mut d = McycleDelay::new(clocks.sysclk().0);
+    loop {
+        // the UART has no clock at 32 MHz, print once back at 144 MHz
+        let fast = clock::reconfigure_sysclk(SysclkFreq::Pll144Mhz, &mut parts.clk_cfg);
+        let fast = measure(fast.sysclk().0);
+        let slow = clock::reconfigure_sysclk(SysclkFreq::Xtal32Mhz, &mut parts.clk_cfg);
+        let slow = measure(slow.sysclk().0);
+        let _ = clock::reconfigure_sysclk(SysclkFreq::Pll144Mhz, &mut parts.clk_cfg);
+        for (mhz, results) in [(144, fast), (32, slow)] {
+            for (ns, (requested, taken)) in EDGES_NS.iter().zip(results) {
+                let _ = writeln!(
+                    serial,
+                    "{} MHz, {} ns: requested {} cycles, took {} cycles\r",
+                    mhz, ns, requested, taken
+                );
+            }
+        }
+        d.delay_ms(1000);
+    }
+}
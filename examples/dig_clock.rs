@@ -0,0 +1,61 @@
+#![no_std]
+#![no_main]
+
+use bl702_hal as hal;
+use core::fmt::Write;
+use embedded_hal::delay::DelayNs;
+use hal::{
+    clock::{board_clock_init, enable_dig_32k, enable_dig_512k, system_init, ClockConfig},
+    delay::SleepDelay,
+    interrupt::{self, Interrupt},
+    pac,
+    prelude::*,
+    uart::*,
+};
+#[cfg(not(feature = "panic_serial"))]
+use panic_halt as _;
+
+#[export_name = "MachineTimer"]
+fn machine_timer() {
+    hal::timer::on_machine_timer();
+}
+
+#[riscv_rt::entry]
+fn main() -> ! {
+    // This *MUST* be called first
+    system_init();
+    // Set up default board clock config
+    board_clock_init();
+    let dp = pac::Peripherals::take().unwrap();
+    let mut parts = dp.GLB.split();
+    let clocks = ClockConfig::new().freeze(&mut parts.clk_cfg);
+
+    let tx = parts.pin14.into_uart0_tx(parts.uart_mux6);
+    let rx = parts.pin15.into_uart0_rx(parts.uart_mux7);
+    let mut serial = Serial::uart0(
+        dp.UART,
+        Config::default().baudrate(2_000_000.Bd()),
+        (tx, rx),
+        clocks,
+    );
+
+    let dig_32k = enable_dig_32k(&mut parts.clk_cfg, &clocks);
+    let dig_512k = enable_dig_512k(&mut parts.clk_cfg, &clocks);
+    writeln!(serial, "dig 32k: {} Hz, dig 512k: {} Hz\r", dig_32k.0, dig_512k.0).ok();
+
+    // Sleep in `wfi` and check that the DIG clocks are still enabled afterwards
+    unsafe { interrupt::enable(Interrupt::MTIP) };
+    let mut delay = SleepDelay::new(&clocks);
+    let glb = unsafe { &*pac::GLB::ptr() };
+    loop {
+        delay.delay_ms(1_000);
+        let ctrl = glb.dig32k_wakeup_ctrl.read();
+        writeln!(
+            serial,
+            "after wfi: dig 32k {}, dig 512k {}\r",
+            if ctrl.dig_32k_en().bit_is_set() { "on" } else { "off" },
+            if ctrl.dig_512k_en().bit_is_set() { "on" } else { "off" },
+        )
+        .ok();
+    }
+}
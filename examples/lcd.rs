@@ -32,20 +32,22 @@ fn main() -> ! {
 
     let sclk = parts.pin23.into_spi_sclk();
     let mosi = parts.pin24.into_spi_mosi();
-    let miso = parts.pin29.into_spi_miso(); // unbonded on bl702
     let dc = parts.pin25.into_floating_output();
     let mut cs = parts.pin1.into_floating_output();
     let rst = parts.pin10.into_floating_output(); // unbonded on bl702
 
     cs.set_high().unwrap();
 
-    let spi = hal::spi::Spi::new(
+    let config = hal::spi::Config::default()
+        .mode(embedded_hal::spi::MODE_0)
+        .frequency(9_000_000u32.Hz()); // fastest that obeys st7735 minimum high/low time with 36mhz bclk
+    let spi = hal::spi::Spi::with_config(
         dp.SPI,
-        (miso, mosi, sclk),
-        embedded_hal::spi::MODE_0,
-        9_000_000u32.Hz(), // fastest that obeys st7735 minimum high/low time with 36mhz bclk
+        (hal::spi::NoMiso, mosi, sclk), // display is write-only
+        config,
         clocks,
-    );
+    )
+    .unwrap();
 
     let mut d = McycleDelay::new(clocks.sysclk().0);
     cs.set_low().unwrap();
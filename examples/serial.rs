@@ -32,16 +32,14 @@ fn main() -> ! {
     let clocks = ClockConfig::new().freeze(&mut parts.clk_cfg);
 
     // Set up uart output. Since this microcontroller has a pin matrix,
-    // we need to set up both the pins and the muxs
-    let tx = parts.pin14.into_uart_sig6();
-    let rx = parts.pin15.into_uart_sig7();
-    let mux6 = parts.uart_mux6.into_uart0_tx();
-    let mux7 = parts.uart_mux7.into_uart0_rx();
+    // each pin is routed through the mux of its UART signal
+    let tx = parts.pin14.into_uart0_tx(parts.uart_mux6);
+    let rx = parts.pin15.into_uart0_rx(parts.uart_mux7);
     // Configure our UART to 2MBaud, and use the pins we configured above
     let mut serial = Serial::uart0(
         dp.UART,
         Config::default().baudrate(2_000_000.Bd()),
-        ((tx, mux6), (rx, mux7)),
+        (tx, rx),
         clocks,
     );
 
@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+//! SPI loopback test, connect pin24 (MOSI) to pin25 (MISO).
+//! Transfers with a write buffer longer than the read buffer must not overflow the RX FIFO,
+//! and the pipelined bulk transfers must receive the same words as a word by word transfer.
+//! Both run at 8 MHz and their durations are printed in cpu cycles, for a throughput comparison.
+
+use bl702_hal as hal;
+use core::fmt::Write;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::spi::SpiBus;
+use embedded_hal_nb::spi::FullDuplex;
+use hal::{
+    clock::{board_clock_init, system_init, ClockConfig},
+    delay::McycleDelay,
+    pac,
+    prelude::*,
+    uart::*,
+};
+#[cfg(not(feature = "panic_serial"))]
+use panic_halt as _;
+
+#[riscv_rt::entry]
+fn main() -> ! {
+    // This *MUST* be called first
+    system_init();
+    // Set up default board clock config
+    board_clock_init();
+    let dp = pac::Peripherals::take().unwrap();
+    let mut parts = dp.GLB.split();
+    let clocks = ClockConfig::new().freeze(&mut parts.clk_cfg);
+
+    let tx = parts.pin14.into_uart_sig6();
+    let rx = parts.pin15.into_uart_sig7();
+    let mux6 = parts.uart_mux6.into_uart0_tx();
+    let mux7 = parts.uart_mux7.into_uart0_rx();
+    let mut serial = Serial::uart0(
+        dp.UART,
+        Config::default().baudrate(2_000_000.Bd()),
+        ((tx, mux6), (rx, mux7)),
+        clocks,
+    );
+
+    let sclk = parts.pin23.into_spi_sclk();
+    let mosi = parts.pin24.into_spi_mosi();
+    let miso = parts.pin25.into_spi_miso();
+    let config = hal::spi::Config::default().frequency(8_000_000u32.Hz());
+    let mut spi = hal::spi::Spi::with_config(dp.SPI, (miso, mosi, sclk), config, clocks).unwrap();
+
+    let mut d = McycleDelay::new(clocks.sysclk().0);
+
+    let mut write = [0u8; 32];
+    for (i, word) in write.iter_mut().enumerate() {
+        *word = i as u8;
+    }
+
+    loop {
+        let mut read = [0u8; 2];
+        let result = spi.transfer(&mut read, &write);
+        let mut tail = [0u8; 4];
+        let result = result.and_then(|_| spi.transfer(&mut tail, &write[28..]));
+        // word by word reference, one frame in flight at a time
+        let mut reference = write;
+        let start = McycleDelay::get_cycle_count();
+        let result = result.and_then(|_| {
+            for word in reference.iter_mut() {
+                nb::block!(FullDuplex::<u8>::write(&mut spi, *word))?;
+                *word = nb::block!(FullDuplex::<u8>::read(&mut spi))?;
+            }
+            Ok(())
+        });
+        let word_by_word = McycleDelay::cycles_since(start);
+        let mut pipelined = write;
+        let start = McycleDelay::get_cycle_count();
+        let result = result.and_then(|_| spi.transfer_in_place(&mut pipelined));
+        let pipelined_cycles = McycleDelay::cycles_since(start);
+        match result {
+            Ok(()) if pipelined != reference => {
+                let _ = writeln!(serial, "pipelined mismatch: {:?}\r", pipelined);
+            }
+            Ok(()) if read == [0, 1] && tail == [28, 29, 30, 31] => {
+                let _ = writeln!(
+                    serial,
+                    "loopback ok, {} bytes: word by word {} cycles, pipelined {} cycles\r",
+                    write.len(),
+                    word_by_word,
+                    pipelined_cycles
+                );
+            }
+            Ok(()) => {
+                let _ = writeln!(serial, "loopback mismatch: {:?} {:?}\r", read, tail);
+            }
+            Err(e) => {
+                let _ = writeln!(serial, "loopback error: {:?}\r", e);
+            }
+        }
+        d.delay_ms(1000);
+    }
+}
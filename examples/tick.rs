@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+use bl702_hal as hal;
+use embedded_hal::digital::StatefulOutputPin;
+use hal::{
+    clock::{board_clock_init, system_init, ClockConfig},
+    gpio::{IsrCell, Output, Pin17, PullUp},
+    interrupt::{self, Interrupt},
+    pac,
+    prelude::*,
+    timer::{self, Tick},
+};
+#[cfg(not(feature = "panic_serial"))]
+use panic_halt as _;
+
+static LED: IsrCell<Pin17<Output<PullUp>>> = IsrCell::new();
+
+#[export_name = "MachineTimer"]
+fn machine_timer() {
+    timer::on_machine_timer();
+}
+
+fn on_tick() {
+    LED.borrow_in_isr(|led| led.toggle().unwrap());
+}
+
+#[riscv_rt::entry]
+fn main() -> ! {
+    // This *MUST* be called first
+    system_init();
+    // Set up default board clock config
+    board_clock_init();
+    let dp = pac::Peripherals::take().unwrap();
+    let mut parts = dp.GLB.split();
+    let clocks = ClockConfig::new().freeze(&mut parts.clk_cfg);
+
+    LED.lend(parts.pin17.into_pull_up_output());
+
+    // Toggle the LED twice a second from the tick interrupt
+    let tick = Tick::start(2u32.Hz(), &clocks);
+    tick.register_callback(on_tick);
+    unsafe {
+        interrupt::enable(Interrupt::MTIP);
+        riscv::interrupt::enable();
+    }
+
+    loop {
+        unsafe { riscv::asm::wfi() };
+    }
+}
@@ -1,18 +1,23 @@
 #![allow(non_camel_case_types, non_snake_case, clippy::upper_case_acronyms)]
 
 use crate::{
-    gpio::ClkCfg,
+    gpio::{pad, CamRefSource, ClkCfg, ClockOutPin, ClockOutSelect},
     system::{
+        self,
         glb::{self, *},
         hbn::{
-            self, HBN_32K_CLK_Type, HBN_32K_Sel, HBN_Power_On_Xtal_32K, HBN_Set_XCLK_CLK_Sel,
+            HBN_32K_CLK_Type, HBN_32K_Sel, HBN_Power_On_Xtal_32K, HBN_Set_XCLK_CLK_Sel,
             HBN_XCLK_CLK_Type,
         },
         pds,
     },
 };
+use crate::delay::McycleDelay;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use embedded_time::rate::Hertz;
 
+pub mod hbn;
+
 pub const BSP_FCLK_DIV: u8 = 0;
 pub const BSP_BCLK_DIV: u8 = 1;
 
@@ -34,47 +39,749 @@ pub enum system_clock_type {
 
 /// System bus frequency
 pub const SYSFREQ: u32 = 144_000_000;
+/// Internal 32 MHz RC oscillator frequency
+pub const RC32M_FREQ: u32 = 32_000_000;
 /// External high-speed crystal frequency
 pub const XTAL_FREQ: u32 = 32_000_000;
 /// UART peripheral clock frequency when PLL selected
 pub const UART_PLL_FREQ: u32 = 96_000_000;
+/// USB clock frequency, divided from the DLL
+pub const USB_FREQ: u32 = 48_000_000;
+/// Default highest flash clock, safe for every flash used with the BL702
+pub const FLASH_MAX_FREQ: u32 = 48_000_000;
+/// Machine timer (mtime) frequency `ClockConfig::freeze` aims for by default
+pub const MTIMER_FREQ: u32 = 1_000_000;
+/// Largest machine timer divider
+pub const MTIMER_MAX_DIV: u32 = 1 << 17;
+/// Lowest I2C clock that still reaches 400 kHz fast mode SCL
+pub const I2C_MIN_FREQ: u32 = 4 * 400_000;
+/// Highest I2C clock that still reaches 100 kHz standard mode SCL
+pub const I2C_MAX_FREQ: u32 = 4 * 256 * 100_000;
+/// Highest GPADC input clock
+pub const ADC_MAX_FREQ: u32 = 32_000_000;
+/// Longest time the 32 kHz crystal gets to start up
+pub const XTAL32K_STARTUP_MS: u32 = 1000;
+/// External 32 kHz crystal frequency
+pub const XTAL32K_FREQ: u32 = 32_768;
+/// Internal 32 kHz RC oscillator frequency
+pub const RC32K_FREQ: u32 = 32_000;
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+/// System clock taps of the DLL, or the crystal itself
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 #[repr(u32)]
 pub enum SysclkFreq {
+    Xtal32Mhz = 32_000_000,
+    Dll57_6Mhz = 57_600_000,
+    Dll96Mhz = 96_000_000,
     Pll144Mhz = 144_000_000,
 }
 
+impl SysclkFreq {
+    fn from_hertz(freq: Hertz) -> Option<Self> {
+        match freq.0 {
+            32_000_000 => Some(SysclkFreq::Xtal32Mhz),
+            57_600_000 => Some(SysclkFreq::Dll57_6Mhz),
+            96_000_000 => Some(SysclkFreq::Dll96Mhz),
+            144_000_000 => Some(SysclkFreq::Pll144Mhz),
+            _ => None,
+        }
+    }
+}
+
+/// Source of the UART clock, before the UART clock divider
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum UartClkSource {
+    /// The 96 MHz DLL tap, independent of the system clock
+    Pll96Mhz,
+    /// The system clock (FCLK)
+    Fclk,
+}
+
+/// Oscillator behind XCLK, which also feeds the DLL
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ClockSource {
+    /// The 32 MHz crystal
+    Xtal,
+    /// The internal RC oscillator, on a board without a crystal
+    Rc32m,
+    /// The internal RC oscillator because the crystal did not start, see
+    /// [`XtalFailPolicy::FallbackRc32m`]
+    Rc32mFallback,
+}
+
+/// Audio PLL output frequencies, 256 times the standard sample rates
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[repr(u32)]
+pub enum AudioPllFreq {
+    /// 48 kHz family, 256 x 48 kHz
+    Mhz12_288 = 12_288_000,
+    /// 44.1 kHz family, 256 x 44.1 kHz
+    Mhz11_2896 = 11_289_600,
+    /// 96 kHz family, 256 x 96 kHz
+    Mhz24_576 = 24_576_000,
+    /// 88.2 kHz family, 256 x 88.2 kHz
+    Mhz22_5792 = 22_579_200,
+}
+
+impl AudioPllFreq {
+    /// Post divider after the VCO, which runs around 200 MHz for every output
+    const fn postdiv(self) -> u8 {
+        match self {
+            AudioPllFreq::Mhz12_288 | AudioPllFreq::Mhz11_2896 => 18,
+            AudioPllFreq::Mhz24_576 | AudioPllFreq::Mhz22_5792 => 9,
+        }
+    }
+}
+
+/// Source of the 32 kHz clock used by the RTC and the HBN timers
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum F32kSource {
+    /// Internal RC oscillator, calibrated against XCLK by `ClockConfig::freeze`
+    Rc32k,
+    /// External 32.768 kHz crystal
+    Xtal32k,
+    /// XCLK divided down to 32 kHz by the DIG clock generator, see [`enable_dig_32k`]
+    ///
+    /// As accurate as the crystal, but the generator is in the GLB and stops in PDS and HBN.
+    Xclk,
+}
+
+/// Source of the PWM counters, shared by all five channels
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum PwmClkSource {
+    /// XCLK, the crystal or RC32M
+    Xclk = 0,
+    /// The peripheral bus clock
+    Bclk = 1,
+    /// The 32 kHz clock, see [`F32kSource`]
+    ///
+    /// The counters then do not depend on the bus clock and keep running while it is gated in
+    /// sleep, e.g. to keep an LED breathing. Only the `Pwm` gate in `ClkCfg` must stay enabled.
+    F32k = 2,
+}
+
+/// Source of the timer counters, shared by both timers
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum TimerClkSource {
+    /// The core clock (FCLK)
+    Fclk = 0,
+    /// The 32 kHz clock, see [`F32kSource`]
+    F32k = 1,
+    /// 1 kHz, divided from the 32 kHz clock
+    OneKhz = 2,
+    /// XCLK, the crystal or RC32M
+    Xclk = 3,
+}
+
+/// Clocks named in an [`Error`] or a [`Mismatch`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Clock {
+    /// The high-speed crystal
+    Xtal,
+    /// The core clock
+    Sysclk,
+    /// The peripheral bus clock
+    Bclk,
+    /// The SPI clock
+    Spi,
+    /// The PWM counter clock
+    Pwm,
+    /// The timer counter clock
+    Timer,
+    /// The UART clock
+    Uart,
+    /// The flash (XIP) clock
+    Flash,
+    /// The I2C clock
+    I2c,
+    /// The GPADC clock
+    Adc,
+    /// The machine timer clock
+    Mtimer,
+    /// The USB clock
+    Usb,
+    /// The chip clock outputs
+    ClockOut,
+    /// The audio PLL
+    AudioPll,
+    /// The 32 kHz clock
+    F32k,
+}
+
+/// Frequencies the DLL runs from
+const XTAL_FREQS: &[Hertz] = &[Hertz(XTAL_FREQ)];
+/// The crystal and the DLL taps. 120 MHz is a PLL tap of other BL70x parts, the BL702 DLL
+/// cannot produce it.
+const SYSCLK_FREQS: &[Hertz] = &[
+    Hertz(SysclkFreq::Xtal32Mhz as u32),
+    Hertz(SysclkFreq::Dll57_6Mhz as u32),
+    Hertz(SysclkFreq::Dll96Mhz as u32),
+    Hertz(SysclkFreq::Pll144Mhz as u32),
+];
+
+/// Clock configuration error, returned by `ClockConfig::try_freeze`
+///
+/// Every variant carries the requested value and what would have been accepted, the `Display`
+/// output is what `ClockConfig::freeze` panics with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// `clock` cannot run at `requested`, only at one of `supported`
+    UnreachableFrequency {
+        clock: Clock,
+        requested: Hertz,
+        supported: &'static [Hertz],
+    },
+    /// The divider of `clock` is outside of `min..=max`
+    DividerOutOfRange {
+        clock: Clock,
+        requested: u32,
+        min: u32,
+        max: u32,
+    },
+    /// `clock` would run at `result`, above its limit `max`
+    FrequencyTooHigh { clock: Clock, result: Hertz, max: Hertz },
+    /// `clock` would run at `result`, below its limit `min`
+    FrequencyTooLow { clock: Clock, result: Hertz, min: Hertz },
+    /// The flash clock source cannot be divided down to `flash_max_clk`, the lowest flash clock
+    /// is `min`
+    XipConstraintViolated { flash_max_clk: Hertz, min: Hertz },
+    /// The crystal `clock` runs from did not report ready within `timeout_ms`
+    CrystalTimeout { clock: Clock, timeout_ms: u32 },
+    /// `clock` needs the DLL, which is not used when the system clock runs from the crystal.
+    /// Select one of the DLL taps as sysclk.
+    DllUnavailable { clock: Clock },
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            Error::UnreachableFrequency {
+                clock,
+                requested,
+                supported,
+            } => {
+                write!(f, "{:?} clock cannot run at {} Hz, supported:", clock, requested.0)?;
+                for freq in supported {
+                    write!(f, " {} Hz", freq.0)?;
+                }
+                Ok(())
+            }
+            Error::DividerOutOfRange {
+                clock,
+                requested,
+                min,
+                max,
+            } => write!(
+                f,
+                "{:?} clock divider {} is outside of {}..={}",
+                clock, requested, min, max
+            ),
+            Error::FrequencyTooHigh { clock, result, max } => write!(
+                f,
+                "{:?} clock would run at {} Hz, above its limit of {} Hz",
+                clock, result.0, max.0
+            ),
+            Error::FrequencyTooLow { clock, result, min } => write!(
+                f,
+                "{:?} clock would run at {} Hz, below its limit of {} Hz",
+                clock, result.0, min.0
+            ),
+            Error::XipConstraintViolated { flash_max_clk, min } => write!(
+                f,
+                "flash clock cannot be divided down to {} Hz, the lowest is {} Hz",
+                flash_max_clk.0, min.0
+            ),
+            Error::CrystalTimeout { clock, timeout_ms } => write!(
+                f,
+                "{:?} clock reference crystal not ready after {} ms",
+                clock, timeout_ms
+            ),
+            Error::DllUnavailable { clock } => write!(
+                f,
+                "{:?} clock needs the DLL, which is off while sysclk runs from the crystal",
+                clock
+            ),
+        }
+    }
+}
+
 /// Frozen clock frequencies
 ///
 /// The existance of this value indicates that the clock configuration can no longer be changed
 #[derive(Clone, Copy)]
 pub struct Clocks {
     sysclk: Hertz,
+    bclk: Hertz,
+    xclk: Hertz,
     uart_clk: Hertz,
     spi_clk: Hertz,
+    i2c_clk: Hertz,
+    adc_clk: Hertz,
+    flash_clk: Hertz,
+    mtimer_clk: Hertz,
+    usb_clk: Hertz,
+    audio_pll_clk: Hertz,
+    pwm_clk: Hertz,
+    timer_clk: Hertz,
+    f32k: Hertz,
+    f32k_source: F32kSource,
+    xclk_source: ClockSource,
+    rc32m_trimmed: bool,
+    generation: u32,
 }
 
 impl Clocks {
     pub fn new() -> Self {
         Clocks {
             sysclk: Hertz(SYSFREQ),
+            bclk: Hertz(SYSFREQ / 2),
+            xclk: Hertz(XTAL_FREQ),
             uart_clk: Hertz(UART_PLL_FREQ),
             spi_clk: Hertz(SYSFREQ / 4),
+            i2c_clk: Hertz(0),
+            adc_clk: Hertz(0),
+            flash_clk: Hertz(0),
+            mtimer_clk: Hertz(0),
+            usb_clk: Hertz(0),
+            audio_pll_clk: Hertz(0),
+            pwm_clk: Hertz(0),
+            timer_clk: Hertz(0),
+            f32k: Hertz(RC32K_FREQ),
+            f32k_source: F32kSource::Rc32k,
+            xclk_source: ClockSource::Xtal,
+            rc32m_trimmed: RC32M_TRIMMED.load(Ordering::Relaxed),
+            generation: GENERATION.load(Ordering::Relaxed),
         }
     }
 
+    /// Read the clock tree back from the divider and source registers
+    ///
+    /// Disabled clocks read as 0 Hz.
+    fn from_registers() -> Self {
+        let glb = unsafe { glb::ptr() };
+        let hbn = unsafe { system::hbn::ptr() };
+        let clk_cfg0 = glb.clk_cfg0.read();
+        let clk_cfg1 = glb.clk_cfg1.read();
+        let clk_cfg2 = glb.clk_cfg2.read();
+        let clk_cfg3 = glb.clk_cfg3.read();
+        let cpu_clk_cfg = glb.cpu_clk_cfg.read();
+        let hbn_glb = hbn.hbn_glb.read();
+
+        let root = match hbn_glb.hbn_root_clk_sel().bits() {
+            0 | 1 => 32_000_000, // RC32M or the crystal
+            _ => match clk_cfg0.reg_pll_sel().bits() {
+                0 => 57_600_000,
+                1 => 96_000_000,
+                2 => 144_000_000,
+                _ => 0,
+            },
+        };
+        let sysclk = root / (clk_cfg0.reg_hclk_div().bits() as u32 + 1);
+        let bclk = sysclk / (clk_cfg0.reg_bclk_div().bits() as u32 + 1);
+        let divided = |enabled: bool, source: u32, div: u8| {
+            if enabled { source / (div as u32 + 1) } else { 0 }
+        };
+
+        let uart_source = if hbn_glb.hbn_uart_clk_sel().bit() { UART_PLL_FREQ } else { sysclk };
+        let gpadc = glb.gpadc_32m_src_ctrl.read();
+        // gpadc_32m_clk_sel picks the crystal over the 96 MHz DLL tap
+        let adc_source = if gpadc.gpadc_32m_clk_sel().bit() { XTAL_FREQ } else { 96_000_000 };
+        let f32k_source = f32k_source();
+        let f32k = match f32k_source {
+            F32kSource::Xtal32k => XTAL32K_FREQ,
+            F32kSource::Rc32k => RC32K_FREQ,
+            F32kSource::Xclk => XTAL_FREQ / DIG_32K_DIV,
+        };
+
+        let flash_source = match (clk_cfg2.sf_clk_sel().bits(), clk_cfg2.sf_clk_sel2().bits()) {
+            (0, 0) => 144_000_000,
+            (0, 1) => XTAL_FREQ,
+            (0, _) => 57_600_000,
+            (1, _) => 72_000_000,
+            (2, _) => bclk,
+            _ => 96_000_000,
+        };
+
+        let pds = unsafe { pds::ptr() };
+        let audio_pll_clk = if pds.pu_rst_clkpll.read().pu_clkpll().bit() {
+            let top_ctrl = pds.clkpll_top_ctrl.read();
+            let reference = XTAL_FREQ as u64 / top_ctrl.clkpll_refdiv_ratio().bits().max(1) as u64;
+            let vco = (reference * pds.clkpll_sdm.read().clkpll_sdmin().bits() as u64)
+                >> AUDIO_PLL_SDM_FRAC_BITS;
+            (vco / top_ctrl.clkpll_postdiv().bits().max(1) as u64) as u32
+        } else {
+            0
+        };
+
+        let mtimer_source = if cpu_clk_cfg.cpu_rtc_sel().bit() { f32k } else { bclk };
+
+        // the PWM and timer registers can only be read with their gate enabled
+        let cgen_cfg1 = glb.cgen_cfg1.read();
+        let pwm_clk = if cgen_cfg1.pwm().bit() {
+            let pwm = unsafe { &*bl702_pac::PWM::ptr() };
+            match pwm.pwm0_config.read().reg_clk_sel().bits() {
+                0 => XTAL_FREQ,
+                1 => bclk,
+                _ => f32k,
+            }
+        } else {
+            0
+        };
+        let timer_clk = if cgen_cfg1.tmr().bit() {
+            let timer = unsafe { &*bl702_pac::TIMER::ptr() };
+            match timer.tccr.read().cs_1().bits() {
+                0 => sysclk,
+                1 => f32k,
+                2 => 1_000,
+                _ => XTAL_FREQ,
+            }
+        } else {
+            0
+        };
+
+        Clocks {
+            sysclk: Hertz(sysclk),
+            bclk: Hertz(bclk),
+            xclk: Hertz(XTAL_FREQ),
+            uart_clk: Hertz(divided(
+                clk_cfg2.uart_clk_en().bit(),
+                uart_source,
+                clk_cfg2.uart_clk_div().bits(),
+            )),
+            spi_clk: Hertz(divided(
+                clk_cfg3.spi_clk_en().bit(),
+                bclk,
+                clk_cfg3.spi_clk_div().bits(),
+            )),
+            i2c_clk: Hertz(divided(
+                clk_cfg3.i2c_clk_en().bit(),
+                bclk,
+                clk_cfg3.i2c_clk_div().bits(),
+            )),
+            adc_clk: Hertz(divided(
+                gpadc.gpadc_32m_div_en().bit(),
+                adc_source,
+                gpadc.gpadc_32m_clk_div().bits(),
+            )),
+            flash_clk: Hertz(divided(
+                clk_cfg2.sf_clk_en().bit(),
+                flash_source,
+                clk_cfg2.sf_clk_div().bits(),
+            )),
+            mtimer_clk: Hertz(if cpu_clk_cfg.cpu_rtc_en().bit() {
+                mtimer_source / (cpu_clk_cfg.cpu_rtc_div().bits() + 1)
+            } else {
+                0
+            }),
+            usb_clk: Hertz(if clk_cfg1.dll_48m_div_en().bit() && clk_cfg1.usb_clk_en().bit() {
+                USB_FREQ
+            } else {
+                0
+            }),
+            audio_pll_clk: Hertz(audio_pll_clk),
+            pwm_clk: Hertz(pwm_clk),
+            timer_clk: Hertz(timer_clk),
+            f32k: Hertz(f32k),
+            f32k_source,
+            xclk_source: if hbn_glb.hbn_root_clk_sel().bits() & 1 != 0 {
+                ClockSource::Xtal
+            } else if XTAL_FALLBACK.load(Ordering::Relaxed) {
+                ClockSource::Rc32mFallback
+            } else {
+                ClockSource::Rc32m
+            },
+            rc32m_trimmed: RC32M_TRIMMED.load(Ordering::Relaxed),
+            generation: GENERATION.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns false once the system clock was switched after these clocks were read, see
+    /// [`reconfigure_sysclk`]
+    pub fn is_current(&self) -> bool {
+        self.generation == GENERATION.load(Ordering::Relaxed)
+    }
+
+    /// Core clock (FCLK/HCLK)
     pub fn sysclk(&self) -> Hertz {
         self.sysclk
     }
 
+    /// Peripheral bus clock
+    pub const fn bclk(&self) -> Hertz {
+        self.bclk
+    }
+
+    /// XCLK, the crystal or RC32M, see `xclk_source`
+    pub const fn xclk(&self) -> Hertz {
+        self.xclk
+    }
+
+    /// Oscillator behind XCLK and the DLL
+    ///
+    /// With `ClockSource::Rc32mFallback` the crystal did not start, all clocks then only have
+    /// the accuracy of RC32M, see `rc32m_trimmed`.
+    pub const fn xclk_source(&self) -> ClockSource {
+        self.xclk_source
+    }
+
+    /// UART clock after the UART clock divider, as programmed by `ClockConfig::freeze`
     pub const fn uart_clk(&self) -> Hertz {
         self.uart_clk
     }
 
+    /// SPI clock after the SPI clock divider
     pub const fn spi_clk(&self) -> Hertz {
         self.spi_clk
     }
+
+    /// I2C clock after the I2C clock divider, 0 Hz while the I2C clock is disabled
+    ///
+    /// Each of the four SCL phases lasts 1 to 256 cycles of this clock, see
+    /// `ClockConfig::i2c_clk_div`.
+    pub const fn i2c_clk(&self) -> Hertz {
+        self.i2c_clk
+    }
+
+    /// Flash (XIP) controller clock after the flash clock divider
+    pub const fn flash_clk(&self) -> Hertz {
+        self.flash_clk
+    }
+
+    /// Frequency the RISC-V `mtime` counter counts at
+    pub const fn mtimer_clk(&self) -> Hertz {
+        self.mtimer_clk
+    }
+
+    /// USB clock, 0 Hz unless `ClockConfig::enable_usb` was used
+    pub const fn usb_clk(&self) -> Hertz {
+        self.usb_clk
+    }
+
+    /// Audio PLL output, 0 Hz while it is powered down
+    ///
+    /// This is the frequency the fractional divider produces, within a few Hz of the
+    /// [`AudioPllFreq`] asked for.
+    pub const fn audio_pll_clk(&self) -> Hertz {
+        self.audio_pll_clk
+    }
+
+    /// Clock the PWM channels count, before their own dividers, 0 Hz while the PWM is gated
+    pub const fn pwm_clk(&self) -> Hertz {
+        self.pwm_clk
+    }
+
+    /// GPADC clock after the GPADC divider, 0 Hz while the divider is disabled
+    pub const fn adc_clk(&self) -> Hertz {
+        self.adc_clk
+    }
+
+    /// Clock the timers count, before their own dividers, 0 Hz while the timers are gated
+    pub const fn timer_clk(&self) -> Hertz {
+        self.timer_clk
+    }
+
+    /// Assumed frequency of the RC32M oscillator, XCLK and the DLL reference on boards
+    /// without a crystal
+    ///
+    /// The factory trim brings it close to 32 MHz, see `rc32m_trimmed`. There is no reference
+    /// to measure it against without a crystal.
+    pub const fn rc32m(&self) -> Hertz {
+        Hertz(RC32M_FREQ)
+    }
+
+    /// Returns true if `apply_rc32m_trim` found a valid trim in the efuse; otherwise RC32M
+    /// runs at its untrimmed frequency, which can be far enough off to break UART framing
+    pub const fn rc32m_trimmed(&self) -> bool {
+        self.rc32m_trimmed
+    }
+
+    /// Nominal frequency of the 32 kHz clock
+    pub const fn f32k(&self) -> Hertz {
+        self.f32k
+    }
+
+    /// Source of the 32 kHz clock
+    ///
+    /// `Rc32k` after `ClockConfig::f32k_source(F32kSource::Xtal32k)` means the crystal did
+    /// not start and `freeze` fell back to the RC oscillator.
+    pub const fn f32k_source(&self) -> F32kSource {
+        self.f32k_source
+    }
+}
+
+/// A clock in `Clocks` that no longer matches the hardware, returned by `Clocks::verify`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    pub clock: Clock,
+    /// The frequency recorded in `Clocks`
+    pub cached: Hertz,
+    /// The frequency the registers produce now
+    pub hardware: Hertz,
+}
+
+impl Clocks {
+    /// Compare the recorded frequencies against the current register contents
+    ///
+    /// Returns the first clock that differs, e.g. because a divider was written behind the
+    /// HAL's back.
+    pub fn verify(&self) -> Result<(), Mismatch> {
+        let hw = Clocks::from_registers();
+        let pairs = [
+            (Clock::Sysclk, self.sysclk, hw.sysclk),
+            (Clock::Bclk, self.bclk, hw.bclk),
+            (Clock::Xtal, self.xclk, hw.xclk),
+            (Clock::Uart, self.uart_clk, hw.uart_clk),
+            (Clock::Spi, self.spi_clk, hw.spi_clk),
+            (Clock::I2c, self.i2c_clk, hw.i2c_clk),
+            (Clock::Adc, self.adc_clk, hw.adc_clk),
+            (Clock::Flash, self.flash_clk, hw.flash_clk),
+            (Clock::Mtimer, self.mtimer_clk, hw.mtimer_clk),
+            (Clock::Usb, self.usb_clk, hw.usb_clk),
+            (Clock::AudioPll, self.audio_pll_clk, hw.audio_pll_clk),
+            (Clock::Pwm, self.pwm_clk, hw.pwm_clk),
+            (Clock::Timer, self.timer_clk, hw.timer_clk),
+            (Clock::F32k, self.f32k, hw.f32k),
+        ];
+        match pairs.iter().find(|(_, cached, hardware)| cached != hardware) {
+            Some(&(clock, cached, hardware)) => Err(Mismatch {
+                clock,
+                cached,
+                hardware,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Print the clock tree as the hardware is configured right now
+///
+/// Every line is decoded from the GLB, HBN and PDS registers, with the raw divider and select
+/// values next to the resulting frequency, so a divider clobbered outside of the HAL shows up:
+///
+/// ```rust
+///   // any core::fmt::Write, such as the UART
+///   clock::dump_tree(&mut serial).ok();
+/// ```
+pub fn dump_tree(w: &mut impl core::fmt::Write) -> core::fmt::Result {
+    let glb = unsafe { glb::ptr() };
+    let hbn = unsafe { system::hbn::ptr() };
+    let pds = unsafe { pds::ptr() };
+    let clk_cfg0 = glb.clk_cfg0.read();
+    let clk_cfg1 = glb.clk_cfg1.read();
+    let clk_cfg2 = glb.clk_cfg2.read();
+    let clk_cfg3 = glb.clk_cfg3.read();
+    let hbn_glb = hbn.hbn_glb.read();
+    let clocks = Clocks::from_registers();
+    let on_off = |en: bool| if en { "on" } else { "off" };
+
+    let root_sel = hbn_glb.hbn_root_clk_sel().bits();
+    let xclk_source = if root_sel & 1 != 0 { "XTAL" } else { "RC32M" };
+    let root_source = if root_sel & 2 != 0 { "DLL" } else { "XCLK" };
+    writeln!(w, "xclk       {} Hz from {}", clocks.xclk.0, xclk_source)?;
+    writeln!(
+        w,
+        "root       {} (hbn_root_clk_sel {:#04b}, reg_pll_sel {}, dll {})",
+        root_source,
+        root_sel,
+        clk_cfg0.reg_pll_sel().bits(),
+        on_off(glb.dll.read().pu_dll().bit())
+    )?;
+    writeln!(
+        w,
+        "  fclk     {} Hz (hclk_div {})",
+        clocks.sysclk.0,
+        clk_cfg0.reg_hclk_div().bits()
+    )?;
+    writeln!(
+        w,
+        "    bclk   {} Hz (bclk_div {})",
+        clocks.bclk.0,
+        clk_cfg0.reg_bclk_div().bits()
+    )?;
+    writeln!(
+        w,
+        "      spi  {} Hz (spi_clk_div {}, {})",
+        clocks.spi_clk.0,
+        clk_cfg3.spi_clk_div().bits(),
+        on_off(clk_cfg3.spi_clk_en().bit())
+    )?;
+    writeln!(
+        w,
+        "      i2c  {} Hz (i2c_clk_div {}, {})",
+        clocks.i2c_clk.0,
+        clk_cfg3.i2c_clk_div().bits(),
+        on_off(clk_cfg3.i2c_clk_en().bit())
+    )?;
+    writeln!(
+        w,
+        "uart       {} Hz from {} (uart_clk_div {}, {})",
+        clocks.uart_clk.0,
+        if hbn_glb.hbn_uart_clk_sel().bit() { "DLL 96 MHz" } else { "fclk" },
+        clk_cfg2.uart_clk_div().bits(),
+        on_off(clk_cfg2.uart_clk_en().bit())
+    )?;
+    writeln!(
+        w,
+        "flash      {} Hz (sf_clk_sel {}, sf_clk_sel2 {}, sf_clk_div {}, {})",
+        clocks.flash_clk.0,
+        clk_cfg2.sf_clk_sel().bits(),
+        clk_cfg2.sf_clk_sel2().bits(),
+        clk_cfg2.sf_clk_div().bits(),
+        on_off(clk_cfg2.sf_clk_en().bit())
+    )?;
+    let gpadc = glb.gpadc_32m_src_ctrl.read();
+    writeln!(
+        w,
+        "adc        {} Hz from {} (gpadc_32m_clk_div {}, {})",
+        clocks.adc_clk.0,
+        if gpadc.gpadc_32m_clk_sel().bit() { "XTAL" } else { "DLL 96 MHz" },
+        gpadc.gpadc_32m_clk_div().bits(),
+        on_off(gpadc.gpadc_32m_div_en().bit())
+    )?;
+    let cpu_clk_cfg = glb.cpu_clk_cfg.read();
+    writeln!(
+        w,
+        "mtimer     {} Hz from {} (cpu_rtc_div {}, {})",
+        clocks.mtimer_clk.0,
+        if cpu_clk_cfg.cpu_rtc_sel().bit() { "f32k" } else { "bclk" },
+        cpu_clk_cfg.cpu_rtc_div().bits(),
+        on_off(cpu_clk_cfg.cpu_rtc_en().bit())
+    )?;
+    writeln!(
+        w,
+        "usb        {} Hz (dll_48m_div {}, usb_clk {})",
+        clocks.usb_clk.0,
+        on_off(clk_cfg1.dll_48m_div_en().bit()),
+        on_off(clk_cfg1.usb_clk_en().bit())
+    )?;
+    let top_ctrl = pds.clkpll_top_ctrl.read();
+    writeln!(
+        w,
+        "audio pll  {} Hz (refdiv {}, sdmin {:#08x}, postdiv {}, {})",
+        clocks.audio_pll_clk.0,
+        top_ctrl.clkpll_refdiv_ratio().bits(),
+        pds.clkpll_sdm.read().clkpll_sdmin().bits(),
+        top_ctrl.clkpll_postdiv().bits(),
+        on_off(pds.pu_rst_clkpll.read().pu_clkpll().bit())
+    )?;
+    writeln!(w, "pwm        {} Hz", clocks.pwm_clk.0)?;
+    writeln!(w, "timer      {} Hz", clocks.timer_clk.0)?;
+    writeln!(
+        w,
+        "f32k       {} Hz from {:?} (hbn_f32k_sel {})",
+        clocks.f32k.0,
+        clocks.f32k_source,
+        hbn_glb.hbn_f32k_sel().bits()
+    )?;
+    let rc32m = pds.rc32m_ctrl0.read();
+    writeln!(
+        w,
+        "rc32m      code {} ({})",
+        rc32m.rc32m_code_fr_ext().bits(),
+        if rc32m.rc32m_ext_code_en().bit() { "trimmed" } else { "untrimmed" }
+    )?;
+    writeln!(w, "gates      cgen_cfg1 {:#010x}", glb.cgen_cfg1.read().bits())
 }
 
 impl Default for Clocks {
@@ -84,36 +791,307 @@ impl Default for Clocks {
 }
 
 pub struct ClockConfig {
-    sysclk: SysclkFreq,
+    xtal: Hertz,
+    sysclk: Hertz,
+    uart_clk: Option<(UartClkSource, u8)>,
+    f32k: Option<F32kSource>,
+    i2c_clk_div: Option<u16>,
+    adc_clk_div: Option<u8>,
+    flash_max_clk: Hertz,
+    mtimer_div: Option<u32>,
+    usb: bool,
+    audio_pll: Option<AudioPllFreq>,
+    pwm_clk: Option<PwmClkSource>,
+    timer_clk: Option<TimerClkSource>,
 }
 
 impl ClockConfig {
-    /// Create initial clock config
+    /// Create initial clock config, 144 MHz from the DLL on the 32 MHz crystal
     pub fn new() -> Self {
         ClockConfig {
-            sysclk: SysclkFreq::Pll144Mhz,
+            xtal: Hertz(XTAL_FREQ),
+            sysclk: Hertz(SysclkFreq::Pll144Mhz as u32),
+            uart_clk: Some((UartClkSource::Pll96Mhz, 1)),
+            f32k: None,
+            i2c_clk_div: None,
+            adc_clk_div: None,
+            flash_max_clk: Hertz(FLASH_MAX_FREQ),
+            mtimer_div: None,
+            usb: false,
+            audio_pll: None,
+            pwm_clk: None,
+            timer_clk: None,
         }
     }
 
-    /// Calculate and balance clock registers to configure into the given clock value.
-    /// Will choose closest valid value if it can't accurately select a frequency
-    pub fn freeze(self, _clk_cfg: &mut ClkCfg) -> Clocks {
-        let pll_enabled = true;
-        let sysclk = self.sysclk;
-        let uart_clk_div = 1; // leave uart clock at 96mhz
-        let spi_clk_div = 4;
+    /// Run the DLL from a crystal of frequency `xtal`, only 32 MHz is supported
+    pub fn use_pll(mut self, xtal: impl Into<Hertz>) -> Self {
+        self.xtal = xtal.into();
+        self
+    }
 
-        unsafe { hbn::ptr() }
-            .hbn_glb
-            .modify(|_, w| w.hbn_uart_clk_sel().bit(pll_enabled));
+    /// Select the system clock, see [`SysclkFreq`] for the valid frequencies
+    pub fn sysclk(mut self, freq: impl Into<Hertz>) -> Self {
+        self.sysclk = freq.into();
+        self
+    }
 
-        // Write UART clock divider
-        unsafe { glb::ptr() }.clk_cfg2.modify(|_, w| unsafe {
-            w.uart_clk_div()
-                .bits(uart_clk_div - 1_u8)
-                .uart_clk_en()
-                .set_bit()
-        });
+    /// Select the UART clock source, by default the 96 MHz DLL tap
+    pub fn uart_clk_source(mut self, source: UartClkSource) -> Self {
+        let div = self.uart_clk.map_or(1, |(_, div)| div);
+        self.uart_clk = Some((source, div));
+        self
+    }
+
+    /// Divide the UART clock source by `div`, 1 to 8, by default 1
+    pub fn uart_clk_div(mut self, div: u8) -> Self {
+        let source = self.uart_clk.map_or(UartClkSource::Pll96Mhz, |(source, _)| source);
+        self.uart_clk = Some((source, div));
+        self
+    }
+
+    /// Leave the UART clock disabled, `Clocks::uart_clk` reports 0 Hz
+    pub fn disable_uart_clk(mut self) -> Self {
+        self.uart_clk = None;
+        self
+    }
+
+    /// Select the 32 kHz clock source, by default it is left as is
+    ///
+    /// For `Xtal32k` `freeze` powers the crystal oscillator and waits up to
+    /// [`XTAL32K_STARTUP_MS`] for it to run, falling back to `Rc32k` if it does not, see
+    /// `Clocks::f32k_source`. For `Rc32k` the oscillator is calibrated against XCLK.
+    pub fn f32k_source(mut self, source: F32kSource) -> Self {
+        self.f32k = Some(source);
+        self
+    }
+
+    /// Enable the I2C clock and its gate, dividing the bus clock by `div` (1 to 256), by default
+    /// the I2C clock is left as is
+    ///
+    /// Each of the four phases of an SCL period lasts 1 to 256 I2C clock cycles, so SCL runs
+    /// from `i2c_clk / 1024` to `i2c_clk / 4`. `freeze` requires an I2C clock between
+    /// [`I2C_MIN_FREQ`] and [`I2C_MAX_FREQ`], which reaches both 100 kHz and 400 kHz. With the
+    /// 72 MHz bus clock of the default 144 MHz sysclk:
+    ///
+    /// | `div` | `i2c_clk` | SCL range           |
+    /// |-------|-----------|---------------------|
+    /// | 1     | 72 MHz    | 70.3 kHz to 18 MHz  |
+    /// | 2     | 36 MHz    | 35.2 kHz to 9 MHz   |
+    /// | 4     | 18 MHz    | 17.6 kHz to 4.5 MHz |
+    /// | 9     | 8 MHz     | 7.8 kHz to 2 MHz    |
+    /// | 45    | 1.6 MHz   | 1.6 kHz to 400 kHz  |
+    pub fn i2c_clk_div(mut self, div: u16) -> Self {
+        self.i2c_clk_div = Some(div);
+        self
+    }
+
+    /// Enable the GPADC clock, divided by `div` (1 to 64) from the 96 MHz DLL tap, or from
+    /// XCLK when the system clock runs from the crystal
+    ///
+    /// The result must not exceed [`ADC_MAX_FREQ`], so with the DLL `div` is at least 3.
+    pub fn adc_clk(mut self, div: u8) -> Self {
+        self.adc_clk_div = Some(div);
+        self
+    }
+
+    /// Highest clock the connected flash is rated for, by default [`FLASH_MAX_FREQ`]
+    ///
+    /// `freeze` divides the flash clock source down to at most this frequency. Raise it to
+    /// 72 MHz only for flash rated for it, XIP faults when the flash is clocked too fast.
+    pub fn flash_max_clk(mut self, freq: impl Into<Hertz>) -> Self {
+        self.flash_max_clk = freq.into();
+        self
+    }
+
+    /// Divide the bus clock by `div` (1 to [`MTIMER_MAX_DIV`]) for the `mtime` counter
+    ///
+    /// By default `freeze` picks the divider closest to [`MTIMER_FREQ`], which is exact at
+    /// every system clock but 57.6 MHz. `Clocks::mtimer_clk` has the resulting frequency.
+    pub fn mtimer_div(mut self, div: u32) -> Self {
+        self.mtimer_div = Some(div);
+        self
+    }
+
+    /// Enable the 48 MHz USB clock, divided from the DLL
+    ///
+    /// `try_freeze` fails with `Error::DllUnavailable` if the system clock runs from the
+    /// crystal, since the DLL is then not in use.
+    pub fn enable_usb(mut self) -> Self {
+        self.usb = true;
+        self
+    }
+
+    /// Start the audio PLL at `freq`, by default it is left as is
+    ///
+    /// The PLL runs from the crystal, `try_freeze` fails with `Error::CrystalTimeout` if the
+    /// crystal is not running. The PLL has no lock indicator, `try_freeze` waits the settling
+    /// time of the loop instead.
+    pub fn audio_pll(mut self, freq: AudioPllFreq) -> Self {
+        self.audio_pll = Some(freq);
+        self
+    }
+
+    /// Select the clock of all PWM channels and enable the PWM gate, by default it is left as is
+    ///
+    /// ```rust
+    ///   // breathe an LED from the 32 kHz clock while the core sleeps
+    ///   let clocks = ClockConfig::new()
+    ///       .f32k_source(F32kSource::Xtal32k)
+    ///       .pwm_clk_source(PwmClkSource::F32k)
+    ///       .freeze(&mut parts.clk_cfg);
+    /// ```
+    pub fn pwm_clk_source(mut self, source: PwmClkSource) -> Self {
+        self.pwm_clk = Some(source);
+        self
+    }
+
+    /// Select the clock of both timers and enable the timer gate, by default it is left as is
+    ///
+    /// The watchdog keeps its own source.
+    pub fn timer_clk_source(mut self, source: TimerClkSource) -> Self {
+        self.timer_clk = Some(source);
+        self
+    }
+
+    /// Switch the system clock and configure the peripheral clocks.
+    ///
+    /// See [`reconfigure_sysclk`] for how the switch is done. The bus clock runs at half the
+    /// system clock.
+    ///
+    /// The flash clock is divided from the 144 MHz DLL tap, or from the crystal without the
+    /// DLL, down to `flash_max_clk`. Later switches with `reconfigure_sysclk` keep it.
+    ///
+    /// The configuration is checked before any clock changes, an invalid one leaves the
+    /// clocks as they were.
+    pub fn try_freeze(self, _clk_cfg: &mut ClkCfg) -> Result<Clocks, Error> {
+        if self.xtal.0 != XTAL_FREQ {
+            return Err(Error::UnreachableFrequency {
+                clock: Clock::Xtal,
+                requested: self.xtal,
+                supported: XTAL_FREQS,
+            });
+        }
+        let sysclk = SysclkFreq::from_hertz(self.sysclk).ok_or(Error::UnreachableFrequency {
+            clock: Clock::Sysclk,
+            requested: self.sysclk,
+            supported: SYSCLK_FREQS,
+        })?;
+        let pll_enabled = sysclk != SysclkFreq::Xtal32Mhz;
+        if let Some((source, div)) = self.uart_clk {
+            if !(1..=8).contains(&div) {
+                return Err(Error::DividerOutOfRange {
+                    clock: Clock::Uart,
+                    requested: div as u32,
+                    min: 1,
+                    max: 8,
+                });
+            }
+            if source == UartClkSource::Pll96Mhz && !pll_enabled {
+                return Err(Error::DllUnavailable { clock: Clock::Uart });
+            }
+        }
+
+        if let Some(div) = self.i2c_clk_div {
+            if !(1..=256).contains(&div) {
+                return Err(Error::DividerOutOfRange {
+                    clock: Clock::I2c,
+                    requested: div as u32,
+                    min: 1,
+                    max: 256,
+                });
+            }
+            let i2c_clk = sysclk as u32 / (BSP_BCLK_DIV as u32 + 1) / div as u32;
+            if i2c_clk < I2C_MIN_FREQ {
+                return Err(Error::FrequencyTooLow {
+                    clock: Clock::I2c,
+                    result: Hertz(i2c_clk),
+                    min: Hertz(I2C_MIN_FREQ),
+                });
+            }
+            if i2c_clk > I2C_MAX_FREQ {
+                return Err(Error::FrequencyTooHigh {
+                    clock: Clock::I2c,
+                    result: Hertz(i2c_clk),
+                    max: Hertz(I2C_MAX_FREQ),
+                });
+            }
+        }
+
+        let adc_source = if pll_enabled { 96_000_000 } else { XTAL_FREQ };
+        if let Some(div) = self.adc_clk_div {
+            if !(1..=64).contains(&div) {
+                return Err(Error::DividerOutOfRange {
+                    clock: Clock::Adc,
+                    requested: div as u32,
+                    min: 1,
+                    max: 64,
+                });
+            }
+            if adc_source / div as u32 > ADC_MAX_FREQ {
+                return Err(Error::FrequencyTooHigh {
+                    clock: Clock::Adc,
+                    result: Hertz(adc_source / div as u32),
+                    max: Hertz(ADC_MAX_FREQ),
+                });
+            }
+        }
+
+        // the flash runs from the 144 MHz DLL tap, or the crystal when the DLL is not used
+        let (flash_source, flash_source_freq) = if pll_enabled {
+            (GLB_SFLASH_CLK_Type::GLB_SFLASH_CLK_144M, 144_000_000)
+        } else {
+            (GLB_SFLASH_CLK_Type::GLB_SFLASH_CLK_XCLK, XTAL_FREQ)
+        };
+        let flash_div = match self.flash_max_clk.0 {
+            0 => u32::MAX,
+            max => flash_source_freq.div_ceil(max),
+        };
+        if flash_div > 8 {
+            return Err(Error::XipConstraintViolated {
+                flash_max_clk: self.flash_max_clk,
+                min: Hertz(flash_source_freq / 8),
+            });
+        }
+
+        if let Some(div) = self.mtimer_div {
+            if !(1..=MTIMER_MAX_DIV).contains(&div) {
+                return Err(Error::DividerOutOfRange {
+                    clock: Clock::Mtimer,
+                    requested: div,
+                    min: 1,
+                    max: MTIMER_MAX_DIV,
+                });
+            }
+        }
+
+        if self.usb && !pll_enabled {
+            return Err(Error::DllUnavailable { clock: Clock::Usb });
+        }
+
+        // the audio PLL runs from the crystal, wait for it before any clock changes
+        if self.audio_pll.is_some() && !wait_xtal_ready() {
+            return Err(Error::CrystalTimeout {
+                clock: Clock::AudioPll,
+                timeout_ms: XTAL_READY_TIMEOUT_MS,
+            });
+        }
+
+        // the flash clock has to be within its rating before and after the switch, so it moves
+        // to the crystal first when the DLL is not used anymore
+        if !pll_enabled {
+            set_flash_clk(flash_source, flash_div as u8);
+        }
+        switch_sysclk(sysclk);
+        if pll_enabled {
+            set_flash_clk(flash_source, flash_div as u8);
+        }
+        match self.mtimer_div {
+            Some(div) => set_mtimer_div(div),
+            None => set_mtimer_freq(MTIMER_FREQ),
+        }
+
+        let spi_clk_div = 4;
 
         unsafe { glb::ptr() }.clk_cfg3.modify(|_, w| unsafe {
             w.spi_clk_div()
@@ -122,72 +1100,897 @@ impl ClockConfig {
                 .set_bit()
         });
 
-        let spi_clk = system_clock_get(system_clock_type::SYSTEM_CLOCK_BCLK) / spi_clk_div as u32;
+        match self.uart_clk {
+            Some((source, div)) => {
+                unsafe { system::hbn::ptr() }
+                    .hbn_glb
+                    .modify(|_, w| w.hbn_uart_clk_sel().bit(source == UartClkSource::Pll96Mhz));
+                unsafe { glb::ptr() }.clk_cfg2.modify(|_, w| unsafe {
+                    w.uart_clk_div().bits(div - 1).uart_clk_en().set_bit()
+                });
+            }
+            None => {
+                unsafe { glb::ptr() }.clk_cfg2.modify(|_, w| w.uart_clk_en().clear_bit());
+            }
+        }
 
-        Clocks {
-            sysclk: Hertz(sysclk as u32),
-            uart_clk: Hertz(UART_PLL_FREQ),
-            spi_clk: Hertz(spi_clk),
+        let sysclk = system_clock_get(system_clock_type::SYSTEM_CLOCK_FCLK);
+        match self.f32k {
+            Some(F32kSource::Xtal32k) if start_xtal32k(sysclk) => {}
+            Some(F32kSource::Xclk) => {
+                start_dig_32k();
+                select_f32k(F32kSource::Xclk);
+            }
+            // the RC oscillator was asked for, or the crystal did not start
+            Some(_) => {
+                select_f32k(F32kSource::Rc32k);
+                calibrate_rc32k(sysclk);
+            }
+            None => {}
+        }
+
+        if let Some(div) = self.i2c_clk_div {
+            enable_gate(Gate::I2c);
+            let glb = unsafe { glb::ptr() };
+            glb.clk_cfg3.modify(|_, w| unsafe {
+                w.i2c_clk_div().bits((div - 1) as u8).i2c_clk_en().set_bit()
+            });
+        }
+
+        if let Some(div) = self.adc_clk_div {
+            enable_gate(Gate::Gpip);
+            let glb = unsafe { glb::ptr() };
+            glb.gpadc_32m_src_ctrl.modify(|_, w| unsafe {
+                w.gpadc_32m_clk_sel()
+                    .bit(!pll_enabled) // crystal instead of the 96 MHz tap
+                    .gpadc_32m_clk_div()
+                    .bits(div - 1)
+                    .gpadc_32m_div_en()
+                    .set_bit()
+            });
+            // the analog side of the GPADC in the always-on domain
+            let aon = unsafe { &*bl702_pac::AON::ptr() };
+            aon.gpadc_reg_cmd.modify(|_, w| w.gpadc_global_en().set_bit());
+        }
+
+        if let Some(freq) = self.audio_pll {
+            start_audio_pll(freq, sysclk);
+        }
+
+        if self.usb {
+            enable_gate(Gate::Usb);
+            let glb = unsafe { glb::ptr() };
+            glb.clk_cfg1
+                .modify(|_, w| w.dll_48m_div_en().set_bit().usb_clk_en().set_bit());
+        }
+
+        if let Some(source) = self.pwm_clk {
+            enable_gate(Gate::Pwm);
+            let pwm = unsafe { &*bl702_pac::PWM::ptr() };
+            let sel = source as u8;
+            pwm.pwm0_config.modify(|_, w| unsafe { w.reg_clk_sel().bits(sel) });
+            pwm.pwm1_config.modify(|_, w| unsafe { w.reg_clk_sel().bits(sel) });
+            pwm.pwm2_config.modify(|_, w| unsafe { w.reg_clk_sel().bits(sel) });
+            pwm.pwm3_config.modify(|_, w| unsafe { w.reg_clk_sel().bits(sel) });
+            pwm.pwm4_config.modify(|_, w| unsafe { w.reg_clk_sel().bits(sel) });
+        }
+
+        if let Some(source) = self.timer_clk {
+            enable_gate(Gate::Timer);
+            let sel = source as u8;
+            crate::timer::modify_shared(|timer| {
+                timer
+                    .tccr
+                    .modify(|_, w| unsafe { w.cs_1().bits(sel).cs_2().bits(sel) })
+            });
+        }
+
+        // read back what the hardware was programmed with, and check it against the
+        // configuration in debug builds
+        let clocks = Clocks::from_registers();
+        debug_assert_eq!(
+            clocks.spi_clk.0,
+            system_clock_get(system_clock_type::SYSTEM_CLOCK_ROOT_CLOCK)
+                / (GLB_Get_HCLK_Div() as u32 + 1)
+                / (BSP_BCLK_DIV as u32 + 1)
+                / spi_clk_div as u32,
+            "SPI clock does not match the programmed dividers"
+        );
+        if let Some((source, div)) = self.uart_clk {
+            let source = match source {
+                UartClkSource::Pll96Mhz => UART_PLL_FREQ,
+                UartClkSource::Fclk => clocks.sysclk.0,
+            };
+            debug_assert_eq!(
+                clocks.uart_clk.0,
+                source / div as u32,
+                "UART clock does not match the programmed divider"
+            );
+        }
+
+        Ok(clocks)
+    }
+
+    /// Same as [`ClockConfig::try_freeze`], panicking with the error message on an invalid
+    /// configuration
+    pub fn freeze(self, clk_cfg: &mut ClkCfg) -> Clocks {
+        match self.try_freeze(clk_cfg) {
+            Ok(clocks) => clocks,
+            Err(e) => panic!("invalid clock configuration: {}", e),
         }
     }
 }
 
+/// A reference clock driven on a pad
+///
+/// Exports the camera reference clock, [`ClockOutSelect::CamRef`], the only path from the
+/// clock tree to the pads besides the audio clocks, with the divider and source checked against
+/// `Clocks`. The 32 kHz clock cannot be exported on the BL702.
+///
+/// Even pins drive chip clock out 0 and odd pins chip clock out 1, and both share one divided
+/// source, so a second `ClockOut` changes the frequency of the first. Use the audio clocks of
+/// `into_clock_output` for anything else.
+///
+/// ```rust
+///   let clk = ClockOut::new(parts.pin8, CamRefSource::Xclk, 2, &clocks).unwrap();
+///   assert_eq!(clk.frequency(), 16_000_000.Hz());
+///   let pin8 = clk.release();
+/// ```
+pub struct ClockOut<PIN: ClockOutPin> {
+    pin: PIN::ClockOut,
+    frequency: Hertz,
+}
+
+impl<PIN: ClockOutPin> ClockOut<PIN> {
+    /// Drive `source` divided by `div` (1 to 4) on `pin`
+    ///
+    /// The source selection and the pad function change in one critical section, so the pad
+    /// never outputs a clock that was not asked for.
+    pub fn new(
+        pin: PIN,
+        source: CamRefSource,
+        div: u8,
+        clocks: &Clocks,
+    ) -> Result<Self, Error> {
+        if !(1..=4).contains(&div) {
+            return Err(Error::DividerOutOfRange {
+                clock: Clock::ClockOut,
+                requested: div as u32,
+                min: 1,
+                max: 4,
+            });
+        }
+        if source == CamRefSource::Dll96M && clocks.sysclk().0 == XTAL_FREQ {
+            return Err(Error::DllUnavailable {
+                clock: Clock::ClockOut,
+            });
+        }
+
+        let select = ClockOutSelect::CamRef {
+            source,
+            divider: div,
+        };
+        let pin = riscv::interrupt::free(|| pin.into_clock_out_pad(select));
+
+        Ok(ClockOut {
+            pin,
+            frequency: Hertz(source.frequency(clocks).0 / div as u32),
+        })
+    }
+
+    /// Frequency on the pad
+    pub fn frequency(&self) -> Hertz {
+        self.frequency
+    }
+
+    /// Stop the clock output and return the pin
+    ///
+    /// The camera reference clock keeps running, it may still feed the other clock output.
+    pub fn release(self) -> PIN::ClockOut {
+        // 2 -> no clock, in the same critical section as the selection in `new`
+        riscv::interrupt::free(|| pad::set_chip_clk_out(PIN::PIN, 2));
+        self.pin
+    }
+}
+
+/// XCLK divider of the DIG 32 kHz clock, 32 kHz from the 32 MHz crystal
+const DIG_32K_DIV: u32 = 1000;
+/// XCLK divider of the DIG 512 kHz clock, the integer divider closest to 512 kHz
+const DIG_512K_DIV: u32 = 62;
+
+/// Start the DIG 32 kHz clock, XCLK divided by 1000, and return its frequency
+///
+/// The DIG clocks time the key scan, IR and QDEC sampling and the platform wakeup. They cannot
+/// be driven on a pad. The pad clock function only carries the two chip clock outputs, see
+/// [`ClockOut`], and their selection in `GLB.clk_cfg3` (`chip_clk_out_0_sel` and
+/// `chip_clk_out_1_sel`, reference manual GLB chapter) has four values: camera reference, I2S
+/// reference, none and audio 128fs, the `GLB_CHIP_CLK_OUT_Type` of the vendor SDK
+/// `bl702_glb.h`. `GLB.dig32k_wakeup_ctrl` has no output enable either. The camera reference
+/// divides XCLK by 4 at most, so an external 32 kHz consumer needs its own crystal.
+///
+/// The DIG clocks are generated in the GLB from XCLK. They keep running while the core waits
+/// for an interrupt, and stop with XCLK or the GLB power domain in PDS and HBN. The
+/// `dig_clock` example checks the configuration across a `wfi` sleep on a board.
+pub fn enable_dig_32k(_clk_cfg: &mut ClkCfg, clocks: &Clocks) -> Hertz {
+    start_dig_32k();
+    Hertz(clocks.xclk().0 / DIG_32K_DIV)
+}
+
+fn start_dig_32k() {
+    set_dig_clk_source();
+    unsafe { glb::ptr() }.dig32k_wakeup_ctrl.modify(|_, w| unsafe {
+        w.dig_32k_div()
+            .bits((DIG_32K_DIV - 1) as u16)
+            .dig_32k_comp()
+            .clear_bit()
+            .dig_32k_en()
+            .set_bit()
+    });
+}
+
+/// Start the DIG 512 kHz clock, XCLK divided by 62, and return its frequency
+///
+/// See [`enable_dig_32k`], the same applies.
+pub fn enable_dig_512k(_clk_cfg: &mut ClkCfg, clocks: &Clocks) -> Hertz {
+    set_dig_clk_source();
+    unsafe { glb::ptr() }.dig32k_wakeup_ctrl.modify(|_, w| unsafe {
+        w.dig_512k_div()
+            .bits((DIG_512K_DIV - 1) as u8)
+            .dig_512k_comp()
+            .clear_bit()
+            .dig_512k_en()
+            .set_bit()
+    });
+    Hertz(clocks.xclk().0 / DIG_512K_DIV)
+}
+
+/// Stop the DIG 32 kHz clock
+pub fn disable_dig_32k(_clk_cfg: &mut ClkCfg) {
+    unsafe { glb::ptr() }
+        .dig32k_wakeup_ctrl
+        .modify(|_, w| w.dig_32k_en().clear_bit());
+}
+
+/// Stop the DIG 512 kHz clock
+pub fn disable_dig_512k(_clk_cfg: &mut ClkCfg) {
+    unsafe { glb::ptr() }
+        .dig32k_wakeup_ctrl
+        .modify(|_, w| w.dig_512k_en().clear_bit());
+}
+
+/// Run the DIG clock generator from XCLK
+fn set_dig_clk_source() {
+    // 1 -> XCLK
+    unsafe { glb::ptr() }
+        .dig32k_wakeup_ctrl
+        .modify(|_, w| unsafe { w.dig_clk_src_sel().bits(1) });
+}
+
+/// Peripheral clock gates in `cgen_cfg1`, the value is the bit of the gate
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+#[repr(u8)]
+pub enum Gate {
+    /// GPADC and GPDAC
+    Gpip = 2,
+    /// DMA controller
+    Dma = 12,
+    /// Ethernet MAC
+    Emac = 13,
+    /// UART0
+    Uart0 = 16,
+    /// UART1
+    Uart1 = 17,
+    /// SPI controller
+    Spi = 18,
+    /// I2C controller
+    I2c = 19,
+    /// PWM channels
+    Pwm = 20,
+    /// Timer counters and watchdog
+    Timer = 21,
+    /// IR transmitter and receiver
+    Ir = 22,
+    /// Checksum engine
+    Checksum = 23,
+    /// Quadrature decoders
+    Qdec = 24,
+    /// Key scan matrix
+    KeyScan = 25,
+    /// I2S interface
+    I2s = 26,
+    /// USB device controller
+    Usb = 28,
+    /// Camera interface
+    Cam = 29,
+    /// MJPEG encoder
+    Mjpeg = 30,
+}
+
+/// Peripheral clock gating
+///
+/// The peripheral constructors enable their own gate, so this is only needed to save power on
+/// unused peripherals, or to stop one temporarily. `Clocks` stays a plain `Copy` record of the
+/// frequencies, the gates are controlled through the `ClkCfg` token instead:
+///
+/// ```rust
+///   parts.clk_cfg.disable(Gate::Uart1);
+/// ```
+///
+/// Disabling the gate of a peripheral that is still in use stops it, register accesses to it
+/// may then stall or fault.
+impl ClkCfg {
+    /// Enable the clock of `gate`
+    pub fn enable(&mut self, gate: Gate) {
+        enable_gate(gate);
+    }
+
+    /// Disable the clock of `gate`
+    pub fn disable(&mut self, gate: Gate) {
+        set_gate(gate, false);
+    }
+
+    /// Returns true if the clock of `gate` is enabled
+    pub fn is_enabled(&self, gate: Gate) -> bool {
+        unsafe { glb::ptr() }.cgen_cfg1.read().bits() & (1 << gate as u8) != 0
+    }
+}
+
+/// Enable the clock of `gate`, for the peripheral constructors
+pub(crate) fn enable_gate(gate: Gate) {
+    set_gate(gate, true);
+}
+
+/// Enable or disable the clock of `gate`, with interrupts disabled so a constructor running in
+/// an interrupt handler can't change another gate of `cgen_cfg1` in between
+fn set_gate(gate: Gate, enable: bool) {
+    let mask = 1 << gate as u8;
+    riscv::interrupt::free(|| {
+        unsafe { glb::ptr() }.cgen_cfg1.modify(|r, w| unsafe {
+            w.bits(if enable { r.bits() | mask } else { r.bits() & !mask })
+        })
+    });
+}
+
 impl Default for ClockConfig {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// Incremented by every clock switch, `Clocks` values from before are stale
+static GENERATION: AtomicU32 = AtomicU32::new(0);
+
+/// Move the root clock to `sysclk` and restore the bus dividers
+///
+/// The ROM driver moves the root clock to RC32M while the DLL is powered up and settles, then
+/// switches to the selected tap, so the core never runs from an unstable clock. It runs from ROM
+/// with interrupts disabled, so no handler fetches code from flash while the root clock moves.
+/// The flash clock has its own source selection and divider and is left alone, keeping XIP
+/// timing valid at every tap.
+fn switch_sysclk(sysclk: SysclkFreq) {
+    // keep the oscillator `board_clock_init_with` chose, the crystal may not be running
+    let xtal = unsafe { system::hbn::ptr() }.hbn_glb.read().hbn_root_clk_sel().bits() & 1 != 0;
+    let (dll_source, xclk) = if xtal {
+        (GLB_DLL_XTAL_Type::GLB_DLL_XTAL_32M, GLB_SYS_CLK_Type::GLB_SYS_CLK_XTAL)
+    } else {
+        (GLB_DLL_XTAL_Type::GLB_DLL_XTAL_RC32M, GLB_SYS_CLK_Type::GLB_SYS_CLK_RC32M)
+    };
+    let sys_clk_type = match sysclk {
+        SysclkFreq::Xtal32Mhz => xclk,
+        SysclkFreq::Dll57_6Mhz => GLB_SYS_CLK_Type::GLB_SYS_CLK_DLL57P6M,
+        SysclkFreq::Dll96Mhz => GLB_SYS_CLK_Type::GLB_SYS_CLK_DLL96M,
+        SysclkFreq::Pll144Mhz => GLB_SYS_CLK_Type::GLB_SYS_CLK_DLL144M,
+    };
+    riscv::interrupt::free(|| {
+        GLB_Set_System_CLK(dll_source, sys_clk_type);
+        GLB_Set_System_CLK_Div(BSP_FCLK_DIV, BSP_BCLK_DIV);
+    });
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Switch the system clock at runtime, e.g. down to the crystal while idle
+///
+/// Everything that was set up with the old `Clocks` still runs with dividers computed for the
+/// old frequency: rebuild `McycleDelay`s and reconstruct the SPI and UART drivers with the
+/// returned value. `Clocks::is_current` tells whether a stored value is stale, the drivers
+/// check it in debug builds.
+///
+/// ```rust
+///   let clocks = clock::reconfigure_sysclk(SysclkFreq::Xtal32Mhz, &mut parts.clk_cfg);
+///   let mut delay = McycleDelay::new(clocks.sysclk().0);
+/// ```
+///
+/// With a UART clock from the DLL (the default) the UART keeps its clock at every tap but
+/// `Xtal32Mhz`, where the DLL is not used.
+///
+/// The machine timer divider is retuned to keep `mtime` counting at the same rate, as far as the
+/// new bus clock divides down to it; compare `Clocks::mtimer_clk` to be sure.
+#[must_use = "drivers and delays must be rebuilt with the new clocks"]
+pub fn reconfigure_sysclk(new: SysclkFreq, _clk_cfg: &mut ClkCfg) -> Clocks {
+    let mtimer_clk = Clocks::from_registers().mtimer_clk;
+    switch_sysclk(new);
+    if mtimer_clk.0 != 0 {
+        set_mtimer_freq(mtimer_clk.0);
+    }
+    Clocks::from_registers()
+}
+
+/// Count `mtime` from the bus clock divided by `div`
+fn set_mtimer_div(div: u32) {
+    GLB_Set_MTimer_CLK(1, GLB_MTIMER_CLK_Type::GLB_MTIMER_CLK_BCLK, div - 1);
+}
+
+/// Count `mtime` as close to `freq` as the bus clock divides down to
+fn set_mtimer_freq(freq: u32) {
+    let bclk = system_clock_get(system_clock_type::SYSTEM_CLOCK_BCLK);
+    set_mtimer_div(((bclk + freq / 2) / freq).clamp(1, MTIMER_MAX_DIV));
+}
+
+/// Reference divider of the audio PLL, 16 MHz from the 32 MHz crystal
+const AUDIO_PLL_REFDIV: u8 = 2;
+/// Fractional bits of the audio PLL sigma-delta multiplier
+const AUDIO_PLL_SDM_FRAC_BITS: u32 = 18;
+
+/// Power up the audio PLL at `freq` and wait for the loop to settle
+fn start_audio_pll(freq: AudioPllFreq, sysclk: u32) {
+    let pds = unsafe { pds::ptr() };
+    let delay_us = |us: u64| McycleDelay::delay_cycles(sysclk as u64 * us / 1_000_000);
+
+    // VCO = reference * sdmin / 2^18, rounded to the nearest multiplier
+    let reference = (XTAL_FREQ / AUDIO_PLL_REFDIV as u32) as u64;
+    let vco = freq as u32 as u64 * freq.postdiv() as u64;
+    let sdmin = ((vco << AUDIO_PLL_SDM_FRAC_BITS) + reference / 2) / reference;
+
+    // power down before changing the dividers
+    pds.pu_rst_clkpll.modify(|_, w| w.pu_clkpll().clear_bit().pu_clkpll_sfreg().clear_bit());
+    pds.clkpll_top_ctrl.modify(|_, w| unsafe {
+        w.clkpll_xtal_rc32m_sel()
+            .clear_bit() // crystal
+            .clkpll_refdiv_ratio()
+            .bits(AUDIO_PLL_REFDIV)
+            .clkpll_postdiv()
+            .bits(freq.postdiv())
+    });
+    pds.clkpll_sdm
+        .modify(|_, w| unsafe { w.clkpll_sdmin().bits(sdmin as u32) });
+
+    pds.pu_rst_clkpll.modify(|_, w| w.pu_clkpll_sfreg().set_bit());
+    delay_us(5);
+    pds.pu_rst_clkpll.modify(|_, w| {
+        w.pu_clkpll()
+            .set_bit()
+            .clkpll_pu_cp()
+            .set_bit()
+            .clkpll_pu_pfd()
+            .set_bit()
+            .clkpll_pu_fbdv()
+            .set_bit()
+            .clkpll_pu_postdiv()
+            .set_bit()
+    });
+    delay_us(5);
+    // restart the sigma-delta modulator and feedback divider on the new settings
+    pds.pu_rst_clkpll.modify(|_, w| w.clkpll_sdm_reset().set_bit());
+    delay_us(1);
+    pds.pu_rst_clkpll.modify(|_, w| w.clkpll_reset_fbdv().set_bit());
+    delay_us(10);
+    pds.pu_rst_clkpll.modify(|_, w| w.clkpll_reset_fbdv().clear_bit());
+    delay_us(1);
+    pds.pu_rst_clkpll.modify(|_, w| w.clkpll_sdm_reset().clear_bit());
+    // no lock flag, wait out the settling time of the loop
+    delay_us(100);
+}
+
+/// Select the flash clock source and divider
+///
+/// The ROM driver does the switch, so no code is fetched from flash while its clock changes.
+fn set_flash_clk(source: GLB_SFLASH_CLK_Type, div: u8) {
+    riscv::interrupt::free(|| {
+        GLB_Set_SF_CLK(1, source, div - 1);
+    });
+}
+
+/// Set by `apply_rc32m_trim` when the efuse held a valid trim
+static RC32M_TRIMMED: AtomicBool = AtomicBool::new(false);
+
+/// Apply the factory trim of the RC32M oscillator from the efuse
+///
+/// Called by `system_init`. The trim code is only used if the efuse marks it as programmed
+/// and its parity bit matches; returns whether it was applied, see `Clocks::rc32m_trimmed`.
+pub fn apply_rc32m_trim() -> bool {
+    // efuse word holding the analog trims, as laid out by the vendor SDK
+    let efuse0 = unsafe { &*bl702_pac::EF_DATA_0::ptr() };
+    let fuse = efuse0.ef_key_slot_5_w2.read().ef_key_slot_5_w2().bits();
+    let code = ((fuse >> 4) & 0xff) as u8;
+    let parity = (fuse >> 12) & 1;
+    let programmed = (fuse >> 13) & 1 != 0;
+
+    let valid = programmed && parity == code.count_ones() & 1;
+    if valid {
+        let pds = unsafe { pds::ptr() };
+        pds.rc32m_ctrl0.modify(|_, w| unsafe {
+            w.rc32m_code_fr_ext().bits(code).rc32m_ext_code_en().set_bit()
+        });
+        // the oscillator needs about 2 us to settle on the new code
+        McycleDelay::delay_cycles(RC32M_FREQ as u64 / 500_000);
+    }
+    RC32M_TRIMMED.store(valid, Ordering::Relaxed);
+    valid
+}
+
+fn select_f32k(source: F32kSource) {
+    let sel = match source {
+        F32kSource::Rc32k => 0,
+        F32kSource::Xtal32k => 1,
+        F32kSource::Xclk => 3,
+    };
+    unsafe { system::hbn::ptr() }
+        .hbn_glb
+        .modify(|_, w| unsafe { w.hbn_f32k_sel().bits(sel) });
+}
+
+fn f32k_source() -> F32kSource {
+    match unsafe { system::hbn::ptr() }.hbn_glb.read().hbn_f32k_sel().bits() {
+        1 => F32kSource::Xtal32k,
+        3 => F32kSource::Xclk,
+        _ => F32kSource::Rc32k,
+    }
+}
+
+/// Returns the low 32 bits of the RTC counter, which counts the 32 kHz clock
+fn rtc_ticks() -> u32 {
+    let hbn = unsafe { system::hbn::ptr() };
+    hbn.rtc_time_h.modify(|_, w| w.rtc_time_latch().set_bit());
+    hbn.rtc_time_h.modify(|_, w| w.rtc_time_latch().clear_bit());
+    hbn.rtc_time_l.read().bits()
+}
+
+/// 32 kHz periods [`measure_sysclk_against_f32k`] counts core cycles over, about 31 ms
+pub const SYSCLK_MEASURE_PERIODS: u32 = 1024;
+
+/// Raw result of [`measure_sysclk`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SysclkMeasurement {
+    /// Core cycles counted
+    pub cycles: u64,
+    /// 32 kHz periods they were counted over
+    pub f32k_periods: u32,
+    /// Nominal frequency of the 32 kHz clock, see `clock::hbn::frequency`
+    pub f32k: Hertz,
+}
+
+impl SysclkMeasurement {
+    /// Core clock frequency this measurement works out to
+    pub fn frequency(&self) -> Hertz {
+        Hertz((self.cycles * self.f32k.0 as u64 / self.f32k_periods as u64) as u32)
+    }
+}
+
+/// Measure the actual core clock against the 32 kHz clock
+///
+/// Useful when running from RC32M, which can be several percent off its nominal frequency:
+///
+/// ```rust
+///   let sysclk = clock::measure_sysclk_against_f32k().unwrap_or(clocks.sysclk());
+///   let mut delay = McycleDelay::new(sysclk.0);
+/// ```
+///
+/// The result is only as accurate as the 32 kHz clock, so select `F32kSource::Xtal32k` first;
+/// RC32K is off by as much as RC32M and `F32kSource::Xclk` is derived from the clock under
+/// test. Returns `None` if the 32 kHz clock does not run.
+pub fn measure_sysclk_against_f32k() -> Option<Hertz> {
+    measure_sysclk(SYSCLK_MEASURE_PERIODS).map(|m| m.frequency())
+}
+
+/// Count core cycles over `periods` periods of the 32 kHz clock
+///
+/// Same as [`measure_sysclk_against_f32k`], but returns the raw counts, e.g. to log drift over
+/// temperature. Longer measurements are more precise: the start and end are each found to
+/// within a polling loop of a few dozen cycles.
+pub fn measure_sysclk(periods: u32) -> Option<SysclkMeasurement> {
+    let periods = periods.max(1);
+    let hbn = unsafe { system::hbn::ptr() };
+    let rtc_enabled = hbn.hbn_ctl.read().rtc_ctl().bits() & 1 != 0;
+    hbn.hbn_ctl
+        .modify(|r, w| unsafe { w.rtc_ctl().bits(r.rtc_ctl().bits() | 1) });
+
+    // give up after four times the nominal duration at the highest core clock
+    let f32k = hbn::frequency();
+    let timeout = SYSFREQ as u64 * 4 * (periods as u64 + 1) / f32k.0 as u64;
+    let wait_for = |ticks: u32, since: u64| loop {
+        let now = rtc_ticks();
+        if now.wrapping_sub(ticks) as i32 > 0 {
+            return Some(now);
+        }
+        if McycleDelay::cycles_since(since) > timeout {
+            return None;
+        }
+    };
+
+    let since = McycleDelay::get_cycle_count();
+    // start right at an edge of the 32 kHz clock
+    let result = wait_for(rtc_ticks(), since).and_then(|first| {
+        let start = McycleDelay::get_cycle_count();
+        let last = wait_for(first.wrapping_add(periods - 1), start)?;
+        Some(SysclkMeasurement {
+            cycles: McycleDelay::cycles_since(start),
+            f32k_periods: last.wrapping_sub(first),
+            f32k,
+        })
+    });
+
+    if !rtc_enabled {
+        hbn.hbn_ctl
+            .modify(|r, w| unsafe { w.rtc_ctl().bits(r.rtc_ctl().bits() & !1) });
+    }
+    result
+}
+
+/// Power the 32 kHz crystal and switch to it once the RTC counts at its frequency
+///
+/// The oscillator has no ready flag, so the RTC ticks are compared with the core clock in
+/// 10 ms windows. Returns false, with the crystal powered down again, if it did not run within
+/// `XTAL32K_STARTUP_MS`.
+fn start_xtal32k(sysclk: u32) -> bool {
+    const WINDOW_MS: u32 = 10;
+    const EXPECTED: u32 = XTAL32K_FREQ / (1000 / WINDOW_MS);
+    let hbn = unsafe { system::hbn::ptr() };
+    hbn.xtal32k
+        .modify(|_, w| w.pu_xtal32k().set_bit().pu_xtal32k_buf().set_bit());
+
+    let rtc_enabled = hbn.hbn_ctl.read().rtc_ctl().bits() & 1 != 0;
+    hbn.hbn_ctl
+        .modify(|r, w| unsafe { w.rtc_ctl().bits(r.rtc_ctl().bits() | 1) });
+    select_f32k(F32kSource::Xtal32k);
+
+    let window = sysclk as u64 * WINDOW_MS as u64 / 1000;
+    let mut running = false;
+    for _ in 0..XTAL32K_STARTUP_MS / WINDOW_MS {
+        let start = rtc_ticks();
+        McycleDelay::delay_cycles(window);
+        let ticks = rtc_ticks().wrapping_sub(start);
+        // a crystal that does not oscillate leaves the counter standing, allow 10 % for the
+        // core clock running off its nominal frequency
+        if ticks.abs_diff(EXPECTED) <= EXPECTED / 10 {
+            running = true;
+            break;
+        }
+    }
+
+    if !rtc_enabled {
+        hbn.hbn_ctl
+            .modify(|r, w| unsafe { w.rtc_ctl().bits(r.rtc_ctl().bits() & !1) });
+    }
+    if !running {
+        hbn.xtal32k
+            .modify(|_, w| w.pu_xtal32k().clear_bit().pu_xtal32k_buf().clear_bit());
+    }
+    running
+}
+
+/// Run the hardware calibration of the RC32K oscillator against XCLK
+///
+/// Gives up if the calibration did not finish within 10 ms, the oscillator then keeps its
+/// previous trim.
+fn calibrate_rc32k(sysclk: u32) {
+    let hbn = unsafe { system::hbn::ptr() };
+    // use the calibrated code instead of the external one
+    hbn.rc32k_ctrl0.modify(|_, w| {
+        w.rc32k_ext_code_en()
+            .clear_bit()
+            .rc32k_allow_cal()
+            .set_bit()
+            .rc32k_cal_en()
+            .clear_bit()
+    });
+    hbn.rc32k_ctrl0.modify(|_, w| w.rc32k_cal_en().set_bit());
+
+    let timeout = sysclk as u64 / 100;
+    let start = McycleDelay::get_cycle_count();
+    while !hbn.rc32k_ctrl0.read().rc32k_cal_done().bit() {
+        if McycleDelay::cycles_since(start) > timeout {
+            return;
+        }
+    }
+}
+
+/// What `board_clock_init_with` does when the crystal does not report ready
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XtalFailPolicy {
+    /// Run from RC32M instead, recorded as `ClockSource::Rc32mFallback` in `Clocks`
+    FallbackRc32m,
+    /// Return `Error::CrystalTimeout` and leave the clocks as the boot ROM set them up
+    Error,
+}
+
+/// Set when `board_clock_init_with` fell back to RC32M
+static XTAL_FALLBACK: AtomicBool = AtomicBool::new(false);
+
+/// Board clock setup for `board_clock_init_with`
+///
+/// The default is the standard board: a 32 MHz crystal, 144 MHz from the DLL, the 32 kHz
+/// crystal as the 32 kHz clock and the flash clock as the boot ROM left it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardClockConfig {
+    /// Crystal frequency, `None` for boards without a crystal, which then run from RC32M.
+    /// Only 32 MHz crystals are supported.
+    pub xtal: Option<Hertz>,
+    /// Run the system clock at 144 MHz from the DLL, otherwise directly from the crystal or RC32M
+    pub dll: bool,
+    /// Flash clock divider, 1 to 8, `None` keeps the divider set by the boot ROM.
+    /// A larger divider is always safe, a smaller one must keep the flash within its rating.
+    pub flash_clk_div: Option<u8>,
+    /// What to do if the crystal does not start, e.g. when it is cold or badly soldered
+    pub xtal_fail: XtalFailPolicy,
+    /// Power up the 32 kHz crystal and select it as the 32 kHz clock, without waiting for it to
+    /// start. Use `ClockConfig::f32k_source` for a checked start with a fallback to RC32K.
+    pub xtal32k: bool,
+}
+
+impl Default for BoardClockConfig {
+    fn default() -> Self {
+        BoardClockConfig {
+            xtal: Some(Hertz(XTAL_FREQ)),
+            dll: true,
+            flash_clk_div: None,
+            xtal_fail: XtalFailPolicy::FallbackRc32m,
+            xtal32k: true,
+        }
+    }
+}
+
+/// What `board_clock_init_with` configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardClockInfo {
+    /// The crystal reported ready; false if there is none or it did not start, the clocks then
+    /// run from RC32M
+    pub xtal_ready: bool,
+    /// Core clock after the switch
+    pub sysclk: Hertz,
+    /// RC32M trim code in use, loaded from the efuse by [`apply_rc32m_trim`] during
+    /// `system_init_with_info`, the reset value if the efuse holds no valid trim
+    pub rc32m_trim: u8,
+    /// Flash clock divider in use
+    pub flash_clk_div: u8,
+}
+
 /// This is late system init, called to reconfigure clocks as per users configuration
+///
+/// Same as `board_clock_init_with(BoardClockConfig::default())`.
 pub fn board_clock_init() {
-    system_clock_init();
-    peripheral_clock_init();
+    // the default configuration is always valid, a missing crystal falls back to RC32M
+    let _ = board_clock_init_with(BoardClockConfig::default());
 }
 
-/// Set up core clocks
-pub fn system_clock_init() {
-    // select root clock
-    GLB_Set_System_CLK(
-        GLB_DLL_XTAL_Type::GLB_DLL_XTAL_32M,
-        GLB_SYS_CLK_Type::GLB_SYS_CLK_DLL144M,
-    );
+/// Set up the core clocks for the board described by `config` and gate the unused peripherals
+///
+/// The crystal gets 10 ms, counted in RC32M cycles, to report ready before `config.xtal_fail`
+/// applies.
+pub fn board_clock_init_with(config: BoardClockConfig) -> Result<BoardClockInfo, Error> {
+    if let Some(xtal) = config.xtal {
+        if xtal.0 != XTAL_FREQ {
+            return Err(Error::UnreachableFrequency {
+                clock: Clock::Xtal,
+                requested: xtal,
+                supported: XTAL_FREQS,
+            });
+        }
+    }
+    if let Some(div) = config.flash_clk_div {
+        if !(1..=8).contains(&div) {
+            return Err(Error::DividerOutOfRange {
+                clock: Clock::Flash,
+                requested: div as u32,
+                min: 1,
+                max: 8,
+            });
+        }
+    }
 
-    // set fclk/hclk and bclk clock
-    GLB_Set_System_CLK_Div(BSP_FCLK_DIV, BSP_BCLK_DIV);
-    // Set MTimer the same frequency as SystemCoreClock
+    let xtal_ready = config.xtal.is_some() && wait_xtal_ready();
+    let fallback = config.xtal.is_some() && !xtal_ready;
+    if fallback && config.xtal_fail == XtalFailPolicy::Error {
+        return Err(Error::CrystalTimeout {
+            clock: Clock::Xtal,
+            timeout_ms: XTAL_READY_TIMEOUT_MS,
+        });
+    }
+    XTAL_FALLBACK.store(fallback, Ordering::Relaxed);
+    let (dll_source, sys_clk) = match (xtal_ready, config.dll) {
+        (true, true) => (GLB_DLL_XTAL_Type::GLB_DLL_XTAL_32M, GLB_SYS_CLK_Type::GLB_SYS_CLK_DLL144M),
+        (true, false) => (GLB_DLL_XTAL_Type::GLB_DLL_XTAL_32M, GLB_SYS_CLK_Type::GLB_SYS_CLK_XTAL),
+        (false, true) => (GLB_DLL_XTAL_Type::GLB_DLL_XTAL_RC32M, GLB_SYS_CLK_Type::GLB_SYS_CLK_DLL144M),
+        (false, false) => (GLB_DLL_XTAL_Type::GLB_DLL_XTAL_RC32M, GLB_SYS_CLK_Type::GLB_SYS_CLK_RC32M),
+    };
+    riscv::interrupt::free(|| {
+        GLB_Set_System_CLK(dll_source, sys_clk);
+        GLB_Set_System_CLK_Div(BSP_FCLK_DIV, BSP_BCLK_DIV);
+    });
+    GENERATION.fetch_add(1, Ordering::Relaxed);
     GLB_Set_MTimer_CLK(
         1,
         GLB_MTIMER_CLK_Type::GLB_MTIMER_CLK_BCLK,
-        mtimer_get_clk_src_div() as u8,
+        mtimer_get_clk_src_div(),
     );
+    if config.xtal32k {
+        HBN_Power_On_Xtal_32K();
+        HBN_32K_Sel(HBN_32K_CLK_Type::HBN_32K_XTAL);
+    }
+    HBN_Set_XCLK_CLK_Sel(if xtal_ready {
+        HBN_XCLK_CLK_Type::HBN_XCLK_CLK_XTAL
+    } else {
+        HBN_XCLK_CLK_Type::HBN_XCLK_CLK_RC32M
+    });
 
-    // TODO: set audio PLL
-    //  PDS_Set_Audio_PLL_Freq(BSP_AUDIO_PLL_CLOCK_SOURCE - ROOT_CLOCK_SOURCE_AUPLL_12288000_HZ);
-    HBN_Power_On_Xtal_32K();
-    HBN_32K_Sel(HBN_32K_CLK_Type::HBN_32K_XTAL);
+    let glb = unsafe { glb::ptr() };
+    if let Some(div) = config.flash_clk_div {
+        // keep interrupt handlers from fetching code while the flash clock changes
+        riscv::interrupt::free(|| {
+            glb.clk_cfg2
+                .modify(|_, w| unsafe { w.sf_clk_div().bits(div - 1) })
+        });
+    }
+
+    peripheral_clock_init();
+
+    Ok(BoardClockInfo {
+        xtal_ready,
+        sysclk: Hertz(system_clock_get(system_clock_type::SYSTEM_CLOCK_FCLK)),
+        rc32m_trim: unsafe { pds::ptr() }.rc32m_ctrl0.read().rc32m_code_fr_ext().bits(),
+        flash_clk_div: glb.clk_cfg2.read().sf_clk_div().bits() + 1,
+    })
+}
 
-    HBN_Set_XCLK_CLK_Sel(HBN_XCLK_CLK_Type::HBN_XCLK_CLK_XTAL);
+/// Longest wait for the crystal to report ready
+const XTAL_READY_TIMEOUT_MS: u32 = 10;
+
+/// Wait up to about `XTAL_READY_TIMEOUT_MS` of RC32M cycles for the crystal to report ready
+fn wait_xtal_ready() -> bool {
+    let aon = unsafe { &*bl702_pac::AON::ptr() };
+    // the core may still run from RC32M here, so the timeout is counted at 32 MHz
+    let timeout = RC32M_FREQ as u64 * XTAL_READY_TIMEOUT_MS as u64 / 1000;
+    let start = McycleDelay::get_cycle_count();
+    while !aon.tsen.read().xtal_rdy().bit() {
+        if McycleDelay::cycles_since(start) > timeout {
+            return false;
+        }
+    }
+    true
 }
 
 /// Disable all peripheral clocks, then re-enable any that we're using  
 /// TODO: move clock enable into peripheral drivers
 pub fn peripheral_clock_init() {
     peripheral_clock_gate_all();
-    unsafe {
-        glb::ptr().cgen_cfg1.modify(|_, w| {
-            w.uart0().set_bit();
-            w.uart1().set_bit();
-            w.spi().set_bit()
-        });
-    }
+    enable_gate(Gate::Uart0);
+    enable_gate(Gate::Uart1);
+    enable_gate(Gate::Spi);
+}
+
+/// What `system_init_with_info` found in the efuse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemInfo {
+    /// The flash is inside the package, its pads are routed to the SF pins
+    pub internal_flash: bool,
+    /// The PSRAM is inside the package
+    pub internal_psram: bool,
+    /// The RC32M factory trim was found and applied, see [`apply_rc32m_trim`]
+    pub rc32m_trimmed: bool,
 }
 
 /// This is early system init - called from preinit in the C SDK
+///
+/// Same as `system_init_with_info`, for callers that do not need the result.
 pub fn system_init() {
+    system_init_with_info();
+}
+
+/// This is early system init, returning the package configuration read from the efuse
+pub fn system_init_with_info() -> SystemInfo {
     unsafe { riscv::interrupt::disable() };
     let pds = unsafe { pds::ptr() };
     let glb = unsafe { glb::ptr() };
-    let hbn = unsafe { hbn::ptr() };
+    let hbn = unsafe { system::hbn::ptr() };
     let efuse0 = unsafe { &*bl702_pac::EF_DATA_0::ptr() };
     pds.pds_int.modify(|_r, w| {
         w.cr_pds_wake_int_mask().set_bit(); // mask pds wakeup
@@ -273,7 +2076,15 @@ pub fn system_init() {
         w
     });
 
+    let rc32m_trimmed = apply_rc32m_trim();
+
     unsafe { riscv::interrupt::enable() };
+
+    SystemInfo {
+        internal_flash: is_internal_flash,
+        internal_psram: is_internal_psram,
+        rc32m_trimmed,
+    }
 }
 
 pub fn system_frequency() -> u32 {
@@ -318,3 +2129,18 @@ fn system_clock_get(t: system_clock_type) -> u32 {
         system_clock_type::SYSTEM_CLOCK_AUPLL => 12_288_000,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dig_dividers_fit_their_fields() {
+        // dig_32k_div is 11 bits, dig_512k_div 7 bits, both hold the divider minus one
+        const { assert!(DIG_32K_DIV - 1 < 1 << 11) };
+        const { assert!(DIG_512K_DIV - 1 < 1 << 7) };
+        assert_eq!(32_000_000 / DIG_32K_DIV, 32_000);
+        // 516 kHz, within 1 % of 512 kHz
+        assert!((32_000_000 / DIG_512K_DIV).abs_diff(512_000) < 512_000 / 100);
+    }
+}
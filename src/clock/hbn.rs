@@ -0,0 +1,75 @@
+//! Clock of the HBN block
+//!
+//! The RTC and the HBN timers count the 32 kHz clock (`f32k`), which is the only clock left
+//! running in HBN. Tick math has to use the frequency of the source that is selected when the
+//! chip goes to sleep, so select it before entering HBN and read it back with [`frequency`].
+//!
+//! The source can change at any time, e.g. to the 32 kHz crystal once the firmware knows it is
+//! fitted:
+//!
+//! ```rust
+//!   match clock::hbn::select(F32kSource::Xtal32k, &mut parts.clk_cfg) {
+//!       Ok(new_clocks) => clocks = new_clocks,
+//!       // no crystal, stay on the RC oscillator
+//!       Err(_) => {}
+//!   }
+//!   let ticks_per_second = clock::hbn::frequency().0;
+//! ```
+//!
+//! [`F32kSource::Xclk`] follows the crystal of the core clocks, but is generated in the GLB
+//! and stops in HBN. Use `Rc32k` or `Xtal32k` for timekeeping across HBN.
+
+use super::{
+    calibrate_rc32k, f32k_source, select_f32k, start_dig_32k, start_xtal32k, system_clock_get,
+    system_clock_type, Clock, Clocks, Error, F32kSource, GENERATION, DIG_32K_DIV, RC32K_FREQ,
+    XTAL32K_FREQ, XTAL32K_STARTUP_MS, XTAL_FREQ,
+};
+use crate::gpio::ClkCfg;
+use core::sync::atomic::Ordering;
+use embedded_time::rate::Hertz;
+
+/// Switch the 32 kHz clock to `source` and return the updated clocks
+///
+/// `Xtal32k` powers the crystal and waits up to [`XTAL32K_STARTUP_MS`] for it to run, and
+/// fails with `Error::CrystalTimeout` with the previous source still selected if it does not.
+/// `Rc32k` is calibrated against XCLK. Earlier `Clocks` values become stale, see
+/// `Clocks::is_current`.
+pub fn select(source: F32kSource, _clk_cfg: &mut ClkCfg) -> Result<Clocks, Error> {
+    let sysclk = system_clock_get(system_clock_type::SYSTEM_CLOCK_FCLK);
+    let previous = f32k_source();
+    match source {
+        F32kSource::Xtal32k => {
+            if !start_xtal32k(sysclk) {
+                select_f32k(previous);
+                return Err(Error::CrystalTimeout {
+                    clock: Clock::F32k,
+                    timeout_ms: XTAL32K_STARTUP_MS,
+                });
+            }
+        }
+        F32kSource::Rc32k => {
+            select_f32k(F32kSource::Rc32k);
+            calibrate_rc32k(sysclk);
+        }
+        F32kSource::Xclk => {
+            start_dig_32k();
+            select_f32k(F32kSource::Xclk);
+        }
+    }
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+    Ok(Clocks::from_registers())
+}
+
+/// Currently selected source of the 32 kHz clock
+pub fn source() -> F32kSource {
+    f32k_source()
+}
+
+/// Nominal frequency of the currently selected 32 kHz clock, the rate the RTC counts at
+pub fn frequency() -> Hertz {
+    Hertz(match f32k_source() {
+        F32kSource::Rc32k => RC32K_FREQ,
+        F32kSource::Xtal32k => XTAL32K_FREQ,
+        F32kSource::Xclk => XTAL_FREQ / DIG_32K_DIV,
+    })
+}
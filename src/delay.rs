@@ -1,4 +1,33 @@
 //! Delays
+//!
+//! [`McycleDelay`] busy-waits on the cycle counter and works before anything else is set up.
+//! [`SleepDelay`] waits in `wfi` for the machine timer instead, which saves power on long
+//! delays. With the `async` feature, [`MtimeDelay`] sleeps on the machine timer compare interrupt
+//! instead, so an executor can run other tasks or `wfi` in the meantime.
+//!
+//! For bit-banging at sub-microsecond timing, [`cycles`] spins inline for an exact number of
+//! core cycles, with the conversion done at compile time by [`ns_to_cycles`]:
+//!
+//! ```rust
+//!   const T0H: u32 = delay::ns_to_cycles(350, 144_000_000);
+//!   riscv::interrupt::free(|| {
+//!       data.set_high().unwrap();
+//!       delay::cycles(T0H);
+//!       data.set_low().unwrap();
+//!   });
+//! ```
+//!
+//! [`Stopwatch`] uses the same cycle counter to profile code:
+//!
+//! ```rust
+//!   let (result, cycles) = Stopwatch::measure(|| spi.write(&FRAME));
+//!   let mut sw = Stopwatch::start();
+//!   prepare();
+//!   let prepare_cycles = sw.lap();
+//!   send();
+//!   let send_cycles = sw.lap();
+//!   let total_ns = sw.elapsed_ns(&clocks);
+//! ```
 
 use embedded_hal::delay::DelayNs;
 
@@ -22,6 +51,11 @@ impl McycleDelay {
         }
     }
 
+    /// Core clock frequency this delay was constructed with
+    pub(crate) fn core_frequency(&self) -> u32 {
+        self.core_frequency
+    }
+
     /// Retrieves the cycle count for the current HART
     #[inline]
     pub fn get_cycle_count() -> u64 {
@@ -43,20 +77,447 @@ impl McycleDelay {
     }
 }
 
+/// Cycles a back-to-back pair of `mcycle` reads counts, `u32::MAX` until measured
+static STOPWATCH_OVERHEAD: core::sync::atomic::AtomicU32 =
+    core::sync::atomic::AtomicU32::new(u32::MAX);
+
+/// Cycles a measurement of nothing reports, taken off every [`Stopwatch`] reading
+///
+/// Measured on first use as the smallest of a few empty measurements, so a cache miss or an
+/// interrupt during calibration does not inflate it.
+fn stopwatch_overhead() -> u64 {
+    use core::sync::atomic::Ordering;
+
+    let overhead = STOPWATCH_OVERHEAD.load(Ordering::Relaxed);
+    if overhead != u32::MAX {
+        return overhead as u64;
+    }
+    let overhead = (0..8)
+        .map(|_| {
+            let start = McycleDelay::get_cycle_count();
+            McycleDelay::cycles_since(start)
+        })
+        .min()
+        .unwrap_or(0);
+    STOPWATCH_OVERHEAD.store(overhead as u32, Ordering::Relaxed);
+    overhead
+}
+
+/// Cycle counting stopwatch for profiling
+///
+/// Readings have the cost of reading the cycle counter itself taken off, so a short section
+/// is not over-reported; an empty section reads as 0 cycles. Interrupts taken during a
+/// measured section are counted as part of it.
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    start: u64,
+    lap: u64,
+}
+
+impl Stopwatch {
+    /// Start measuring from now
+    pub fn start() -> Self {
+        stopwatch_overhead();
+        let start = McycleDelay::get_cycle_count();
+        Stopwatch { start, lap: start }
+    }
+
+    /// Cycles since the previous lap, or since the start for the first one
+    pub fn lap(&mut self) -> u64 {
+        let now = McycleDelay::get_cycle_count();
+        let cycles = now.wrapping_sub(self.lap);
+        self.lap = now;
+        cycles.saturating_sub(stopwatch_overhead())
+    }
+
+    /// Cycles since the start
+    pub fn elapsed_cycles(&self) -> u64 {
+        McycleDelay::cycles_since(self.start).saturating_sub(stopwatch_overhead())
+    }
+
+    /// Nanoseconds since the start, at the core clock in `clocks`
+    pub fn elapsed_ns(&self, clocks: &crate::clock::Clocks) -> u64 {
+        (self.elapsed_cycles() as u128 * 1_000_000_000 / clocks.sysclk().0 as u128) as u64
+    }
+
+    /// Run `f` and return its result with the number of cycles it took
+    pub fn measure<T>(f: impl FnOnce() -> T) -> (T, u64) {
+        let overhead = stopwatch_overhead();
+        let start = McycleDelay::get_cycle_count();
+        let result = f();
+        let cycles = McycleDelay::cycles_since(start);
+        (result, cycles.saturating_sub(overhead))
+    }
+}
+
+/// Cycles spent entering and leaving a delay, on top of the busy-wait loop itself
+///
+/// A conservative count of the call, the cycle conversion and the first `mcycle` read on the
+/// E24 core in a release build. It is taken off the computed cycle count; keeping it on the low
+/// side means the time actually spent never falls short of the request.
+const DELAY_OVERHEAD_CYCLES: u64 = 10;
+
+/// Cycles at `freq` Hz covering at least `value` units of `1 / per_second` seconds
+///
+/// Rounds up, so a short delay never gets fewer cycles than the timing it was asked for.
+/// Both factors are `u32`, so the product always fits in a `u64` and the whole input range is
+/// honored: `u32::MAX` ms at 144 MHz is about 6.2e14 cycles.
+#[inline]
+const fn to_cycles(value: u32, freq: u32, per_second: u64) -> u64 {
+    (value as u64 * freq as u64).div_ceil(per_second)
+}
+
+/// Cycles left to busy-wait for `value` units of `1 / per_second` seconds at `freq` Hz, once
+/// the fixed overhead is taken off
+#[inline]
+const fn loop_cycles(value: u32, freq: u32, per_second: u64) -> u64 {
+    to_cycles(value, freq, per_second).saturating_sub(DELAY_OVERHEAD_CYCLES)
+}
+
+/// Busy-wait for `value` units of `1 / per_second` seconds at `freq` Hz, less the fixed overhead
+#[inline]
+fn delay(value: u32, freq: u32, per_second: u64) {
+    McycleDelay::delay_cycles(loop_cycles(value, freq, per_second));
+}
+
 impl DelayNs for McycleDelay {
 
     #[inline]
     fn delay_ns(&mut self, ns: u32) {
-        McycleDelay::delay_cycles(((ns as u64)  * (self.core_frequency as u64)) / 1_000_000_0000u64);
+        delay(ns, self.core_frequency, 1_000_000_000);
     }
 
     #[inline]
     fn delay_us(&mut self, us: u32) {
-        McycleDelay::delay_cycles(((us as u64)  * (self.core_frequency as u64)) / 1_000_000u64);
+        delay(us, self.core_frequency, 1_000_000);
     }
 
     #[inline]
     fn delay_ms(&mut self, ms: u32) {
-        McycleDelay::delay_cycles(((ms as u64) * (self.core_frequency as u64)) / 1000u64);
+        delay(ms, self.core_frequency, 1_000);
+    }
+}
+
+/// Cycles [`cycles`] spends besides its loop: the two counter reads and the subtraction
+///
+/// Taken off the requested count, so the whole call comes out at the requested length.
+const CYCLES_OVERHEAD: u32 = 4;
+
+/// Spin for `n` core cycles, including the cost of the call
+///
+/// Always inlined, so there is no call overhead to account for beyond [`CYCLES_OVERHEAD`].
+/// One pass of the loop takes about 4 cycles, so the spin ends up to one pass late: about 30 ns
+/// at 144 MHz and 125 ns at 32 MHz. These figures follow from the instruction count, no
+/// measured accuracy is recorded yet: `examples/delay_accuracy.rs` measures the 350 ns and
+/// 700 ns WS2812 edges with a [`Stopwatch`] at both frequencies and prints the requested and
+/// taken cycles. Code running from flash can be stretched further by cache misses, so
+/// run timing critical sequences from RAM and with interrupts disabled.
+#[inline(always)]
+pub fn cycles(n: u32) {
+    let start = riscv::register::mcycle::read() as u32;
+    let n = n.saturating_sub(CYCLES_OVERHEAD);
+    while (riscv::register::mcycle::read() as u32).wrapping_sub(start) < n {}
+}
+
+/// Core cycles covering at least `ns` nanoseconds at `cpu_hz`, for [`cycles`]
+///
+/// A `const fn`, so the conversion costs nothing when both are constants. Saturates at
+/// `u32::MAX`, which only a `cpu_hz` above 1 GHz can reach.
+pub const fn ns_to_cycles(ns: u32, cpu_hz: u32) -> u32 {
+    let cycles = to_cycles(ns, cpu_hz, 1_000_000_000);
+    if cycles > u32::MAX as u64 {
+        u32::MAX
+    } else {
+        cycles as u32
+    }
+}
+
+/// Delays shorter than this many µs spin in [`SleepDelay`], as the interrupt latency of the
+/// wake-up would be a large part of them
+const SLEEP_THRESHOLD_US: u32 = 50;
+
+/// Blocking delay that sleeps in `wfi` until the machine timer expires
+///
+/// `wfi` stops the core clock until the next interrupt, whereas [`McycleDelay`] keeps the core
+/// executing for the whole delay. No current saving is claimed: it depends on what else stays
+/// clocked and has not been measured, at 32 MHz or otherwise.
+/// Delays below 50 µs spin on [`McycleDelay`] instead.
+///
+/// The delay has its own alarm of the `mtimecmp` queue, see
+/// [`MachineTimer`](crate::timer::MachineTimer). The `MTIP` interrupt must be enabled in the
+/// CLIC, or `wfi` doesn't return, and routed to
+/// [`on_machine_timer`](crate::timer::on_machine_timer) if interrupts are enabled:
+///
+/// ```rust
+///   #[export_name = "MachineTimer"]
+///   fn machine_timer() {
+///       hal::timer::on_machine_timer();
+///   }
+///
+///   unsafe { interrupt::enable(Interrupt::MTIP) };
+///   let mut delay = SleepDelay::new(&clocks);
+///   delay.delay_ms(1_000);
+/// ```
+///
+/// Other interrupts are fine, every wake-up before the deadline just sleeps again.
+#[derive(Copy, Clone)]
+pub struct SleepDelay {
+    spin: McycleDelay,
+    mtimer_clk: u32,
+}
+
+impl SleepDelay {
+    /// Constructs the delay from the core and `mtime` frequencies in `clocks`
+    pub fn new(clocks: &crate::clock::Clocks) -> Self {
+        SleepDelay {
+            spin: McycleDelay::new(clocks.sysclk().0),
+            mtimer_clk: clocks.mtimer_clk().0,
+        }
+    }
+
+    /// Sleep for `value` units of `1 / per_second` seconds, rounded up to whole `mtime` ticks
+    fn sleep(&mut self, value: u32, per_second: u64) {
+        use riscv::register::mstatus;
+
+        let deadline = crate::timer::mtime() + to_cycles(value, self.mtimer_clk, per_second);
+        let interrupts = mstatus::read().mie();
+        loop {
+            // with MIE clear the alarm can't fire and be disarmed between the check and `wfi`,
+            // `wfi` still returns once it is pending
+            unsafe { mstatus::clear_mie() };
+            if crate::timer::mtime() >= deadline {
+                break;
+            }
+            // rearmed on every pass, the handler disarms it when it fires
+            crate::timer::set_alarm(crate::timer::SLEEP_ALARM, deadline);
+            unsafe { riscv::asm::wfi() };
+            if interrupts {
+                // let the pending handlers run
+                unsafe { mstatus::set_mie() };
+            }
+        }
+        crate::timer::set_alarm(crate::timer::SLEEP_ALARM, u64::MAX);
+        if interrupts {
+            unsafe { mstatus::set_mie() };
+        }
+    }
+}
+
+impl DelayNs for SleepDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        if ns < SLEEP_THRESHOLD_US * 1_000 {
+            self.spin.delay_ns(ns);
+        } else {
+            self.sleep(ns, 1_000_000_000);
+        }
+    }
+
+    fn delay_us(&mut self, us: u32) {
+        if us < SLEEP_THRESHOLD_US {
+            self.spin.delay_us(us);
+        } else {
+            self.sleep(us, 1_000_000);
+        }
+    }
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.sleep(ms, 1_000);
+    }
+}
+
+/// Number of `MtimeDelay` instances that can exist at the same time
+#[cfg(feature = "async")]
+pub const MTIME_DELAY_SLOTS: usize = 8;
+
+/// Slots owned by an `MtimeDelay`
+#[cfg(feature = "async")]
+static CLAIMED_SLOTS: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(0);
+
+#[cfg(feature = "async")]
+static SLOT_WAKERS: [crate::interrupt::WakerSlot; MTIME_DELAY_SLOTS] =
+    [const { crate::interrupt::WakerSlot::new() }; MTIME_DELAY_SLOTS];
+
+/// Async delay on the machine timer compare interrupt
+///
+/// Every instance owns one of [`MTIME_DELAY_SLOTS`] alarms of the `mtimecmp` queue, see
+/// [`MachineTimer`](crate::timer::MachineTimer), so delays in different tasks can run
+/// concurrently, and alongside a `Tick`. Each task needs its own instance.
+///
+/// `mtime` must count at [`MTIMER_FREQ`](crate::clock::MTIMER_FREQ), which gives delays a 1 µs
+/// resolution; they are rounded up. The `MTIP` interrupt must be enabled and routed to
+/// [`on_machine_timer`](crate::timer::on_machine_timer):
+///
+/// ```rust
+///   #[export_name = "MachineTimer"]
+///   fn machine_timer() {
+///       hal::timer::on_machine_timer();
+///   }
+///
+///   unsafe { interrupt::enable(Interrupt::MTIP) };
+///   let mut delay = MtimeDelay::new(&clocks).unwrap();
+///   delay.delay_ms(100).await;
+/// ```
+#[cfg(feature = "async")]
+pub struct MtimeDelay {
+    slot: u8,
+}
+
+#[cfg(feature = "async")]
+impl MtimeDelay {
+    /// Claim a slot of the timer queue, `None` if all of them are in use
+    ///
+    /// # Panics
+    /// if `mtime` does not count at [`MTIMER_FREQ`](crate::clock::MTIMER_FREQ)
+    pub fn new(clocks: &crate::clock::Clocks) -> Option<Self> {
+        use core::sync::atomic::Ordering;
+
+        assert_eq!(
+            clocks.mtimer_clk().0,
+            crate::clock::MTIMER_FREQ,
+            "mtime does not count at MTIMER_FREQ"
+        );
+        let mut claimed = CLAIMED_SLOTS.load(Ordering::Relaxed);
+        loop {
+            let slot = claimed.trailing_ones() as u8;
+            if slot as usize >= MTIME_DELAY_SLOTS {
+                return None;
+            }
+            match CLAIMED_SLOTS.compare_exchange_weak(
+                claimed,
+                claimed | 1 << slot,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Some(MtimeDelay { slot }),
+                Err(current) => claimed = current,
+            }
+        }
+    }
+
+    /// Sleep until `mtime` reaches `deadline`
+    async fn wait_until(&mut self, deadline: u64) {
+        use core::task::Poll;
+
+        /// Takes the slot out of the queue if the future is dropped before it expired
+        struct Disarm(u8);
+
+        impl Drop for Disarm {
+            fn drop(&mut self) {
+                crate::timer::set_alarm(crate::timer::DELAY_ALARMS + self.0 as usize, u64::MAX);
+            }
+        }
+
+        let slot = self.slot;
+        let _disarm = Disarm(slot);
+        core::future::poll_fn(|cx| {
+            if crate::timer::mtime() >= deadline {
+                return Poll::Ready(());
+            }
+            SLOT_WAKERS[slot as usize].register(cx.waker());
+            crate::timer::set_alarm(crate::timer::DELAY_ALARMS + slot as usize, deadline);
+            // check again, the deadline may have passed before the comparator was set
+            if crate::timer::mtime() >= deadline {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for MtimeDelay {
+    fn drop(&mut self) {
+        CLAIMED_SLOTS.fetch_and(!(1 << self.slot), core::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+#[cfg(feature = "async")]
+impl embedded_hal_async::delay::DelayNs for MtimeDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        let deadline = crate::timer::mtime() + (ns as u64).div_ceil(1_000);
+        self.wait_until(deadline).await
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        let deadline = crate::timer::mtime() + us as u64;
+        self.wait_until(deadline).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        let deadline = crate::timer::mtime() + ms as u64 * 1_000;
+        self.wait_until(deadline).await
+    }
+}
+
+/// Wake the delay of `slot`, its alarm expired
+#[cfg(feature = "async")]
+pub(crate) fn wake_slot(slot: usize) {
+    SLOT_WAKERS[slot].wake();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MHZ_144: u32 = 144_000_000;
+
+    #[test]
+    fn to_cycles_rounds_up() {
+        assert_eq!(to_cycles(0, MHZ_144, 1_000_000_000), 0);
+        // 0.144 cycles
+        assert_eq!(to_cycles(1, MHZ_144, 1_000_000_000), 1);
+        // 143.856 cycles
+        assert_eq!(to_cycles(999, MHZ_144, 1_000_000_000), 144);
+        assert_eq!(to_cycles(1_000, MHZ_144, 1_000_000_000), 144);
+        assert_eq!(to_cycles(1_001, MHZ_144, 1_000_000_000), 145);
+    }
+
+    const MHZ_32: u32 = 32_000_000;
+
+    #[test]
+    fn delay_ns_boundaries() {
+        // everything up to the overhead is spent entering and leaving the delay
+        assert_eq!(loop_cycles(0, MHZ_32, 1_000_000_000), 0);
+        assert_eq!(loop_cycles(1, MHZ_32, 1_000_000_000), 0);
+        assert_eq!(loop_cycles(312, MHZ_32, 1_000_000_000), 0);
+        // 10.016 cycles round up to 11, one more than the overhead
+        assert_eq!(loop_cycles(313, MHZ_32, 1_000_000_000), 1);
+        assert_eq!(loop_cycles(1_000, MHZ_32, 1_000_000_000), 32 - DELAY_OVERHEAD_CYCLES);
+        assert_eq!(loop_cycles(1, MHZ_144, 1_000_000_000), 0);
+        assert_eq!(loop_cycles(69, MHZ_144, 1_000_000_000), 0);
+        assert_eq!(loop_cycles(70, MHZ_144, 1_000_000_000), 1);
+        assert_eq!(loop_cycles(1_000, MHZ_144, 1_000_000_000), 144 - DELAY_OVERHEAD_CYCLES);
+        assert_eq!(loop_cycles(u32::MAX, MHZ_144, 1_000_000_000), 618_475_281);
+    }
+
+    #[test]
+    fn delay_us_boundaries() {
+        assert_eq!(loop_cycles(0, MHZ_32, 1_000_000), 0);
+        assert_eq!(loop_cycles(1, MHZ_32, 1_000_000), 32 - DELAY_OVERHEAD_CYCLES);
+        assert_eq!(loop_cycles(1, MHZ_144, 1_000_000), 144 - DELAY_OVERHEAD_CYCLES);
+        assert_eq!(loop_cycles(u32::MAX, MHZ_32, 1_000_000), 137_438_953_440 - 10);
+        assert_eq!(loop_cycles(u32::MAX, MHZ_144, 1_000_000), 618_475_290_480 - 10);
+        // the overhead is never taken off below zero
+        assert_eq!(loop_cycles(1, 1_000_000, 1_000_000), 0);
+    }
+
+    #[test]
+    fn ns_to_cycles_saturates() {
+        assert_eq!(ns_to_cycles(0, MHZ_144), 0);
+        assert_eq!(ns_to_cycles(350, MHZ_144), 51);
+        assert_eq!(ns_to_cycles(350, MHZ_32), 12);
+        assert_eq!(ns_to_cycles(u32::MAX, MHZ_144), 618_475_291);
+        assert_eq!(ns_to_cycles(u32::MAX, u32::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn to_cycles_does_not_overflow() {
+        // the products are far beyond u32
+        assert_eq!(to_cycles(u32::MAX, MHZ_144, 1_000_000_000), 618_475_291);
+        assert_eq!(to_cycles(u32::MAX, MHZ_144, 1_000_000), 618_475_290_480);
+        assert_eq!(to_cycles(u32::MAX, MHZ_144, 1_000), 618_475_290_480_000);
+        assert_eq!(to_cycles(u32::MAX, u32::MAX, 1), u32::MAX as u64 * u32::MAX as u64);
     }
 }
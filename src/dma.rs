@@ -0,0 +1,310 @@
+/*!
+# Direct Memory Access
+The DMA controller has 8 channels which can move data between memory and peripheral FIFOs
+without involving the CPU. Split the peripheral into its channels and hand a channel to a
+peripheral driver, for example `Spi::write_dma`.
+
+```rust
+  let dma = dp.DMA.split();
+  let transfer = spi.write_dma(dma.ch0, &FRAMEBUFFER);
+  let (framebuffer, ch0) = transfer.wait();
+```
+
+Buffers are passed using the [`embedded-dma`] traits, which only allow buffers that stay
+valid for as long as the DMA might access them (e.g. `&'static` slices).
+
+[`embedded-dma`]: https://crates.io/crates/embedded-dma
+*/
+
+use crate::pac;
+use core::mem::ManuallyDrop;
+use core::ptr;
+
+/// Base address of the DMA controller.
+///
+/// The PAC currently places the DMA register block at the efuse address, so the HAL uses the
+/// address from the reference manual instead.
+const DMA_BASE: usize = 0x4000_c000;
+
+/// Maximum number of items a channel can move in a single transfer
+pub const MAX_TRANSFER_SIZE: usize = 0xfff;
+
+/// Pointer to the DMA register block
+///
+/// # Safety
+/// only use this to access registers you already have exclusive access to
+unsafe fn ptr() -> &'static pac::dma::RegisterBlock {
+    &*(DMA_BASE as *const pac::dma::RegisterBlock)
+}
+
+/// Pointer to the registers of channel `id`.
+///
+/// All channels share the channel 0 layout, each offset by 0x100. The returned block is shifted
+/// so that its `dma_c0*` fields address the registers of channel `id`.
+///
+/// # Safety
+/// only use this to access registers of a channel you own
+unsafe fn channel_ptr(id: u8) -> &'static pac::dma::RegisterBlock {
+    &*((DMA_BASE + 0x100 * id as usize) as *const pac::dma::RegisterBlock)
+}
+
+/// Peripheral handshake lines that can pace a DMA channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// UART0 RX FIFO
+    Uart0Rx = 0,
+    /// UART0 TX FIFO
+    Uart0Tx = 1,
+    /// UART1 RX FIFO
+    Uart1Rx = 2,
+    /// UART1 TX FIFO
+    Uart1Tx = 3,
+    /// I2C RX FIFO
+    I2cRx = 6,
+    /// I2C TX FIFO
+    I2cTx = 7,
+    /// SPI RX FIFO
+    SpiRx = 10,
+    /// SPI TX FIFO
+    SpiTx = 11,
+    /// I2S RX FIFO
+    I2sRx = 20,
+    /// I2S TX FIFO
+    I2sTx = 21,
+    /// GPADC channel 0
+    Gpadc0 = 22,
+    /// GPADC channel 1
+    Gpadc1 = 23,
+}
+
+/// Width of a single item moved by the DMA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    /// 8 bit
+    Byte = 0,
+    /// 16 bit
+    HalfWord = 1,
+    /// 32 bit
+    Word = 2,
+}
+
+impl Width {
+    /// Width matching the size of `T`
+    pub(crate) const fn of<T>() -> Self {
+        match core::mem::size_of::<T>() {
+            1 => Width::Byte,
+            2 => Width::HalfWord,
+            _ => Width::Word,
+        }
+    }
+}
+
+/// Direction and flow control of a transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    /// Memory to peripheral, paced by the peripheral
+    MemoryToPeripheral(Request),
+    /// Peripheral to memory, paced by the peripheral
+    PeripheralToMemory(Request),
+}
+
+/// Extension trait to split the DMA peripheral into independent channels
+pub trait DmaExt {
+    /// Splits the DMA controller into its channels
+    fn split(self) -> Channels;
+}
+
+impl DmaExt for pac::DMA {
+    fn split(self) -> Channels {
+        crate::clock::enable_gate(crate::clock::Gate::Dma);
+
+        let dma = unsafe { ptr() };
+        dma.dma_top_config.modify(|_, w| w.e().set_bit());
+
+        Channels {
+            ch0: Channel { id: 0 },
+            ch1: Channel { id: 1 },
+            ch2: Channel { id: 2 },
+            ch3: Channel { id: 3 },
+            ch4: Channel { id: 4 },
+            ch5: Channel { id: 5 },
+            ch6: Channel { id: 6 },
+            ch7: Channel { id: 7 },
+        }
+    }
+}
+
+/// DMA channels
+pub struct Channels {
+    pub ch0: Channel,
+    pub ch1: Channel,
+    pub ch2: Channel,
+    pub ch3: Channel,
+    pub ch4: Channel,
+    pub ch5: Channel,
+    pub ch6: Channel,
+    pub ch7: Channel,
+}
+
+/// A single DMA channel
+pub struct Channel {
+    id: u8,
+}
+
+impl Channel {
+    /// Channel number
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Program a transfer of `len` items between `memory` and the peripheral register `register`.
+    ///
+    /// The peripheral register address stays fixed. The memory address is incremented after every
+    /// item unless `increment_memory` is false, which is used to feed a constant dummy word.
+    pub(crate) fn configure(
+        &mut self,
+        direction: Direction,
+        memory: usize,
+        register: usize,
+        len: usize,
+        width: Width,
+        increment_memory: bool,
+    ) {
+        let ch = unsafe { channel_ptr(self.id) };
+        let dma = unsafe { ptr() };
+
+        self.stop();
+        let mask = 1 << self.id;
+        dma.dma_int_tcclear.write(|w| unsafe { w.int_tcclear().bits(mask) });
+        dma.dma_int_err_clr.write(|w| unsafe { w.bits(mask as u32) });
+
+        let (src, dst, flow, src_peripheral, dst_peripheral, si, di) = match direction {
+            Direction::MemoryToPeripheral(req) => {
+                (memory, register, 1, 0, req as u8, increment_memory, false)
+            }
+            Direction::PeripheralToMemory(req) => {
+                (register, memory, 2, req as u8, 0, false, increment_memory)
+            }
+        };
+
+        ch.dma_c0src_addr.write(|w| unsafe { w.bits(src as u32) });
+        ch.dma_c0dst_addr.write(|w| unsafe { w.bits(dst as u32) });
+        ch.dma_c0lli.write(|w| unsafe { w.bits(0) });
+        ch.dma_c0control.write(|w| unsafe {
+            w.transfer_size()
+                .bits(len as u16)
+                .sbsize()
+                .bits(0) // single item bursts, FIFO thresholds are 0
+                .dbsize()
+                .bits(0)
+                .swidth()
+                .bits(width as u8)
+                .dwidth()
+                .bits(width as u8)
+                .si()
+                .bit(si)
+                .di()
+                .bit(di)
+                .i()
+                .set_bit()
+        });
+        ch.dma_c0config.write(|w| unsafe {
+            w.flow_cntrl()
+                .bits(flow)
+                .src_peripheral()
+                .bits(src_peripheral)
+                .dst_peripheral()
+                .bits(dst_peripheral)
+                .ie()
+                .set_bit()
+                .itc()
+                .set_bit()
+        });
+    }
+
+    /// Start the programmed transfer
+    pub(crate) fn start(&mut self) {
+        let ch = unsafe { channel_ptr(self.id) };
+        ch.dma_c0config.modify(|_, w| w.e().set_bit());
+    }
+
+    /// Disable the channel, aborting a transfer in progress
+    pub(crate) fn stop(&mut self) {
+        let ch = unsafe { channel_ptr(self.id) };
+        ch.dma_c0config.modify(|_, w| w.e().clear_bit());
+    }
+
+    /// Returns true once the programmed transfer has completed
+    pub fn is_done(&self) -> bool {
+        let dma = unsafe { ptr() };
+        dma.dma_raw_int_tcstatus.read().raw_int_tcstatus().bits() & (1 << self.id) != 0
+            || dma.dma_enbld_chns.read().enabled_channels().bits() & (1 << self.id) == 0
+    }
+}
+
+/// An ongoing DMA transfer.
+///
+/// The buffer and channels are returned from [`Transfer::wait`]. Transfers longer than
+/// [`MAX_TRANSFER_SIZE`] items are split into chunks, the next chunk is started whenever
+/// [`Transfer::is_done`] or [`Transfer::wait`] notices the previous one finished.
+///
+/// Dropping an unfinished transfer stops its channels and the DMA requests of the peripheral,
+/// the channels are lost with it. Don't `mem::forget` a transfer: the DMA then keeps
+/// accessing the buffer, which is only sound for `'static` buffers that are never used again.
+#[must_use = "dropping a transfer aborts it"]
+pub struct Transfer<BUF, PAYLOAD>
+where
+    PAYLOAD: TransferPayload,
+{
+    pub(crate) buffer: BUF,
+    pub(crate) payload: PAYLOAD,
+}
+
+/// Peripheral side of a DMA transfer - DO NOT IMPLEMENT THIS TRAIT
+///
+/// # Safety
+/// DO NOT IMPLEMENT THIS TRAIT
+pub unsafe trait TransferPayload {
+    /// Channel(s) handed back once the transfer completes
+    type Channels;
+
+    /// Start the next chunk if the current one completed.
+    /// Returns true once all chunks have completed.
+    fn poll(&mut self) -> bool;
+
+    /// Called once after the last chunk completed, before the buffer is handed back
+    fn finish(self) -> Self::Channels;
+
+    /// Stop the channel(s) and the DMA requests of the peripheral, when the transfer is
+    /// dropped before it completed
+    fn abort(&mut self);
+}
+
+impl<BUF, PAYLOAD> Transfer<BUF, PAYLOAD>
+where
+    PAYLOAD: TransferPayload,
+{
+    /// Returns true once the whole buffer has been transferred
+    pub fn is_done(&mut self) -> bool {
+        self.payload.poll()
+    }
+
+    /// Block until the transfer has completed and hand back the buffer and the channel(s)
+    pub fn wait(self) -> (BUF, PAYLOAD::Channels) {
+        let mut transfer = ManuallyDrop::new(self);
+        while !transfer.payload.poll() {}
+        // both fields are moved out exactly once and `transfer` is not dropped
+        let (buffer, payload) =
+            unsafe { (ptr::read(&transfer.buffer), ptr::read(&transfer.payload)) };
+        (buffer, payload.finish())
+    }
+}
+
+impl<BUF, PAYLOAD> Drop for Transfer<BUF, PAYLOAD>
+where
+    PAYLOAD: TransferPayload,
+{
+    fn drop(&mut self) {
+        self.payload.abort();
+    }
+}
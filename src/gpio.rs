@@ -1,5 +1,127 @@
 //! General Purpose Input/Output
+//!
+//! Pins start out as floating inputs after `GlbExt::split`. Use the `into_*_input` conversions to
+//! select the pull resistor and read the pin through `embedded_hal::digital::InputPin`:
+//!
+//! ```rust
+//!   let mut button = parts.pin10.into_pull_up_input();
+//!   if button.is_low().unwrap() {
+//!       // pressed
+//!   }
+//! ```
+//!
+//! Output pins do not implement `InputPin`; the last level written to them can be read back
+//! through `embedded_hal::digital::StatefulOutputPin` instead.
+//!
+//! The `into_*_output` conversions keep the level in the output latch. Lines that must not
+//! glitch, such as a reset line, select the level before the driver turns on:
+//!
+//! ```rust
+//!   let mut modem_reset = parts.pin22.into_floating_output_with_state(PinState::High);
+//! ```
+//!
+//! ## Pad configuration
+//! Every `into_*` conversion leaves the pad with the lowest drive strength
+//! ([`DriveStrength::Level0`]), the schmitt trigger enabled and the input buffer enabled.
+//! `set_drive_strength` and `enable_schmitt` change these afterwards in any mode.
+//!
+//! The GPIO input path has no deglitch filter; only the SPI, UART, I2S and QDEC peripherals
+//! have their own. The schmitt trigger removes slow-edge chatter, and
+//! `InterruptPin::control_synchronous` samples the trigger with the GLB clock, so spikes
+//! shorter than a clock cycle may be missed but longer ones still fire. Debounce mechanical
+//! contacts such as reed switches in software, e.g. by disabling the interrupt in the handler
+//! and re-enabling it after a delay.
+//!
+//! ## Always-on pads
+//! GPIO9 to GPIO13 sit on the always-on (AON) pads. While the chip runs they are configured
+//! through the GLB like every other pin, so all `into_*` conversions apply to them. In HBN the
+//! GLB is powered down and the HBN block takes over these pads: only their input buffer (for
+//! wakeup, see [`crate::hbn`]) and pull resistors (see [`crate::hbn::hold_aon_pulls`]) are
+//! kept. The pads have no output latch in the always-on domain, so a level that must survive
+//! HBN has to be held with a pull resistor. Low-power code can require such a pad with the
+//! [`AonPin`] bound, or check at runtime with `is_aon`.
+//!
+//! ## Holding output levels
+//! The pads have no output latch retention. GPIO17 to GPIO28 can instead have their level
+//! kept by a pull resistor in the PDS block: `hold` on an output pin selects the pull-up if
+//! the pin is high and the pull-down if it is low. The PDS pulls take over while the GLB pad
+//! configuration is lost in PDS sleep, and keep a power-enable line at its level through a
+//! CPU reset, which leaves the GLB and PDS configuration alone; a full system reset clears
+//! both. `hold` returns [`Error::HoldNotSupported`] on all other pads, use
+//! [`crate::hbn::hold_aon_pulls`] for GPIO9 to GPIO13. Release the holds with
+//! [`release_all_holds`] once `main` configured the pads again.
+//!
+//! ## PWM
+//! `into_pwm` connects a pin to PWM channel `pin % 5`. The resulting pin implements
+//! `PwmPin<PwmChN>` for its channel only, so a PWM driver can reject a pin on the wrong channel
+//! at compile time:
+//!
+//! | channel | pins                     |
+//! |---------|--------------------------|
+//! | 0       | 0, 5, 10, 15, 20, 25, 30 |
+//! | 1       | 1, 6, 11, 16, 21, 26, 31 |
+//! | 2       | 2, 7, 12, 17, 22, 27     |
+//! | 3       | 3, 8, 13, 18, 23, 28     |
+//! | 4       | 4, 9, 14, 19, 24, 29     |
+//!
+//! ## QDEC
+//! `into_qdec_a`, `into_qdec_b` and `into_qdec_led` connect a pin to one of the three quadrature
+//! decoders. All pads use the same function, the decoder and signal are fixed by the pad number
+//! and repeat every nine pins, so each pin has only the conversion for its signal:
+//!
+//! | decoder | A             | B             | LED           |
+//! |---------|---------------|---------------|---------------|
+//! | 0       | 0, 9, 18, 27  | 1, 10, 19, 28 | 2, 11, 20, 29 |
+//! | 1       | 3, 12, 21, 30 | 4, 13, 22, 31 | 5, 14, 23     |
+//! | 2       | 6, 15, 24     | 7, 16, 25     | 8, 17, 26     |
+//!
+//! The resulting pins implement `QdecAPin<QdecN>`, `QdecBPin<QdecN>` or `QdecLedPin<QdecN>`.
+//!
+//! ## Interrupts
+//! Input pins implement [`InterruptPin`]. All pins share the `GPIO_INT0` interrupt, which is
+//! enabled in the CLIC with [`unmask_irq`]. The handler finds the pins that fired with
+//! [`pending_pins`] and acknowledges them with [`clear_pending_pins`]:
+//!
+//! ```rust
+//!   #[export_name = "DefaultHandler"]
+//!   fn default_handler() {
+//!       if interrupt::current() == Some(Interrupt::GPIO_INT0) {
+//!           let pending = gpio::pending_pins();
+//!           if pending & (1 << 10) != 0 {
+//!               // button on pin 10
+//!           }
+//!           gpio::clear_pending_pins(pending);
+//!       }
+//!   }
+//!
+//!   button.enable_interrupt(TriggerMode::FallingEdge);
+//!   unsafe { gpio::unmask_irq() };
+//! ```
+//!
+//! Clear level triggered pins only after the level went away, or disable them in the handler,
+//! otherwise the interrupt fires again right away.
+//!
+//! ## Async
+//! With the `async` feature enabled input pins implement `embedded_hal_async::digital::Wait`.
+//! The futures are woken from the GPIO interrupt, so route it to [`on_interrupt`] and enable it:
+//!
+//! ```rust
+//!   #[export_name = "DefaultHandler"]
+//!   fn default_handler() {
+//!       if interrupt::current() == Some(Interrupt::GPIO_INT0) {
+//!           hal::gpio::on_interrupt();
+//!       }
+//!   }
+//!
+//!   unsafe { gpio::unmask_irq() };
+//!   button.wait_for_falling_edge().await?;
+//! ```
+//!
+//! [`on_interrupt`] only handles pins with a pending `Wait` future, other pins can still be
+//! dispatched with [`pending_pins`] in the same handler.
 use core::marker::PhantomData;
+use embedded_time::duration::Microseconds;
+use crate::delay::McycleDelay;
 use crate::pac;
 
 /// Extension trait to split GLB peripheral into independent pins, registers and other modules
@@ -14,7 +136,9 @@ pub trait GlbExt {
 #[non_exhaustive]
 pub enum Error {
     /// Framing error
-    Other
+    Other,
+    /// The pad cannot hold its level, see `hold`
+    HoldNotSupported,
 }
 
 impl embedded_hal::digital::Error for Error {
@@ -25,29 +149,103 @@ impl embedded_hal::digital::Error for Error {
     }
 }
 
-#[derive(Copy, Clone)]
-pub enum Event {
-    /// Trigger on the falling edge
-    NegativePulse = 0,
+/// Condition that raises a GPIO interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
     /// Trigger on the rising edge
-    PositivePulse = 1,
-    /// Trigger while low level
-    NegativeLevel = 2,
-    /// Trigger while high level
-    HighLevel = 3,
+    RisingEdge,
+    /// Trigger on the falling edge
+    FallingEdge,
+    /// Trigger on both edges
+    ///
+    /// The hardware has no dual edge mode, so this arms a level trigger for the opposite of the
+    /// current pin level and flips it every time the pending flag is cleared. An edge between
+    /// reading the level and re-arming leaves the new level trigger active, so it is not lost.
+    BothEdges,
+    /// Trigger while the pin is high
+    HighLevel,
+    /// Trigger while the pin is low
+    LowLevel,
 }
 
-/// Extension trait to setup/enable/disable/clear/check input pins
+/// Extension trait to setup/enable/disable/clear/check input pin interrupts
 pub trait InterruptPin {
-    // Is make_interrupt_source redundant?
-    //fn make_interrupt_source(&mut self, afio: &mut afio::Parts);
-    fn trigger_on_event(&mut self, event: Event);
+    /// Arm the interrupt of this pin for `trigger`
+    ///
+    /// The pin is masked while the trigger is changed and its pending flag is cleared before it
+    /// is unmasked again, so a stale event from the previous configuration never fires.
+    fn enable_interrupt(&mut self, trigger: TriggerMode);
+    /// Mask the interrupt of this pin
+    fn disable_interrupt(&mut self);
+    /// Clear the pending flag of this pin
+    ///
+    /// Level triggers stay pending for as long as the level is held.
+    fn clear_pending(&mut self);
+    /// Returns true if the interrupt of this pin is pending
+    fn is_pending(&self) -> bool;
+    /// Sample the trigger asynchronously, this also works while the GLB clock is gated
     fn control_asynchronous(&mut self);
+    /// Sample the trigger synchronously to the GLB clock (default)
     fn control_synchronous(&mut self);
-    fn enable_interrupt(&mut self);
-    fn disable_interrupt(&mut self);
-    fn clear_interrupt_pending_bit(&mut self);
-    fn check_interrupt(&self) -> bool;
+}
+
+/// Returns a bitmask of the pins with an enabled and pending interrupt, bit `n` is pin `n`
+///
+/// Intended for dispatching inside the `GPIO_INT0` handler together with [`clear_pending_pins`].
+pub fn pending_pins() -> u32 {
+    let glb = unsafe { &*pac::GLB::ptr() };
+    glb.gpio_int_stat1.read().bits() & !glb.gpio_int_mask1.read().bits()
+}
+
+/// Clear the pending flags of all pins in `mask`, bit `n` is pin `n`
+pub fn clear_pending_pins(mask: u32) {
+    (0..32)
+        .filter(|pin| mask & (1 << pin) != 0)
+        .for_each(pad::clear_interrupt);
+}
+
+/// Returns the input levels of all pins at once, bit `n` is pin `n`
+///
+/// Test single pins in the snapshot with `is_high_in`.
+pub fn read_all() -> u32 {
+    let glb = unsafe { &*pac::GLB::ptr() };
+    glb.gpio_cfgctl30.read().bits()
+}
+
+/// Set the output latches of the pins in `mask` to the matching bits of `value`
+///
+/// All pins change with a single write, done in a critical section so it is safe to call from
+/// interrupt context. Pins that are not in output mode only get their latch updated.
+pub fn write_all(mask: u32, value: u32) {
+    let glb = unsafe { &*pac::GLB::ptr() };
+    riscv::interrupt::free(|| {
+        glb.gpio_cfgctl32
+            .modify(|r, w| unsafe { w.bits((r.bits() & !mask) | (value & mask)) });
+    });
+}
+
+/// Enable the GPIO interrupt in the CLIC
+///
+/// # Safety
+/// See [`crate::interrupt::enable`]
+pub unsafe fn unmask_irq() {
+    crate::interrupt::enable(crate::interrupt::Interrupt::GPIO_INT0);
+}
+
+/// Disable the GPIO interrupt in the CLIC
+pub fn mask_irq() {
+    crate::interrupt::disable(crate::interrupt::Interrupt::GPIO_INT0);
+}
+
+/// Release the holds of all pads, see `hold`
+///
+/// Call this early in `main`, after the held pads were configured again.
+pub fn release_all_holds() {
+    let pds = unsafe { &*pac::PDS::ptr() };
+    riscv::interrupt::free(|| {
+        pds.pds_gpio_set_pu_pd.reset();
+        pds.pds_ctl.modify(|_, w| w.cr_pds_ctrl_gpio_ie_pu_pd().clear_bit());
+    });
 }
 
 pub use uart_sig::*;
@@ -82,6 +280,25 @@ pub mod uart_sig {
     /// UART1 TXD (type state)
     pub struct Uart1Tx;
 
+    /// Returns two UART signals that are routed to a pad and select the same UART function
+    ///
+    /// Pin `n` carries UART signal `n % 8`, so e.g. two pins both configured with
+    /// `into_uart0_tx` on different signals are reported here. Signals without a pad in UART
+    /// mode are ignored.
+    pub fn find_conflict() -> Option<(u8, u8)> {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        let sel = glb.uart_sig_sel_0.read().bits();
+        let used = (0..32)
+            .filter(|pin| super::pad::function(*pin) == super::pad::FUNC_UART)
+            .fold(0u8, |used, pin| used | (1 << (pin % 8)));
+        let function = |sig: u8| (sel >> (sig * 4)) & 0xf;
+
+        (0..8)
+            .filter(|a| used & (1 << a) != 0)
+            .flat_map(|a| ((a + 1)..8).map(move |b| (a, b)))
+            .find(|&(a, b)| used & (1 << b) != 0 && function(a) == function(b))
+    }
+
     macro_rules! impl_uart_sig {
         ($UartSigi: ident, $doc1: expr, $sigi:ident, $UartMuxi: ident, $doc2: expr) => {
             #[doc = $doc1]
@@ -197,70 +414,1361 @@ pub mod uart_sig {
         "UART multiplexer peripherals for signal 5"
     );
 
-    impl_uart_sig!(
-        UartSig6,
-        "UART signal 6 (type state)",
-        sig_6,
-        UartMux6,
-        "UART multiplexer peripherals for signal 6"
-    );
+    impl_uart_sig!(
+        UartSig6,
+        "UART signal 6 (type state)",
+        sig_6,
+        UartMux6,
+        "UART multiplexer peripherals for signal 6"
+    );
+
+    impl_uart_sig!(
+        UartSig7,
+        "UART signal 7 (type state)",
+        sig_7,
+        UartMux7,
+        "UART multiplexer peripherals for signal 7"
+    );
+}
+
+/// Clock configurator registers
+pub struct ClkCfg {
+    pub(crate) _ownership: (),
+}
+
+/// JTAG pad ownership
+///
+/// Some pads come out of reset with the JTAG function selected and shared with the CCI
+/// interface. The `into_*` conversions only change the pad function, so call [`Jtag::release`]
+/// first to use those pads as GPIO. This is never done implicitly, since it disconnects an
+/// attached debugger.
+pub struct Jtag {
+    /// Pads switched away from the JTAG function by `release`
+    released: u32,
+    /// `reg_cci_use_jtag_pin` was set before `release` cleared it
+    cci: bool,
+}
+
+impl Jtag {
+    /// Hand the JTAG pads over to GPIO, leaving them as floating inputs
+    ///
+    /// An attached debugger loses the connection.
+    pub fn release(&mut self) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        self.cci |= glb.glb_parm.read().reg_cci_use_jtag_pin().bit_is_set();
+        glb.glb_parm.modify(|_, w| w.reg_cci_use_jtag_pin().clear_bit());
+        for pin in (0..32).filter(|pin| pad::function(*pin) == pad::FUNC_JTAG) {
+            pad::set_output_enable(pin, false);
+            pad::set_function(pin, pad::FUNC_SWGPIO);
+            self.released |= 1 << pin;
+        }
+    }
+
+    /// Returns a bitmask of the pads released by [`Jtag::release`], bit `n` is pin `n`
+    pub fn released_pins(&self) -> u32 {
+        self.released
+    }
+
+    /// Select the JTAG function again on the pads released by [`Jtag::release`], and hand
+    /// them back to the CCI interface if they were shared with it before
+    ///
+    /// # Safety
+    /// The released pins must not be used as GPIO afterwards, their typed handles keep
+    /// compiling but no longer control the pads.
+    pub unsafe fn restore(&mut self) {
+        for pin in (0..32).filter(|pin| self.released & (1 << pin) != 0) {
+            pad::set_function(pin, pad::FUNC_JTAG);
+        }
+        if self.cci {
+            let glb = unsafe { &*pac::GLB::ptr() };
+            glb.glb_parm.modify(|_, w| w.reg_cci_use_jtag_pin().set_bit());
+        }
+        self.released = 0;
+        self.cci = false;
+    }
+}
+
+/*
+// todo: english
+    在GPIO模式下，可以设置内部上下拉，以类型状态机模式设计
+    SPI、UART、I2C等数字功能下，可以设置内部上下拉，但不会影响返回类型的状态
+    ADC、DAC下，软件禁止设置内部上下拉。HAL库不会生成此类函数，以免出错。
+*/
+
+/// Hi-Z Floating pin (type state)
+pub struct Floating;
+/// Pulled down pin (type state)
+pub struct PullDown;
+/// Pulled up pin (type state)
+pub struct PullUp;
+/// Pin with a pull resistor selected at runtime (type state)
+pub struct Dynamic;
+
+/// Input mode (type state)
+pub struct Input<MODE> {
+    _mode: PhantomData<MODE>,
+}
+
+/// Output mode (type state)
+pub struct Output<MODE> {
+    _mode: PhantomData<MODE>,
+}
+
+/// Pad output drive strength
+///
+/// The `into_*` conversions reset the pad to `Level0` with the schmitt trigger enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriveStrength {
+    /// Weakest drive, the default
+    Level0 = 0,
+    /// Drive level 1
+    Level1 = 1,
+    /// Drive level 2
+    Level2 = 2,
+    /// Strongest drive
+    Level3 = 3,
+}
+
+/// Clock output pin mode (type state)
+pub struct ClockOut;
+
+/// Source of the camera reference clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CamRefSource {
+    /// XCLK, the crystal after `board_clock_init`
+    Xclk = 0,
+    /// 96 MHz output of the DLL
+    Dll96M = 1,
+}
+
+impl CamRefSource {
+    /// Returns the frequency of the source, before the camera reference divider
+    pub fn frequency(&self, clocks: &crate::clock::Clocks) -> embedded_time::rate::Hertz {
+        match self {
+            CamRefSource::Xclk => clocks.xclk(),
+            CamRefSource::Dll96M => embedded_time::rate::Hertz(96_000_000),
+        }
+    }
+}
+
+/// Clock routed to a pad by `into_clock_output`
+///
+/// Even pins drive chip clock out 0 and odd pins chip clock out 1. Pins of the same parity
+/// share the selection, configuring one changes the clock on the others.
+/// [`crate::clock::ClockOut`] exports XCLK or the DLL with the divider checked against `Clocks`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockOutSelect {
+    /// Camera reference clock, `source` divided by `divider` (1 to 4, clamped)
+    ///
+    /// The camera reference clock is shared by both clock outputs.
+    CamRef { source: CamRefSource, divider: u8 },
+    /// I2S reference clock
+    I2sRef,
+    /// Audio clock at 128 times the sample rate
+    Audio128Fs,
+}
+
+impl ClockOutSelect {
+    /// Value of `chip_clk_out_x_sel`
+    fn sel(&self) -> u8 {
+        match self {
+            ClockOutSelect::CamRef { .. } => 0,
+            ClockOutSelect::I2sRef => 1,
+            ClockOutSelect::Audio128Fs => 3,
+        }
+    }
+
+    /// Returns the frequency on the pad, or `None` for the audio clocks since the HAL does
+    /// not configure the audio PLL yet
+    pub fn frequency(&self, clocks: &crate::clock::Clocks) -> Option<embedded_time::rate::Hertz> {
+        match *self {
+            ClockOutSelect::CamRef { source, divider } => Some(embedded_time::rate::Hertz(
+                source.frequency(clocks).0 / divider.clamp(1, 4) as u32,
+            )),
+            ClockOutSelect::I2sRef | ClockOutSelect::Audio128Fs => None,
+        }
+    }
+}
+
+/// Analog pin mode (type state)
+pub struct Analog;
+
+/// Pins connected to a GPADC channel, implemented for pins in [`Analog`] mode
+pub trait AdcChannel {
+    /// GPADC channel of the pin
+    const CHANNEL: u8;
+}
+
+/// IR receive pin mode (type state)
+pub struct IrRx;
+
+/// IR transmit (LED driver) pin mode (type state)
+pub struct IrTx;
+
+#[doc(hidden)]
+pub trait IrRxPin {}
+
+#[doc(hidden)]
+pub trait IrTxPin {}
+
+/// UART pin mode (type state)
+pub struct Uart;
+
+/// SPI pin mode (type state)
+pub struct Spi;
+
+/// I2C pin mode (type state)
+pub struct I2c;
+
+/// PWM pin mode (type state)
+pub struct Pwm;
+
+/// PWM channel 0 (type state)
+pub struct PwmCh0;
+/// PWM channel 1 (type state)
+pub struct PwmCh1;
+/// PWM channel 2 (type state)
+pub struct PwmCh2;
+/// PWM channel 3 (type state)
+pub struct PwmCh3;
+/// PWM channel 4 (type state)
+pub struct PwmCh4;
+
+#[doc(hidden)]
+pub trait UartPin<SIG> {}
+
+#[doc(hidden)]
+pub trait PwmPin<CH> {}
+
+/// Quadrature decoder pin mode (type state)
+pub struct Qdec;
+
+/// Quadrature decoder 0 (type state)
+pub struct Qdec0;
+/// Quadrature decoder 1 (type state)
+pub struct Qdec1;
+/// Quadrature decoder 2 (type state)
+pub struct Qdec2;
+
+#[doc(hidden)]
+pub trait QdecAPin<QDEC> {}
+
+#[doc(hidden)]
+pub trait QdecBPin<QDEC> {}
+
+#[doc(hidden)]
+pub trait QdecLedPin<QDEC> {}
+
+/// Pins on an always-on pad, GPIO9 to GPIO13, in any mode
+pub trait AonPin {
+    /// GPIO number of the pad
+    #[doc(hidden)]
+    const PIN: u8;
+}
+
+/// Pins that can drive a chip clock output, in any mode, see [`crate::clock::ClockOut`]
+pub trait ClockOutPin {
+    /// The pin in [`ClockOut`] mode
+    type ClockOut;
+    /// GPIO number of the pad
+    #[doc(hidden)]
+    const PIN: u8;
+    /// Same as `into_clock_output`, for drivers generic over the pin
+    #[doc(hidden)]
+    fn into_clock_out_pad(self, select: ClockOutSelect) -> Self::ClockOut;
+}
+
+/// Runtime pad configuration by pin number, for drivers that temporarily take over a pin
+/// they were handed as a typed pin
+pub(crate) mod pad {
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    use super::{CamRefSource, ClockOutSelect, DriveStrength, Error, Pull, TriggerMode};
+    use crate::pac;
+
+    /// GPIO_FUN_SPI_x
+    pub(crate) const FUNC_SPI: u8 = 4;
+    /// GPIO_FUN_UART
+    pub(crate) const FUNC_UART: u8 = 7;
+    /// GPIO_FUN_ANALOG
+    pub(crate) const FUNC_ANALOG: u8 = 10;
+    /// GPIO_FUN_SWGPIO
+    pub(crate) const FUNC_SWGPIO: u8 = 11;
+    /// GPIO_FUN_JTAG
+    pub(crate) const FUNC_JTAG: u8 = 14;
+    /// GPIO_FUN_QDEC
+    pub(crate) const FUNC_QDEC: u8 = 18;
+
+    /// Fields of the 16 bit pad configuration written by the typed pin conversions: ie at bit 0,
+    /// smt at bit 1, drv at bits 2..3, pu at bit 4, pd at bit 5, func_sel at bits 8..12
+    const MODE_MASK: u16 = 0b11_1111 | (0x1f << 8);
+    /// drv at bits 2..3
+    const DRIVE_MASK: u16 = 0b11 << 2;
+    /// Fields cleared by [`set_analog`]: ie, smt, pu, pd and func_sel
+    const ANALOG_MASK: u16 = 0b11 | (0b11 << 4) | (0x1f << 8);
+
+    /// Pad configuration written by the typed pin conversions for function `func`: lowest drive
+    /// level, schmitt trigger on and the input buffer kept active (github issue #8)
+    pub(crate) const fn mode_bits(func: u8, pu: bool, pd: bool) -> u16 {
+        0b11 | ((pu as u16) << 4) | ((pd as u16) << 5) | ((func as u16 & 0x1f) << 8)
+    }
+
+    /// Drive strength field of the pad configuration
+    pub(crate) const fn drive_bits(strength: DriveStrength) -> u16 {
+        (strength as u16) << 2
+    }
+
+    /// Pad configuration of the analog function, input buffer, schmitt trigger and pulls off
+    pub(crate) const fn analog_bits() -> u16 {
+        (FUNC_ANALOG as u16) << 8
+    }
+
+    /// Modify the 16 bit pad configuration of `pin`, two pins per gpio_cfgctl register
+    fn modify_cfgctl(pin: u8, mask: u32, bits: u32) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        let reg = (&glb.gpio_cfgctl0 as *const _ as *mut u32).wrapping_add(pin as usize / 2);
+        let shift = (pin as u32 % 2) * 16;
+        unsafe {
+            let value = reg.read_volatile() & !(mask << shift);
+            reg.write_volatile(value | ((bits & mask) << shift));
+        }
+    }
+
+    /// Select the pad function of `pin`
+    pub(crate) fn set_function(pin: u8, func: u8) {
+        // func_sel at bits 8..12
+        modify_cfgctl(pin, 0x1f << 8, (func as u32) << 8);
+    }
+
+    /// Returns the pad function of `pin`
+    pub(crate) fn function(pin: u8) -> u8 {
+        // func_sel at bits 8..12
+        ((cfgctl(pin) >> 8) & 0x1f) as u8
+    }
+
+    /// Select the analog function of `pin` with the input buffer, schmitt trigger and pulls off
+    pub(crate) fn set_analog(pin: u8) {
+        set_output_enable(pin, false);
+        modify_cfgctl(pin, ANALOG_MASK as u32, analog_bits() as u32);
+    }
+
+    /// Write the pad configuration of a typed pin conversion to `pin`, see [`mode_bits`]
+    pub(crate) fn set_mode(pin: u8, func: u8, pu: bool, pd: bool) {
+        modify_cfgctl(pin, MODE_MASK as u32, mode_bits(func, pu, pd) as u32);
+    }
+
+    /// Select the output drive strength of `pin`
+    pub(crate) fn set_drive(pin: u8, strength: DriveStrength) {
+        modify_cfgctl(pin, DRIVE_MASK as u32, drive_bits(strength) as u32);
+    }
+
+    /// Select the clock of the chip clock output that drives `pin`, chip clock out 0 for even
+    /// pins and 1 for odd pins
+    pub(crate) fn set_chip_clk_out(pin: u8, sel: u8) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        if pin & 1 == 0 {
+            glb.clk_cfg3.modify(|_, w| unsafe { w.chip_clk_out_0_sel().bits(sel) });
+        } else {
+            glb.clk_cfg3.modify(|_, w| unsafe { w.chip_clk_out_1_sel().bits(sel) });
+        }
+    }
+
+    /// Route `select` to the chip clock output that drives `pin`
+    pub(crate) fn set_clock_output(pin: u8, select: ClockOutSelect) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        if let ClockOutSelect::CamRef { source, divider } = select {
+            glb.clk_cfg1.modify(|_, w| unsafe {
+                w.reg_cam_ref_clk_src_sel()
+                    .bit(source == CamRefSource::Dll96M)
+                    .reg_cam_ref_clk_div()
+                    .bits(divider.clamp(1, 4) - 1)
+                    .reg_cam_ref_clk_en()
+                    .set_bit()
+            });
+        }
+        set_chip_clk_out(pin, select.sel());
+    }
+
+    /// Returns the 16 bit pad configuration of `pin`
+    pub(crate) fn cfgctl(pin: u8) -> u16 {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        let reg = (&glb.gpio_cfgctl0 as *const _ as *const u32).wrapping_add(pin as usize / 2);
+        (unsafe { reg.read_volatile() } >> ((pin as u32 % 2) * 16)) as u16
+    }
+
+    /// Returns true if the output driver of `pin` is enabled
+    pub(crate) fn output_enabled(pin: u8) -> bool {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        glb.gpio_cfgctl34.read().bits() & (1 << pin) != 0
+    }
+
+    /// Enable or disable the schmitt trigger of `pin`
+    pub(crate) fn set_schmitt(pin: u8, enable: bool) {
+        // smt at bit 1
+        modify_cfgctl(pin, 1 << 1, (enable as u32) << 1);
+    }
+
+    /// Select the pull resistor of `pin`
+    pub(crate) fn set_pull(pin: u8, pull: Pull) {
+        // pu at bit 4, pd at bit 5
+        let bits = match pull {
+            Pull::None => 0,
+            Pull::Up => 1 << 4,
+            Pull::Down => 1 << 5,
+        };
+        modify_cfgctl(pin, 0b11 << 4, bits);
+    }
+
+    /// Pads whose pulls the PDS block can drive, one bit per pad in each `pds_gpio_set_pu_pd` field
+    const HOLD_PINS: core::ops::RangeInclusive<u8> = 17..=28;
+
+    /// Returns the pull-down bit of `pin` in `pds_gpio_set_pu_pd`, the pull-up is 8 bits above
+    fn hold_bit(pin: u8) -> Result<u32, Error> {
+        if !HOLD_PINS.contains(&pin) {
+            return Err(Error::HoldNotSupported);
+        }
+        // GPIO17..22 pd at bits 0..5, GPIO23..28 pd at bits 16..21
+        let index = pin - 17;
+        Ok(if index < 6 { 1 << index } else { 1 << (16 + index - 6) })
+    }
+
+    /// Hold the output level of `pin` with the PDS pull resistors
+    pub(crate) fn hold(pin: u8) -> Result<(), Error> {
+        let pds = unsafe { &*pac::PDS::ptr() };
+        let pd = hold_bit(pin)?;
+        let bit = if output(pin) { pd << 8 } else { pd };
+        riscv::interrupt::free(|| {
+            pds.pds_gpio_set_pu_pd
+                .modify(|r, w| unsafe { w.bits((r.bits() & !(pd | (pd << 8))) | bit) });
+            pds.pds_ctl.modify(|_, w| w.cr_pds_ctrl_gpio_ie_pu_pd().set_bit());
+        });
+        Ok(())
+    }
+
+    /// Stop holding the level of `pin`
+    pub(crate) fn release_hold(pin: u8) -> Result<(), Error> {
+        let pds = unsafe { &*pac::PDS::ptr() };
+        let pd = hold_bit(pin)?;
+        riscv::interrupt::free(|| {
+            pds.pds_gpio_set_pu_pd.modify(|r, w| unsafe { w.bits(r.bits() & !(pd | (pd << 8))) });
+            if pds.pds_gpio_set_pu_pd.read().bits() == 0 {
+                pds.pds_ctl.modify(|_, w| w.cr_pds_ctrl_gpio_ie_pu_pd().clear_bit());
+            }
+        });
+        Ok(())
+    }
+
+    /// Set the output level of `pin`
+    ///
+    /// Like every read-modify-write of the shared output registers this runs in a critical
+    /// section, so it can't undo a `write_all` from an interrupt handler.
+    pub(crate) fn set_output(pin: u8, high: bool) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        riscv::interrupt::free(|| {
+            glb.gpio_cfgctl32.modify(|r, w| unsafe {
+                w.bits((r.bits() & !(1 << pin)) | ((high as u32) << pin))
+            });
+        });
+    }
+
+    /// Returns the level last written to the output latch of `pin`
+    pub(crate) fn output(pin: u8) -> bool {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        glb.gpio_cfgctl32.read().bits() & (1 << pin) != 0
+    }
+
+    /// Flip the output latch of `pin`
+    pub(crate) fn toggle_output(pin: u8) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        riscv::interrupt::free(|| {
+            glb.gpio_cfgctl32.modify(|r, w| unsafe { w.bits(r.bits() ^ (1 << pin)) });
+        });
+    }
+
+    /// Enable or disable the output driver of `pin`
+    pub(crate) fn set_output_enable(pin: u8, enable: bool) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        riscv::interrupt::free(|| {
+            glb.gpio_cfgctl34.modify(|r, w| unsafe {
+                w.bits((r.bits() & !(1 << pin)) | ((enable as u32) << pin))
+            });
+        });
+    }
+
+    /// Pins armed for `TriggerMode::BothEdges`
+    static BOTH_EDGES: AtomicU32 = AtomicU32::new(0);
+
+    /// GLB_GPIO_INT_TRIG_NEG_LEVEL
+    const TRIG_NEG_LEVEL: u32 = 2;
+    /// GLB_GPIO_INT_TRIG_POS_LEVEL
+    const TRIG_POS_LEVEL: u32 = 3;
+
+    /// Returns the input level of `pin`
+    pub(crate) fn input(pin: u8) -> bool {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        glb.gpio_cfgctl30.read().bits() & (1 << pin) != 0
+    }
+
+    /// Modify the 3 bit interrupt mode field of `pin`, 10 pins per gpio_int_mode_set register
+    fn modify_interrupt_mode(pin: u8, f: impl FnOnce(u32) -> u32) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        let reg = (&glb.gpio_int_mode_set1 as *const _ as *mut u32).wrapping_add(pin as usize / 10);
+        let shift = (pin as u32 % 10) * 3;
+        unsafe {
+            let value = reg.read_volatile();
+            let mode = f((value >> shift) & 0b111) & 0b111;
+            reg.write_volatile((value & !(0b111 << shift)) | (mode << shift));
+        }
+    }
+
+    /// Select the trigger of `pin`, keeping the sync/async control bit
+    fn set_trigger(pin: u8, trigger: u32) {
+        modify_interrupt_mode(pin, |mode| (mode & 0b100) | trigger);
+    }
+
+    /// Select asynchronous (`true`) or synchronous trigger sampling for `pin`
+    pub(crate) fn set_interrupt_async(pin: u8, asynchronous: bool) {
+        modify_interrupt_mode(pin, |mode| (mode & 0b011) | ((asynchronous as u32) << 2));
+    }
+
+    /// Arm the level trigger opposite to the current level of `pin`
+    fn rearm_both_edges(pin: u8) {
+        set_trigger(pin, if input(pin) { TRIG_NEG_LEVEL } else { TRIG_POS_LEVEL });
+    }
+
+    /// Mask (`true`) or unmask the interrupt of `pin`
+    pub(crate) fn set_interrupt_mask(pin: u8, masked: bool) {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        glb.gpio_int_mask1.modify(|r, w| unsafe {
+            w.bits((r.bits() & !(1 << pin)) | ((masked as u32) << pin))
+        });
+    }
+
+    /// Clear the pending interrupt flag of `pin`
+    pub(crate) fn clear_interrupt(pin: u8) {
+        if BOTH_EDGES.load(Ordering::Relaxed) & (1 << pin) != 0 {
+            rearm_both_edges(pin);
+        }
+        let glb = unsafe { &*pac::GLB::ptr() };
+        // the clear bit is level sensitive, release it again like the SDK does
+        glb.gpio_int_clr1.modify(|r, w| unsafe { w.bits(r.bits() | (1 << pin)) });
+        glb.gpio_int_clr1.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << pin)) });
+    }
+
+    /// Returns true if the interrupt of `pin` is pending
+    pub(crate) fn interrupt_pending(pin: u8) -> bool {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        glb.gpio_int_stat1.read().bits() & (1 << pin) != 0
+    }
+
+    /// Mask `pin`, program `trigger`, clear the pending flag and unmask again
+    pub(crate) fn enable_interrupt(pin: u8, trigger: TriggerMode) {
+        set_interrupt_mask(pin, true);
+        if trigger == TriggerMode::BothEdges {
+            BOTH_EDGES.fetch_or(1 << pin, Ordering::Relaxed);
+        } else {
+            BOTH_EDGES.fetch_and(!(1 << pin), Ordering::Relaxed);
+        }
+        match trigger {
+            TriggerMode::FallingEdge => set_trigger(pin, 0),
+            TriggerMode::RisingEdge => set_trigger(pin, 1),
+            TriggerMode::LowLevel => set_trigger(pin, TRIG_NEG_LEVEL),
+            TriggerMode::HighLevel => set_trigger(pin, TRIG_POS_LEVEL),
+            // armed by clear_interrupt below
+            TriggerMode::BothEdges => {}
+        }
+        clear_interrupt(pin);
+        set_interrupt_mask(pin, false);
+    }
+}
+
+/// GPIO9 to GPIO13 are on the always-on pads
+const fn is_aon(pin: u8) -> bool {
+    matches!(pin, 9..=13)
+}
+
+/// Pin with its number stored at runtime, so pins of the same mode share one type
+///
+/// Obtained with `downgrade` on any typed pin. Useful to keep several pins in an array:
+///
+/// ```rust
+///   let mut leds = [
+///       parts.pin17.into_pull_up_output().downgrade(),
+///       parts.pin18.into_pull_up_output().downgrade(),
+///   ];
+///   for led in leds.iter_mut() {
+///       led.set_high().unwrap();
+///   }
+/// ```
+pub struct ErasedPin<MODE> {
+    pin: u8,
+    _mode: PhantomData<MODE>,
+}
+
+impl<MODE> ErasedPin<MODE> {
+    /// Returns the GPIO number of this pin
+    pub fn pin_number(&self) -> u8 {
+        self.pin
+    }
+
+    /// Returns `true` if this pin is on an always-on pad, see [`AonPin`]
+    pub const fn is_aon(&self) -> bool {
+        is_aon(self.pin)
+    }
+}
+
+impl<MODE> embedded_hal::digital::ErrorType for ErasedPin<MODE> {
+    type Error = Error;
+}
+
+impl<MODE> embedded_hal::digital::InputPin for ErasedPin<Input<MODE>> {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(pad::input(self.pin))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!pad::input(self.pin))
+    }
+}
+
+impl<MODE> InterruptPin for ErasedPin<Input<MODE>> {
+    fn enable_interrupt(&mut self, trigger: TriggerMode) {
+        pad::enable_interrupt(self.pin, trigger);
+    }
+
+    fn disable_interrupt(&mut self) {
+        pad::set_interrupt_mask(self.pin, true);
+    }
+
+    fn clear_pending(&mut self) {
+        pad::clear_interrupt(self.pin);
+    }
+
+    fn is_pending(&self) -> bool {
+        pad::interrupt_pending(self.pin)
+    }
+
+    fn control_asynchronous(&mut self) {
+        pad::set_interrupt_async(self.pin, true);
+    }
+
+    fn control_synchronous(&mut self) {
+        pad::set_interrupt_async(self.pin, false);
+    }
+}
+
+impl<MODE> embedded_hal::digital::OutputPin for ErasedPin<Output<MODE>> {
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        pad::set_output(self.pin, true);
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        pad::set_output(self.pin, false);
+        Ok(())
+    }
+}
+
+impl<MODE> embedded_hal::digital::StatefulOutputPin for ErasedPin<Output<MODE>> {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(pad::output(self.pin))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!pad::output(self.pin))
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        pad::toggle_output(self.pin);
+        Ok(())
+    }
+}
+
+/// Pad configuration as found in the GLB registers, see [`configuration`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PadConfig {
+    /// Pad function select (`GPIO_FUN_*`)
+    pub function: u8,
+    /// Pull resistor, `Pull::Up` if both pull-up and pull-down are set
+    pub pull: Pull,
+    /// Output drive strength
+    pub drive: DriveStrength,
+    /// Schmitt trigger enabled
+    pub schmitt: bool,
+    /// Input buffer enabled
+    pub input_enable: bool,
+    /// Output driver enabled
+    pub output_enable: bool,
+    /// Level of the output latch
+    pub output_high: bool,
+    /// Level seen by the input buffer
+    pub input_high: bool,
+}
+
+impl PadConfig {
+    /// Decode the 16 bit `gpio_cfgctl` half of a pad plus its output enable, output and input bits
+    pub fn decode(cfgctl: u16, output_enable: bool, output_high: bool, input_high: bool) -> Self {
+        let bit = |n: u16| cfgctl & (1 << n) != 0;
+        PadConfig {
+            function: ((cfgctl >> 8) & 0x1f) as u8,
+            pull: if bit(4) {
+                Pull::Up
+            } else if bit(5) {
+                Pull::Down
+            } else {
+                Pull::None
+            },
+            drive: match (cfgctl >> 2) & 0b11 {
+                0 => DriveStrength::Level0,
+                1 => DriveStrength::Level1,
+                2 => DriveStrength::Level2,
+                _ => DriveStrength::Level3,
+            },
+            schmitt: bit(1),
+            input_enable: bit(0),
+            output_enable,
+            output_high,
+            input_high,
+        }
+    }
+
+    /// Name of the pad function, `"?"` for functions the HAL does not use
+    pub fn function_name(&self) -> &'static str {
+        match self.function {
+            0 => "clk_out",
+            4 => "spi",
+            6 => "i2c",
+            7 => "uart",
+            8 => "pwm",
+            10 => "analog",
+            11 => "gpio",
+            14 => "jtag",
+            18 => "qdec",
+            _ => "?",
+        }
+    }
+}
+
+/// Read the configuration of pad `pin`
+pub fn configuration(pin: u8) -> PadConfig {
+    PadConfig::decode(
+        pad::cfgctl(pin),
+        pad::output_enabled(pin),
+        pad::output(pin),
+        pad::input(pin),
+    )
+}
+
+/// Print a table with the configuration of all 32 pads to `writer`
+pub fn dump_all(writer: &mut impl core::fmt::Write) -> core::fmt::Result {
+    writeln!(writer, "pin func        pull  drv smt ie oe out in")?;
+    for pin in 0..32 {
+        let cfg = configuration(pin);
+        let pull = match cfg.pull {
+            Pull::None => "none",
+            Pull::Up => "up",
+            Pull::Down => "down",
+        };
+        writeln!(
+            writer,
+            "{:>3} {:>2} {:<8} {:<5} {:>3} {:>3} {:>2} {:>2} {:>3} {:>2}",
+            pin,
+            cfg.function,
+            cfg.function_name(),
+            pull,
+            cfg.drive as u8,
+            cfg.schmitt as u8,
+            cfg.input_enable as u8,
+            cfg.output_enable as u8,
+            cfg.output_high as u8,
+            cfg.input_high as u8,
+        )?;
+    }
+    Ok(())
+}
+
+/// Up to 8 output pins that are updated together with a single write of the output register
+///
+/// Bit `i` of the values passed to `write`, `set_bits` and `clear_bits` drives `pins[i]`.
+/// The GLB has no set/clear registers, so every update is one read-modify-write of
+/// `gpio_cfgctl32` inside a critical section, changing all pins on the same clock edge.
+///
+/// ```rust
+///   let mut bus = PortGroup::new([
+///       parts.pin0.into_floating_output().downgrade(),
+///       parts.pin1.into_floating_output().downgrade(),
+///       // ...
+///   ]);
+///   bus.write(0xa5);
+/// ```
+pub struct PortGroup<MODE, const N: usize> {
+    pins: [ErasedPin<Output<MODE>>; N],
+    mask: u32,
+}
+
+impl<MODE, const N: usize> PortGroup<MODE, N> {
+    const FITS_IN_U8: () = assert!(N <= 8, "a PortGroup holds at most 8 pins");
+
+    /// Group `pins`, which all have to be outputs
+    pub fn new(pins: [ErasedPin<Output<MODE>>; N]) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::FITS_IN_U8;
+        let mask = pins.iter().fold(0, |mask, pin| mask | (1 << pin.pin));
+        PortGroup { pins, mask }
+    }
+
+    /// Release the pins
+    pub fn free(self) -> [ErasedPin<Output<MODE>>; N] {
+        self.pins
+    }
+
+    /// Spread the bits of `value` over the GPIO numbers of the pins
+    fn to_gpio_bits(&self, value: u8) -> u32 {
+        self.pins
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| value & (1 << i) != 0)
+            .fold(0, |bits, (_, pin)| bits | (1 << pin.pin))
+    }
+
+    /// Drive all pins to the matching bit of `value`
+    pub fn write(&mut self, value: u8) {
+        let bits = self.to_gpio_bits(value);
+        write_all(self.mask, bits);
+    }
+
+    /// Drive the pins selected by `mask` high, leaving the others unchanged
+    pub fn set_bits(&mut self, mask: u8) {
+        let bits = self.to_gpio_bits(mask);
+        write_all(bits, bits);
+    }
+
+    /// Drive the pins selected by `mask` low, leaving the others unchanged
+    pub fn clear_bits(&mut self, mask: u8) {
+        let bits = self.to_gpio_bits(mask);
+        write_all(bits, 0);
+    }
+
+    /// Returns the levels last written to the pins
+    pub fn read_output(&self) -> u8 {
+        let glb = unsafe { &*pac::GLB::ptr() };
+        let out = glb.gpio_cfgctl32.read().bits();
+        self.pins
+            .iter()
+            .enumerate()
+            .filter(|(_, pin)| out & (1 << pin.pin) != 0)
+            .fold(0, |value, (i, _)| value | (1 << i))
+    }
+
+}
+
+/// Pull resistor selection of a [`FlexPin`] or an `Input<Dynamic>` pin
+///
+/// The pull lives in the GLB pad configuration, which is not retained in HBN. While the chip
+/// hibernates the pads are controlled by the always-on domain instead, so a pull changed here
+/// does not affect a pad armed with [`crate::hbn::enable_gpio_wakeup`] until after wakeup,
+/// and after an HBN wakeup the pads are back at their reset configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pull {
+    /// Hi-Z floating
+    None,
+    /// Pulled up
+    Up,
+    /// Pulled down
+    Down,
+}
+
+/// Pin that switches between input and output at runtime, for bit-banged protocols
+///
+/// Obtained with `into_flex` on any typed pin, which leaves it as a floating input.
+///
+/// Switching direction is a single read-modify-write of `gpio_cfgctl34` (one load and one store
+/// on the GLB bus). `set_as_input` only touches the pad configuration register as well when the
+/// pull changes, so repeated switches with the same pull stay at one register modify. Reading
+/// and writing the level is one bus access each, which is small next to the microsecond delays
+/// a `McycleDelay` typically provides for 1-Wire or DHT22 timing.
+///
+/// ```rust
+///   let mut line = parts.pin10.into_flex();
+///   line.set_low().unwrap();
+///   line.set_as_output();
+///   delay.delay_us(480);
+///   line.set_as_input(Pull::Up);
+///   let present = line.is_low().unwrap();
+/// ```
+pub struct FlexPin {
+    pin: u8,
+    pull: Pull,
+}
+
+impl FlexPin {
+    /// Returns the GPIO number of this pin
+    pub fn pin_number(&self) -> u8 {
+        self.pin
+    }
+
+    /// Returns `true` if this pin is on an always-on pad, see [`AonPin`]
+    pub const fn is_aon(&self) -> bool {
+        is_aon(self.pin)
+    }
+
+    /// Disable the output driver and select the `pull` resistor
+    pub fn set_as_input(&mut self, pull: Pull) {
+        pad::set_output_enable(self.pin, false);
+        self.set_pull(pull);
+    }
+
+    /// Select the `pull` resistor without changing the direction
+    ///
+    /// See [`Pull`] for how the setting behaves in HBN.
+    pub fn set_pull(&mut self, pull: Pull) {
+        if pull != self.pull {
+            pad::set_pull(self.pin, pull);
+            self.pull = pull;
+        }
+    }
+
+    /// Enable the output driver, driving the level last set with `set_high`/`set_low`
+    pub fn set_as_output(&mut self) {
+        pad::set_output_enable(self.pin, true);
+    }
+}
+
+impl embedded_hal::digital::ErrorType for FlexPin {
+    type Error = Error;
+}
+
+impl embedded_hal::digital::InputPin for FlexPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(pad::input(self.pin))
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!pad::input(self.pin))
+    }
+}
+
+impl embedded_hal::digital::OutputPin for FlexPin {
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        pad::set_output(self.pin, true);
+        Ok(())
+    }
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        pad::set_output(self.pin, false);
+        Ok(())
+    }
+}
+
+impl embedded_hal::digital::StatefulOutputPin for FlexPin {
+    fn is_set_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(pad::output(self.pin))
+    }
+
+    fn is_set_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(!pad::output(self.pin))
+    }
+
+    fn toggle(&mut self) -> Result<(), Self::Error> {
+        pad::toggle_output(self.pin);
+        Ok(())
+    }
+}
+
+/// Edge counts of the pins in [`PulseCounter`]s, wrapping on overflow
+static EDGE_COUNTS: [core::sync::atomic::AtomicU32; 32] =
+    [const { core::sync::atomic::AtomicU32::new(0) }; 32];
+
+/// Pins counted in the background by [`irq::on_interrupt`]
+static COUNTING_PINS: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// Edges counted by a [`PulseCounter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// Count low to high transitions
+    Rising,
+    /// Count high to low transitions
+    Falling,
+    /// Count all transitions
+    Both,
+}
+
+impl Edge {
+    fn matches(self, high: bool) -> bool {
+        match self {
+            Edge::Rising => high,
+            Edge::Falling => !high,
+            Edge::Both => true,
+        }
+    }
+}
+
+/// Edge counter on an input pin, e.g. for the pulses of a flow meter
+///
+/// Count by polling for a fixed time with [`count_edges_for`](Self::count_edges_for), or in the
+/// background from the GPIO interrupt with [`start`](Self::start). The background count is
+/// kept by [`irq::on_interrupt`], so route `GPIO_INT0` there:
+///
+/// ```rust
+///   let mut flow = PulseCounter::new(parts.pin10.into_pull_up_input().downgrade(), Edge::Falling);
+///   flow.start();
+///   unsafe { gpio::unmask_irq() };
+///   // ...
+///   let pulses = flow.count();
+///   flow.reset();
+/// ```
+///
+/// The count is a `u32` that wraps around on overflow, compute rates with `wrapping_sub` on two
+/// readings. The schmitt trigger of the pin is enabled to suppress chatter on slow edges.
+pub struct PulseCounter<MODE> {
+    pin: ErasedPin<Input<MODE>>,
+    edge: Edge,
+}
+
+impl<MODE> PulseCounter<MODE> {
+    /// Count `edge` on `pin`, starting from 0
+    pub fn new(pin: ErasedPin<Input<MODE>>, edge: Edge) -> Self {
+        pad::set_schmitt(pin.pin, true);
+        EDGE_COUNTS[pin.pin as usize].store(0, core::sync::atomic::Ordering::Relaxed);
+        PulseCounter { pin, edge }
+    }
+
+    /// Poll the pin for `duration` and return the number of edges seen in that time
+    ///
+    /// The edges are also added to [`count`](Self::count). Pulses shorter than one loop
+    /// iteration, a few dozen core cycles, are missed.
+    pub fn count_edges_for(&mut self, delay: &McycleDelay, duration: Microseconds<u32>) -> u32 {
+        let cycles = duration.0 as u64 * delay.core_frequency() as u64 / 1_000_000;
+        let start = McycleDelay::get_cycle_count();
+        let mut level = pad::input(self.pin.pin);
+        let mut edges: u32 = 0;
+        while McycleDelay::cycles_since(start) < cycles {
+            let now = pad::input(self.pin.pin);
+            if now != level && self.edge.matches(now) {
+                edges = edges.wrapping_add(1);
+            }
+            level = now;
+        }
+        EDGE_COUNTS[self.pin.pin as usize].fetch_add(edges, core::sync::atomic::Ordering::Relaxed);
+        edges
+    }
+
+    /// Count edges in the background from the GPIO interrupt
+    ///
+    /// Call [`irq::on_interrupt`] from the `GPIO_INT0` handler and enable it with [`unmask_irq`].
+    pub fn start(&mut self) {
+        let trigger = match self.edge {
+            Edge::Rising => TriggerMode::RisingEdge,
+            Edge::Falling => TriggerMode::FallingEdge,
+            Edge::Both => TriggerMode::BothEdges,
+        };
+        riscv::interrupt::free(|| {
+            COUNTING_PINS.fetch_or(1 << self.pin.pin, core::sync::atomic::Ordering::Relaxed);
+            pad::enable_interrupt(self.pin.pin, trigger);
+        });
+    }
+
+    /// Stop counting in the background, the count is kept
+    pub fn stop(&mut self) {
+        riscv::interrupt::free(|| {
+            pad::set_interrupt_mask(self.pin.pin, true);
+            COUNTING_PINS.fetch_and(!(1 << self.pin.pin), core::sync::atomic::Ordering::Relaxed);
+        });
+    }
+
+    /// Returns the number of edges counted since creation or the last [`reset`](Self::reset)
+    pub fn count(&self) -> u32 {
+        EDGE_COUNTS[self.pin.pin as usize].load(core::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Set the count back to 0
+    pub fn reset(&mut self) {
+        EDGE_COUNTS[self.pin.pin as usize].store(0, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Stop counting and return the pin
+    pub fn free(mut self) -> ErasedPin<Input<MODE>> {
+        self.stop();
+        self.pin
+    }
+}
+
+/// Cell to hand a pin, or any other value, to an interrupt handler
+///
+/// Put it in a `static`, [`lend`](Self::lend) the value to it once set up and access it from
+/// the handler with [`borrow_in_isr`](Self::borrow_in_isr):
+///
+/// ```rust
+///   static LED: IsrCell<Pin17<Output<PullUp>>> = IsrCell::new();
+///
+///   fn button_pressed() {
+///       LED.borrow_in_isr(|led| led.toggle().unwrap());
+///   }
+///
+///   LED.lend(parts.pin17.into_pull_up_output());
+///   gpio::irq::register_handler(10, button_pressed);
+/// ```
+///
+/// Every access runs with interrupts disabled, so main code can use the same methods. Pin
+/// types only carry their pin number and mode, so they are all `Send` and can be lent.
+pub struct IsrCell<T> {
+    inner: core::cell::RefCell<Option<T>>,
+}
+
+// all accesses run inside a critical section on the single hart
+unsafe impl<T: Send> Sync for IsrCell<T> {}
+
+/// Critical section of the [`IsrCell`] accesses, the host tests have no interrupts to mask
+fn isr_free<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(not(test))]
+    return riscv::interrupt::free(f);
+    #[cfg(test)]
+    f()
+}
+
+impl<T> IsrCell<T> {
+    /// Create an empty cell
+    pub const fn new() -> Self {
+        IsrCell { inner: core::cell::RefCell::new(None) }
+    }
+
+    /// Store `value` in the cell, returning the value lent before if any
+    pub fn lend(&self, value: T) -> Option<T> {
+        isr_free(|| self.inner.borrow_mut().replace(value))
+    }
 
-    impl_uart_sig!(
-        UartSig7,
-        "UART signal 7 (type state)",
-        sig_7,
-        UartMux7,
-        "UART multiplexer peripherals for signal 7"
-    );
+    /// Take the value back out of the cell
+    pub fn take(&self) -> Option<T> {
+        isr_free(|| self.inner.borrow_mut().take())
+    }
+
+    /// Run `f` on the lent value with interrupts disabled
+    ///
+    /// Returns `None` without calling `f` if nothing was lent yet. Panics if called again
+    /// from within `f` on the same cell.
+    pub fn borrow_in_isr<R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        isr_free(|| self.inner.borrow_mut().as_mut().map(f))
+    }
 }
 
-/// Clock configurator registers
-pub struct ClkCfg {
-    pub(crate) _ownership: (),
+impl<T> Default for IsrCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-/*
-// todo: english
-    在GPIO模式下，可以设置内部上下拉，以类型状态机模式设计
-    SPI、UART、I2C等数字功能下，可以设置内部上下拉，但不会影响返回类型的状态
-    ADC、DAC下，软件禁止设置内部上下拉。HAL库不会生成此类函数，以免出错。
-*/
+// Pins are lent to interrupt handlers, keep them `Send`
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<Pin0<Input<Floating>>>();
+    assert_send::<Pin31<Output<PullUp>>>();
+    assert_send::<ErasedPin<Input<PullDown>>>();
+    assert_send::<FlexPin>();
+    assert_send::<PortGroup<Floating, 8>>();
+    assert_send::<PulseCounter<PullUp>>();
+    assert_send::<Jtag>();
+};
+
+/// Per-pin GPIO interrupt callbacks
+///
+/// Register a callback per pin and call [`irq::on_interrupt`] from the `GPIO_INT0` handler:
+///
+/// ```rust
+///   fn button_pressed() {
+///       // ...
+///   }
+///
+///   gpio::irq::register_handler(10, button_pressed);
+///   button.enable_interrupt(TriggerMode::FallingEdge);
+///   unsafe { gpio::unmask_irq() };
+/// ```
+///
+/// With the `gpio_irq_handler` feature the HAL defines `DefaultHandler` itself and dispatches
+/// `GPIO_INT0` there. Leave the feature off to write your own `DefaultHandler`, for example
+/// when other interrupts are used as well.
+pub mod irq {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{pad, pending_pins, COUNTING_PINS, EDGE_COUNTS};
+
+    /// Registered callbacks as `fn()` pointers, 0 when unset
+    static HANDLERS: [AtomicUsize; 32] = [const { AtomicUsize::new(0) }; 32];
+
+    /// Call `f` from [`on_interrupt`] whenever the interrupt of `pin` is pending
+    pub fn register_handler(pin: u8, f: fn()) {
+        HANDLERS[pin as usize].store(f as usize, Ordering::Release);
+    }
 
-/// Hi-Z Floating pin (type state)
-pub struct Floating;
-/// Pulled down pin (type state)
-pub struct PullDown;
-/// Pulled up pin (type state)
-pub struct PullUp;
+    /// Remove the callback of `pin`
+    pub fn unregister_handler(pin: u8) {
+        HANDLERS[pin as usize].store(0, Ordering::Release);
+    }
 
-/// Input mode (type state)
-pub struct Input<MODE> {
-    _mode: PhantomData<MODE>,
+    /// Dispatch the pending pins to their callbacks
+    ///
+    /// Pins of a running [`PulseCounter`](super::PulseCounter) are counted instead.
+    ///
+    /// Each pin is cleared right before its callback runs, so a new trigger during the
+    /// callback stays pending and is handled on the next interrupt. Pending pins without a
+    /// callback are cleared and masked, otherwise `GPIO_INT0` would fire again right away and
+    /// never let the core return; enable their interrupt again after registering a callback.
+    pub fn on_interrupt() {
+        let pending = pending_pins();
+        let counting = pending & COUNTING_PINS.load(Ordering::Relaxed);
+        for pin in (0..32).filter(|pin| counting & (1 << pin) != 0) {
+            pad::clear_interrupt(pin);
+            EDGE_COUNTS[pin as usize].fetch_add(1, Ordering::Relaxed);
+        }
+        // pins of pending `Wait` futures belong to the async handler
+        #[cfg(feature = "async")]
+        let pending = pending & !super::WAITING_PINS.load(Ordering::Relaxed);
+        for pin in (0..32).filter(|pin| pending & !counting & (1 << pin) != 0) {
+            let raw = HANDLERS[pin as usize].load(Ordering::Acquire);
+            if raw != 0 {
+                pad::clear_interrupt(pin);
+                // only ever stored from a `fn()` in register_handler
+                let f = unsafe { core::mem::transmute::<usize, fn()>(raw) };
+                f();
+            } else {
+                pad::set_interrupt_mask(pin, true);
+                pad::clear_interrupt(pin);
+            }
+        }
+    }
+
+    #[cfg(feature = "gpio_irq_handler")]
+    #[export_name = "DefaultHandler"]
+    fn default_handler() {
+        if crate::interrupt::current() == Some(crate::interrupt::Interrupt::GPIO_INT0) {
+            #[cfg(feature = "async")]
+            super::on_interrupt();
+            on_interrupt();
+        }
+    }
 }
 
-/// Output mode (type state)
-pub struct Output<MODE> {
-    _mode: PhantomData<MODE>,
+#[cfg(feature = "async")]
+static PIN_WAKERS: [crate::interrupt::WakerSlot; 32] =
+    [const { crate::interrupt::WakerSlot::new() }; 32];
+
+/// Pins with a pending `Wait` future, cleared by the interrupt handler once the trigger fired
+#[cfg(feature = "async")]
+static WAITING_PINS: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// Interrupt handler for the async GPIO driver.
+///
+/// Call this from the handler of [`Interrupt::GPIO_INT0`](crate::interrupt::Interrupt::GPIO_INT0).
+/// It masks and clears the pins a pending future was waiting on and wakes those futures.
+#[cfg(feature = "async")]
+pub fn on_interrupt() {
+    use core::sync::atomic::Ordering;
+
+    let pending = pending_pins() & WAITING_PINS.load(Ordering::Relaxed);
+    for pin in (0..32).filter(|pin| pending & (1 << pin) != 0) {
+        pad::set_interrupt_mask(pin, true);
+        pad::clear_interrupt(pin);
+        WAITING_PINS.fetch_and(!(1 << pin), Ordering::Relaxed);
+        PIN_WAKERS[pin as usize].wake();
+    }
 }
 
-/// UART pin mode (type state)
-pub struct Uart;
+/// Wait until `trigger` fires on `pin`
+#[cfg(feature = "async")]
+async fn wait_for_trigger(pin: u8, trigger: TriggerMode) {
+    use core::sync::atomic::Ordering;
+    use core::task::Poll;
 
-/// SPI pin mode (type state)
-pub struct Spi;
+    /// Masks the pin again if the future is dropped before the trigger fired
+    struct Disarm(u8);
 
-/// I2C pin mode (type state)
-pub struct I2c;
+    impl Drop for Disarm {
+        fn drop(&mut self) {
+            pad::set_interrupt_mask(self.0, true);
+            WAITING_PINS.fetch_and(!(1 << self.0), Ordering::Relaxed);
+        }
+    }
 
-#[doc(hidden)]
-pub trait UartPin<SIG> {}
+    let level = match trigger {
+        TriggerMode::HighLevel => Some(true),
+        TriggerMode::LowLevel => Some(false),
+        _ => None,
+    };
+    if level == Some(pad::input(pin)) {
+        return;
+    }
+
+    let bit = 1 << pin;
+    let _disarm = Disarm(pin);
+    let mut armed = false;
+    core::future::poll_fn(|cx| {
+        PIN_WAKERS[pin as usize].register(cx.waker());
+        if !armed {
+            armed = true;
+            // an edge right after unmasking must find the pin in WAITING_PINS, otherwise the
+            // handler leaves it pending and the interrupt keeps firing
+            riscv::interrupt::free(|| {
+                pad::enable_interrupt(pin, trigger);
+                WAITING_PINS.fetch_or(bit, Ordering::Relaxed);
+            });
+        }
+        if WAITING_PINS.load(Ordering::Relaxed) & bit == 0 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Implement `embedded_hal_async::digital::Wait` for a pin type with a runtime pin number
+#[cfg(feature = "async")]
+macro_rules! impl_wait {
+    ($pin: expr) => {
+        async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+            wait_for_trigger($pin(self), TriggerMode::HighLevel).await;
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+            wait_for_trigger($pin(self), TriggerMode::LowLevel).await;
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+            wait_for_trigger($pin(self), TriggerMode::RisingEdge).await;
+            Ok(())
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+            wait_for_trigger($pin(self), TriggerMode::FallingEdge).await;
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+            wait_for_trigger($pin(self), TriggerMode::BothEdges).await;
+            Ok(())
+        }
+    };
+}
+
+/// Async input, the GPIO interrupt must be enabled and routed to [`on_interrupt`]
+#[cfg(feature = "async")]
+impl<MODE> embedded_hal_async::digital::Wait for ErasedPin<Input<MODE>> {
+    impl_wait!(|pin: &Self| pin.pin);
+}
+
+/// Async input, the GPIO interrupt must be enabled and routed to [`on_interrupt`]
+#[cfg(feature = "async")]
+impl embedded_hal_async::digital::Wait for FlexPin {
+    impl_wait!(|pin: &Self| pin.pin);
+}
 
 // There are Pin0 to Pin22, totally 23 pins
 
 pub use self::pin::*;
 
 macro_rules! impl_glb {
-    ($($Pini: ident: ($pini: ident, $gpio_cfgctli: ident, $UartSigi: ident, $sigi: ident, $spi_kind: ident, $i2c_kind: ident, $gpio_i: ident, $gpio_int_mode_seti: ident, $pin_id: literal) ,)+) => {
+    ($($Pini: ident: ($pini: ident, $gpio_cfgctli: ident, $UartSigi: ident, $UartMuxi: ident, $sigi: ident, $PwmChi: ident, $spi_kind: ident, $i2c_kind: ident, $gpio_i: ident, $pin_id: literal) ,)+) => {
         impl GlbExt for pac::GLB {
             fn split(self) -> Parts {
                 Parts {
@@ -274,6 +1782,7 @@ macro_rules! impl_glb {
                     uart_mux6: UartMux6 { _mode: PhantomData },
                     uart_mux7: UartMux7 { _mode: PhantomData },
                     clk_cfg: ClkCfg { _ownership: () },
+                    jtag: Jtag { released: 0, cci: false },
                 }
             }
         }
@@ -290,12 +1799,13 @@ macro_rules! impl_glb {
             pub uart_mux6: UartMux6<Uart0Cts>,
             pub uart_mux7: UartMux7<Uart0Cts>,
             pub clk_cfg: ClkCfg,
+            pub jtag: Jtag,
         }
 
         /// GPIO pins
         pub mod pin {
             use core::marker::PhantomData;
-            use embedded_hal::digital::{InputPin, OutputPin, StatefulOutputPin};
+            use embedded_hal::digital::{InputPin, OutputPin, PinState, StatefulOutputPin};
 
             use crate::pac;
             use super::*;
@@ -310,6 +1820,7 @@ macro_rules! impl_glb {
             trait InternalOutputPinImp {
                 fn set_high_inner(&self);
                 fn set_low_inner(&self);
+                fn toggle_inner(&self);
             }
 
             /// Simple implementation of StatefulOutputPin trait to use within EH0 and EH1 impls without name conflicts
@@ -331,20 +1842,51 @@ macro_rules! impl_glb {
             impl<MODE> $Pini<MODE> {
                 // 11 -> GPIO_FUN_SWGPIO
                 /// Configures the pin to operate as a Hi-Z floating output pin.
+                ///
+                /// The pin drives whatever is in its output latch, which is low after reset
+                /// and otherwise the last level written. Use `into_floating_output_with_state`
+                /// to select the level.
                 pub fn into_floating_output(self) -> $Pini<Output<Floating>> {
                     self.into_pin_with_mode(11, false, false, false)
                 }
 
                 /// Configures the pin to operate as a pull-up output pin.
+                ///
+                /// See `into_floating_output` for the initial level.
                 pub fn into_pull_up_output(self) -> $Pini<Output<PullUp>> {
                     self.into_pin_with_mode(11, true, false, false)
                 }
 
                 /// Configures the pin to operate as a pull-down output pin.
+                ///
+                /// See `into_floating_output` for the initial level.
                 pub fn into_pull_down_output(self) -> $Pini<Output<PullDown>> {
                     self.into_pin_with_mode(11, false, true, false)
                 }
 
+                /// Configures the pin to operate as a Hi-Z floating output pin driving `state`.
+                ///
+                /// The output latch is written before the output driver is enabled, so the pin
+                /// never drives the previous level.
+                pub fn into_floating_output_with_state(self, state: PinState) -> $Pini<Output<Floating>> {
+                    pad::set_output($pin_id, state == PinState::High);
+                    self.into_pin_with_mode(11, false, false, false)
+                }
+
+                /// Configures the pin to operate as a pull-up output pin driving `state`, see
+                /// `into_floating_output_with_state`.
+                pub fn into_pull_up_output_with_state(self, state: PinState) -> $Pini<Output<PullUp>> {
+                    pad::set_output($pin_id, state == PinState::High);
+                    self.into_pin_with_mode(11, true, false, false)
+                }
+
+                /// Configures the pin to operate as a pull-down output pin driving `state`, see
+                /// `into_floating_output_with_state`.
+                pub fn into_pull_down_output_with_state(self, state: PinState) -> $Pini<Output<PullDown>> {
+                    pad::set_output($pin_id, state == PinState::High);
+                    self.into_pin_with_mode(11, false, true, false)
+                }
+
                 /// Configures the pin to operate as a Hi-Z floating input pin.
                 pub fn into_floating_input(self) -> $Pini<Input<Floating>> {
                     self.into_pin_with_mode(11, false, false, true)
@@ -360,42 +1902,95 @@ macro_rules! impl_glb {
                     self.into_pin_with_mode(11, false, true, true)
                 }
 
+                /// Configures the pin to operate as an input pin whose pull can be changed with `set_pull`.
+                pub fn into_dynamic_input(self, pull: Pull) -> $Pini<Input<Dynamic>> {
+                    self.into_pin_with_mode(11, pull == Pull::Up, pull == Pull::Down, true)
+                }
+
                 paste::paste! {
                     #[inline]
                     fn into_pin_with_mode<T>(self, mode: u8, pu: bool, pd: bool, ie: bool) -> $Pini<T> {
-                        let glb = unsafe { &*pac::GLB::ptr() };
-
-                        glb.$gpio_cfgctli.modify(|_r, w| unsafe { w
-                            .[<reg_ $gpio_i _func_sel>]().bits(mode)
-                            .[<reg_ $gpio_i _pd>]().bit(pd)
-                            .[<reg_ $gpio_i _pu>]().bit(pu)
-                            .[<reg_ $gpio_i _drv>]().bits(0) // lowest drive level
-                            .[<reg_ $gpio_i _smt>]().set_bit()
-                            .[<reg_ $gpio_i _ie>]().set_bit() // keep input buffer active, github issue #8
-                        });
+                        pad::set_mode($pin_id, mode, pu, pd);
 
                         // If we're an input clear the Output Enable bit as well, else set it.
-                        glb.gpio_cfgctl34.modify(|_, w| w.[<reg_ $gpio_i _oe>]().bit(!ie));
+                        pad::set_output_enable($pin_id, !ie);
 
                         $Pini { _mode: PhantomData }
                     }
                 }
             }
 
-            impl<MODE> $Pini<Input<MODE>> {
-                paste::paste! {
-                    /// Enable smitter GPIO input filter
-                    pub fn enable_smitter(&mut self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
+            impl $Pini<Input<Dynamic>> {
+                /// Select the `pull` resistor, only rewriting the pull bits of the pad
+                ///
+                /// See [`Pull`] for how the setting behaves in HBN.
+                pub fn set_pull(&mut self, pull: Pull) {
+                    pad::set_pull($pin_id, pull);
+                }
+            }
 
-                        glb.$gpio_cfgctli.modify(|_, w| w.[<reg_ $gpio_i _smt>]().set_bit());
-                    }
+            impl<MODE> $Pini<Output<MODE>> {
+                /// Keep the current output level with the PDS pull resistors while the GLB pad
+                /// configuration is lost, see [`release_all_holds`]
+                ///
+                /// Returns [`Error::HoldNotSupported`] for pads other than GPIO17 to GPIO28.
+                pub fn hold(&mut self) -> Result<(), Error> {
+                    pad::hold($pin_id)
+                }
 
-                    /// Enable smitter GPIO output filter
-                    pub fn disable_smitter(&mut self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
+                /// Stop holding the level of this pad
+                pub fn release_hold(&mut self) -> Result<(), Error> {
+                    pad::release_hold($pin_id)
+                }
+            }
+
+            impl<MODE> $Pini<MODE> {
+                /// Read the current configuration of this pad from the GLB registers
+                pub fn configuration(&self) -> PadConfig {
+                    configuration($pin_id)
+                }
+
+                /// Returns `true` if this pin is on an always-on pad, see [`AonPin`]
+                pub const fn is_aon(&self) -> bool {
+                    is_aon($pin_id)
+                }
+
+                /// Returns the level of this pin in a snapshot taken with [`read_all`]
+                pub fn is_high_in(&self, snapshot: u32) -> bool {
+                    snapshot & (1 << $pin_id) != 0
+                }
+
+                /// Erase the pin number from the type, see [`ErasedPin`]
+                pub fn downgrade(self) -> ErasedPin<MODE> {
+                    ErasedPin { pin: $pin_id, _mode: PhantomData }
+                }
+
+                /// Configures the pin as a floating input that can switch direction at runtime, see [`FlexPin`]
+                pub fn into_flex(self) -> FlexPin {
+                    let _: $Pini<Input<Floating>> = self.into_pin_with_mode(11, false, false, true);
+                    FlexPin { pin: $pin_id, pull: Pull::None }
+                }
+            }
+
+            impl<MODE> ClockOutPin for $Pini<MODE> {
+                type ClockOut = $Pini<ClockOut>;
+                const PIN: u8 = $pin_id;
+
+                fn into_clock_out_pad(self, select: ClockOutSelect) -> $Pini<ClockOut> {
+                    self.into_clock_output(select)
+                }
+            }
+
+            impl<MODE> $Pini<MODE> {
+                paste::paste! {
+                    /// Set the output drive strength of the pad, keeping the rest of its configuration
+                    pub fn set_drive_strength(&mut self, strength: DriveStrength) {
+                        pad::set_drive($pin_id, strength);
+                    }
 
-                        glb.$gpio_cfgctli.modify(|_, w| w.[<reg_ $gpio_i _smt>]().clear_bit());
+                    /// Enable or disable the schmitt trigger on the pad input, keeping the rest of its configuration
+                    pub fn enable_schmitt(&mut self, enable: bool) {
+                        pad::set_schmitt($pin_id, enable);
                     }
                 }
             }
@@ -408,12 +2003,65 @@ macro_rules! impl_glb {
                         self.into_pin_with_mode(7, true, false, true)
                     }
 
+                    /// Configures the pin as UART0 TXD, routing its UART signal through `mux`
+                    pub fn into_uart0_tx<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart0Tx>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart0_tx())
+                    }
+
+                    /// Configures the pin as UART0 RXD, routing its UART signal through `mux`
+                    pub fn into_uart0_rx<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart0Rx>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart0_rx())
+                    }
+
+                    /// Configures the pin as UART0 RTS, routing its UART signal through `mux`
+                    pub fn into_uart0_rts<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart0Rts>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart0_rts())
+                    }
+
+                    /// Configures the pin as UART0 CTS, routing its UART signal through `mux`
+                    pub fn into_uart0_cts<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart0Cts>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart0_cts())
+                    }
+
+                    /// Configures the pin as UART1 TXD, routing its UART signal through `mux`
+                    pub fn into_uart1_tx<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart1Tx>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart1_tx())
+                    }
+
+                    /// Configures the pin as UART1 RXD, routing its UART signal through `mux`
+                    pub fn into_uart1_rx<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart1Rx>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart1_rx())
+                    }
+
+                    /// Configures the pin as UART1 RTS, routing its UART signal through `mux`
+                    pub fn into_uart1_rts<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart1Rts>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart1_rts())
+                    }
+
+                    /// Configures the pin as UART1 CTS, routing its UART signal through `mux`
+                    pub fn into_uart1_cts<M>(self, mux: $UartMuxi<M>) -> ($Pini<Uart>, $UartMuxi<Uart1Cts>) {
+                        (self.[<into_uart_ $sigi>](), mux.into_uart1_cts())
+                    }
+
                     /// Configures the pin to SPI alternate mode
                     pub fn [<into_spi_ $spi_kind>](self) -> $Pini<Spi> {
                         // 4 -> GPIO0_FUN_SPI_x
                         self.into_pin_with_mode(4, true, false, true)
                     }
 
+                    /// Configures the pin to output the clock selected by `select`, see [`ClockOutSelect`]
+                    pub fn into_clock_output(self, select: ClockOutSelect) -> $Pini<ClockOut> {
+                        pad::set_clock_output($pin_id, select);
+                        // 0 -> GPIO_FUN_CLK_OUT
+                        self.into_pin_with_mode(0, false, false, false)
+                    }
+
+                    /// Configures the pin as output of its PWM channel, see the pin to channel table in the module docs
+                    pub fn into_pwm(self) -> $Pini<Pwm> {
+                        // 8 -> GPIO_FUN_PWM
+                        self.into_pin_with_mode(8, false, false, false)
+                    }
+
                     /// Configures the pin to I2C alternate mode
                     pub fn [<into_i2c_ $i2c_kind>](self) -> $Pini<I2c> {
                         // 6 -> GPIO_FUN_I2C_x
@@ -424,6 +2072,8 @@ macro_rules! impl_glb {
 
             impl UartPin<$UartSigi> for $Pini<Uart> {}
 
+            impl PwmPin<$PwmChi> for $Pini<Pwm> {}
+
             impl<MODE> InternalInputPinImpl for $Pini<Input<MODE>> {
                 paste::paste! {
                     fn is_high_inner(&self) -> bool {
@@ -442,16 +2092,18 @@ macro_rules! impl_glb {
             impl<MODE> InternalOutputPinImp for $Pini<Output<MODE>> {
                 paste::paste! {
                     fn set_high_inner(&self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
-                        glb.gpio_cfgctl32.modify(|_, w| w.[<reg_ $gpio_i _o>]().set_bit())
+                        pad::set_output($pin_id, true)
                     }
                 }
                 paste::paste! {
                     fn set_low_inner(&self)  {
-                        let glb = unsafe { &*pac::GLB::ptr() };
-                        glb.gpio_cfgctl32.modify(|_, w| w.[<reg_ $gpio_i _o>]().clear_bit())
+                        pad::set_output($pin_id, false)
                     }
                 }
+                fn toggle_inner(&self) {
+                    // no set/clear/toggle registers, flip the output latch in a single modify
+                    pad::toggle_output($pin_id)
+                }
             }
 
             impl<MODE> InternalStatefulOutputImp for $Pini<Output<MODE>> {
@@ -480,83 +2132,35 @@ macro_rules! impl_glb {
                 }
             }
 
-            impl<MODE> InterruptPin for $Pini<Input<MODE>> {
-
-                paste::paste! {
-                    fn trigger_on_event(&mut self, event: Event) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
-                        // 10 pins per register
-                        let pin_id = $pin_id % 10;
-                        // each pin uses 3 bits
-                        let offset = pin_id * 3;
-                        // ensure our event fits in the mask
-                        let event = (0b11 & event as u32) << offset;
-                        // Write new event
-                        glb.$gpio_int_mode_seti.modify(|r,w| {
-                            // Mask off previous bits
-                            let mask = 0b11 << offset;
-                            let prev = r.bits() & ! mask;
-                            unsafe {w.bits(prev | event)}
-                        });
-                    }
-
-                    fn control_asynchronous(&mut self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
-                        let pin_id = $pin_id % 10;
-                        let offset = pin_id * 3;
-                        glb.$gpio_int_mode_seti.modify(|r,w| {
-                            let mask = 0b100 << offset;
-                            unsafe {w.bits(r.bits() | mask)}
-                        });
-                    }
-
-                    fn control_synchronous(&mut self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
-                        let pin_id = $pin_id % 10;
-                        // each pin uses 3 bits
-                        let offset = pin_id * 3;
-                        glb.gpio_int_mode_set1.modify(|r,w| {
-                            let mask = 0b100 << offset;
-                            unsafe {w.bits(r.bits() & !mask)}
-                        });
-                    }
-
-                    fn enable_interrupt(&mut self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
+            /// Async input, the GPIO interrupt must be enabled and routed to [`on_interrupt`]
+            #[cfg(feature = "async")]
+            impl<MODE> embedded_hal_async::digital::Wait for $Pini<Input<MODE>> {
+                impl_wait!(|_: &Self| $pin_id);
+            }
 
-                        glb.gpio_int_mask1.modify(|r,w| {
-                            unsafe {
-                            w.bits(r.bits() | (1 << $pin_id))
-                            }
-                        });
-                    }
+            impl<MODE> InterruptPin for $Pini<Input<MODE>> {
+                fn enable_interrupt(&mut self, trigger: TriggerMode) {
+                    pad::enable_interrupt($pin_id, trigger);
+                }
 
-                    fn disable_interrupt(&mut self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
+                fn disable_interrupt(&mut self) {
+                    pad::set_interrupt_mask($pin_id, true);
+                }
 
-                        glb.gpio_int_mask1.modify(|r, w| {
-                            unsafe {
-                                w.bits(r.bits() & (0xFFFF_FFFF - (1 << $pin_id)))
-                            }
-                        });
-                    }
+                fn clear_pending(&mut self) {
+                    pad::clear_interrupt($pin_id);
+                }
 
-                    fn clear_interrupt_pending_bit(&mut self) {
-                        let glb = unsafe { &*pac::GLB::ptr() };
+                fn is_pending(&self) -> bool {
+                    pad::interrupt_pending($pin_id)
+                }
 
-                        glb.gpio_int_clr1.write(|w| {
-                            unsafe {
-                            w.bits(1 << $pin_id)
-                            }
-                        });
-                    }
+                fn control_asynchronous(&mut self) {
+                    pad::set_interrupt_async($pin_id, true);
+                }
 
-                    fn check_interrupt(&self) -> bool {
-                        let glb = unsafe { &*pac::GLB::ptr() };
-                        let intstat:u32 = glb.gpio_int_stat1.read().gpio_int_stat1().bits();
-                        let int_masked:u32 = intstat | (1 << $pin_id);
-                        0 != int_masked
-                    }
+                fn control_synchronous(&mut self) {
+                    pad::set_interrupt_async($pin_id, false);
                 }
             }
 
@@ -582,6 +2186,11 @@ macro_rules! impl_glb {
                 fn is_set_low(&mut self) -> Result<bool, Self::Error> {
                     Ok(self.is_output_low_inner())
                 }
+
+                fn toggle(&mut self) -> Result<(), Self::Error> {
+                    self.toggle_inner();
+                    Ok(())
+                }
             }
 
             )+
@@ -593,36 +2202,327 @@ macro_rules! impl_glb {
 // there are at most 32 GPIO, the other 6 pins are analogue only
 // TODO: implement the rest of the pins
 impl_glb! {
-    Pin0: (pin0, gpio_cfgctl0, UartSig0, sig0, mosi, scl, gpio_0, gpio_int_mode_set1, 0),
-    Pin1: (pin1, gpio_cfgctl0, UartSig1, sig1, miso, sda, gpio_1, gpio_int_mode_set1, 1),
-    Pin2: (pin2, gpio_cfgctl1, UartSig2, sig2, ss, scl, gpio_2, gpio_int_mode_set1, 2),
-    Pin3: (pin3, gpio_cfgctl1, UartSig3, sig3, sclk, sda, gpio_3, gpio_int_mode_set1, 3),
-    Pin4: (pin4, gpio_cfgctl2, UartSig4, sig4, mosi, scl, gpio_4, gpio_int_mode_set1, 4),
-    Pin5: (pin5, gpio_cfgctl2, UartSig5, sig5, miso, sda, gpio_5, gpio_int_mode_set1, 5),
-    Pin6: (pin6, gpio_cfgctl3, UartSig6, sig6, ss, scl, gpio_6, gpio_int_mode_set1, 6),
-    Pin7: (pin7, gpio_cfgctl3, UartSig7, sig7, sclk, sda, gpio_7, gpio_int_mode_set1, 7),
-    Pin8: (pin8, gpio_cfgctl4, UartSig0, sig0, mosi, scl, gpio_8, gpio_int_mode_set1, 8),
-    Pin9: (pin9, gpio_cfgctl4, UartSig1, sig1, miso, sda, gpio_9, gpio_int_mode_set1, 9),
-    Pin10: (pin10, gpio_cfgctl5, UartSig2, sig2, ss, scl, gpio_10, gpio_int_mode_set2, 10),
-    Pin11: (pin11, gpio_cfgctl5, UartSig3, sig3, sclk, sda, gpio_11, gpio_int_mode_set2, 11),
-    Pin12: (pin12, gpio_cfgctl6, UartSig4, sig4, mosi, scl, gpio_12, gpio_int_mode_set2, 12),
-    Pin13: (pin13, gpio_cfgctl6, UartSig5, sig5, miso, sda, gpio_13, gpio_int_mode_set2, 13),
-    Pin14: (pin14, gpio_cfgctl7, UartSig6, sig6, ss, scl, gpio_14, gpio_int_mode_set2, 14),
-    Pin15: (pin15, gpio_cfgctl7, UartSig7, sig7, sclk, sda, gpio_15, gpio_int_mode_set2, 15),
-    Pin16: (pin16, gpio_cfgctl8, UartSig0, sig0, mosi, scl, gpio_16, gpio_int_mode_set2, 16),
-    Pin17: (pin17, gpio_cfgctl8, UartSig1, sig1, miso, sda, gpio_17, gpio_int_mode_set2, 17),
-    Pin18: (pin18, gpio_cfgctl9, UartSig2, sig2, ss, scl, gpio_18, gpio_int_mode_set2, 18),
-    Pin19: (pin19, gpio_cfgctl9, UartSig3, sig3, sclk, sda, gpio_19, gpio_int_mode_set2, 19),
-    Pin20: (pin20, gpio_cfgctl10, UartSig4, sig4, mosi, scl, gpio_20, gpio_int_mode_set3, 20),
-    Pin21: (pin21, gpio_cfgctl10, UartSig5, sig5, miso, sda, gpio_21, gpio_int_mode_set3, 21),
-    Pin22: (pin22, gpio_cfgctl11, UartSig6, sig6, ss, scl, gpio_22, gpio_int_mode_set3, 22),
-    Pin23: (pin23, gpio_cfgctl11, UartSig7, sig7, sclk, sda, gpio_23, gpio_int_mode_set3, 23),
-    Pin24: (pin24, gpio_cfgctl12, UartSig0, sig0, mosi, scl, gpio_24, gpio_int_mode_set3, 24),
-    Pin25: (pin25, gpio_cfgctl12, UartSig1, sig1, miso, sda, gpio_25, gpio_int_mode_set3, 25),
-    Pin26: (pin26, gpio_cfgctl13, UartSig2, sig2, ss, scl, gpio_26, gpio_int_mode_set3, 26),
-    Pin27: (pin27, gpio_cfgctl13, UartSig3, sig3, sclk, sda, gpio_27, gpio_int_mode_set3, 27),
-    Pin28: (pin28, gpio_cfgctl14, UartSig4, sig4, mosi, scl, gpio_28, gpio_int_mode_set3, 28),
-    Pin29: (pin29, gpio_cfgctl14, UartSig5, sig5, miso, sda, gpio_29, gpio_int_mode_set3, 29),
-    Pin30: (pin30, gpio_cfgctl15, UartSig6, sig6, ss, scl, gpio_30, gpio_int_mode_set4, 30),
-    Pin31: (pin31, gpio_cfgctl15, UartSig7, sig7, sclk, sda, gpio_31, gpio_int_mode_set4, 31),
+    Pin0: (pin0, gpio_cfgctl0, UartSig0, UartMux0, sig0, PwmCh0, mosi, scl, gpio_0, 0),
+    Pin1: (pin1, gpio_cfgctl0, UartSig1, UartMux1, sig1, PwmCh1, miso, sda, gpio_1, 1),
+    Pin2: (pin2, gpio_cfgctl1, UartSig2, UartMux2, sig2, PwmCh2, ss, scl, gpio_2, 2),
+    Pin3: (pin3, gpio_cfgctl1, UartSig3, UartMux3, sig3, PwmCh3, sclk, sda, gpio_3, 3),
+    Pin4: (pin4, gpio_cfgctl2, UartSig4, UartMux4, sig4, PwmCh4, mosi, scl, gpio_4, 4),
+    Pin5: (pin5, gpio_cfgctl2, UartSig5, UartMux5, sig5, PwmCh0, miso, sda, gpio_5, 5),
+    Pin6: (pin6, gpio_cfgctl3, UartSig6, UartMux6, sig6, PwmCh1, ss, scl, gpio_6, 6),
+    Pin7: (pin7, gpio_cfgctl3, UartSig7, UartMux7, sig7, PwmCh2, sclk, sda, gpio_7, 7),
+    Pin8: (pin8, gpio_cfgctl4, UartSig0, UartMux0, sig0, PwmCh3, mosi, scl, gpio_8, 8),
+    Pin9: (pin9, gpio_cfgctl4, UartSig1, UartMux1, sig1, PwmCh4, miso, sda, gpio_9, 9),
+    Pin10: (pin10, gpio_cfgctl5, UartSig2, UartMux2, sig2, PwmCh0, ss, scl, gpio_10, 10),
+    Pin11: (pin11, gpio_cfgctl5, UartSig3, UartMux3, sig3, PwmCh1, sclk, sda, gpio_11, 11),
+    Pin12: (pin12, gpio_cfgctl6, UartSig4, UartMux4, sig4, PwmCh2, mosi, scl, gpio_12, 12),
+    Pin13: (pin13, gpio_cfgctl6, UartSig5, UartMux5, sig5, PwmCh3, miso, sda, gpio_13, 13),
+    Pin14: (pin14, gpio_cfgctl7, UartSig6, UartMux6, sig6, PwmCh4, ss, scl, gpio_14, 14),
+    Pin15: (pin15, gpio_cfgctl7, UartSig7, UartMux7, sig7, PwmCh0, sclk, sda, gpio_15, 15),
+    Pin16: (pin16, gpio_cfgctl8, UartSig0, UartMux0, sig0, PwmCh1, mosi, scl, gpio_16, 16),
+    Pin17: (pin17, gpio_cfgctl8, UartSig1, UartMux1, sig1, PwmCh2, miso, sda, gpio_17, 17),
+    Pin18: (pin18, gpio_cfgctl9, UartSig2, UartMux2, sig2, PwmCh3, ss, scl, gpio_18, 18),
+    Pin19: (pin19, gpio_cfgctl9, UartSig3, UartMux3, sig3, PwmCh4, sclk, sda, gpio_19, 19),
+    Pin20: (pin20, gpio_cfgctl10, UartSig4, UartMux4, sig4, PwmCh0, mosi, scl, gpio_20, 20),
+    Pin21: (pin21, gpio_cfgctl10, UartSig5, UartMux5, sig5, PwmCh1, miso, sda, gpio_21, 21),
+    Pin22: (pin22, gpio_cfgctl11, UartSig6, UartMux6, sig6, PwmCh2, ss, scl, gpio_22, 22),
+    Pin23: (pin23, gpio_cfgctl11, UartSig7, UartMux7, sig7, PwmCh3, sclk, sda, gpio_23, 23),
+    Pin24: (pin24, gpio_cfgctl12, UartSig0, UartMux0, sig0, PwmCh4, mosi, scl, gpio_24, 24),
+    Pin25: (pin25, gpio_cfgctl12, UartSig1, UartMux1, sig1, PwmCh0, miso, sda, gpio_25, 25),
+    Pin26: (pin26, gpio_cfgctl13, UartSig2, UartMux2, sig2, PwmCh1, ss, scl, gpio_26, 26),
+    Pin27: (pin27, gpio_cfgctl13, UartSig3, UartMux3, sig3, PwmCh2, sclk, sda, gpio_27, 27),
+    Pin28: (pin28, gpio_cfgctl14, UartSig4, UartMux4, sig4, PwmCh3, mosi, scl, gpio_28, 28),
+    Pin29: (pin29, gpio_cfgctl14, UartSig5, UartMux5, sig5, PwmCh4, miso, sda, gpio_29, 29),
+    Pin30: (pin30, gpio_cfgctl15, UartSig6, UartMux6, sig6, PwmCh0, ss, scl, gpio_30, 30),
+    Pin31: (pin31, gpio_cfgctl15, UartSig7, UartMux7, sig7, PwmCh1, sclk, sda, gpio_31, 31),
+}
+
+macro_rules! impl_aon {
+    ($($Pini: ident: $pin_id: literal,)+) => {
+        $(
+            impl<MODE> AonPin for $Pini<MODE> {
+                const PIN: u8 = $pin_id;
+            }
+        )+
+    };
+}
+
+impl_aon! {
+    Pin9: 9,
+    Pin10: 10,
+    Pin11: 11,
+    Pin12: 12,
+    Pin13: 13,
+}
+
+macro_rules! impl_qdec {
+    ($($Pini: ident: $pin_id: literal => ($signal: ident, $QdecPin: ident, $Qdeci: ident),)+) => {
+        $(
+            impl<MODE> $Pini<MODE> {
+                paste::paste! {
+                    /// Configures the pin as quadrature decoder signal, see the table in the module docs
+                    pub fn [<into_qdec_ $signal>](self) -> $Pini<Qdec> {
+                        let _: $Pini<Input<Floating>> = self.into_floating_input();
+                        pad::set_function($pin_id, pad::FUNC_QDEC);
+                        $Pini { _mode: PhantomData }
+                    }
+                }
+            }
+
+            impl $QdecPin<$Qdeci> for $Pini<Qdec> {}
+        )+
+    };
+}
+
+impl_qdec! {
+    Pin0: 0 => (a, QdecAPin, Qdec0),
+    Pin1: 1 => (b, QdecBPin, Qdec0),
+    Pin2: 2 => (led, QdecLedPin, Qdec0),
+    Pin3: 3 => (a, QdecAPin, Qdec1),
+    Pin4: 4 => (b, QdecBPin, Qdec1),
+    Pin5: 5 => (led, QdecLedPin, Qdec1),
+    Pin6: 6 => (a, QdecAPin, Qdec2),
+    Pin7: 7 => (b, QdecBPin, Qdec2),
+    Pin8: 8 => (led, QdecLedPin, Qdec2),
+    Pin9: 9 => (a, QdecAPin, Qdec0),
+    Pin10: 10 => (b, QdecBPin, Qdec0),
+    Pin11: 11 => (led, QdecLedPin, Qdec0),
+    Pin12: 12 => (a, QdecAPin, Qdec1),
+    Pin13: 13 => (b, QdecBPin, Qdec1),
+    Pin14: 14 => (led, QdecLedPin, Qdec1),
+    Pin15: 15 => (a, QdecAPin, Qdec2),
+    Pin16: 16 => (b, QdecBPin, Qdec2),
+    Pin17: 17 => (led, QdecLedPin, Qdec2),
+    Pin18: 18 => (a, QdecAPin, Qdec0),
+    Pin19: 19 => (b, QdecBPin, Qdec0),
+    Pin20: 20 => (led, QdecLedPin, Qdec0),
+    Pin21: 21 => (a, QdecAPin, Qdec1),
+    Pin22: 22 => (b, QdecBPin, Qdec1),
+    Pin23: 23 => (led, QdecLedPin, Qdec1),
+    Pin24: 24 => (a, QdecAPin, Qdec2),
+    Pin25: 25 => (b, QdecBPin, Qdec2),
+    Pin26: 26 => (led, QdecLedPin, Qdec2),
+    Pin27: 27 => (a, QdecAPin, Qdec0),
+    Pin28: 28 => (b, QdecBPin, Qdec0),
+    Pin29: 29 => (led, QdecLedPin, Qdec0),
+    Pin30: 30 => (a, QdecAPin, Qdec1),
+    Pin31: 31 => (b, QdecBPin, Qdec1),
+}
+
+macro_rules! impl_analog {
+    ($($Pini: ident: $pin_id: literal => $channel: literal,)+) => {
+        $(
+            impl<MODE> $Pini<MODE> {
+                /// Configures the pin as GPADC input, disabling the digital input buffer and pulls.
+                ///
+                /// The `into_*` digital conversions enable the input buffer again.
+                pub fn into_analog(self) -> $Pini<Analog> {
+                    pad::set_analog($pin_id);
+                    $Pini { _mode: PhantomData }
+                }
+            }
+
+            impl AdcChannel for $Pini<Analog> {
+                const CHANNEL: u8 = $channel;
+            }
+        )+
+    };
+}
+
+// GPADC channel of each analog capable pad
+impl_analog! {
+    Pin8: 8 => 0,
+    Pin15: 15 => 1,
+    Pin17: 17 => 2,
+    Pin11: 11 => 3,
+    Pin12: 12 => 4,
+    Pin14: 14 => 5,
+    Pin7: 7 => 6,
+    Pin9: 9 => 7,
+    Pin18: 18 => 8,
+    Pin19: 19 => 9,
+    Pin20: 20 => 10,
+    Pin21: 21 => 11,
+}
+
+macro_rules! impl_ir_rx {
+    ($($Pini: ident: $pin_id: literal,)+) => {
+        $(
+            impl<MODE> $Pini<MODE> {
+                /// Configures the pin as IR receiver input, routing it through `ir_rx_gpio_sel`
+                pub fn into_ir_rx(self) -> $Pini<IrRx> {
+                    let glb = unsafe { &*pac::GLB::ptr() };
+                    let _: $Pini<Input<Floating>> = self.into_floating_input();
+                    // GPIO17 to GPIO31 are selected with 1 to 15, 0 disconnects the receiver
+                    glb.led_driver.modify(|_, w| unsafe { w.ir_rx_gpio_sel().bits($pin_id - 16) });
+                    $Pini { _mode: PhantomData }
+                }
+            }
+
+            impl IrRxPin for $Pini<IrRx> {}
+        )+
+    };
+}
+
+impl_ir_rx! {
+    Pin17: 17,
+    Pin18: 18,
+    Pin19: 19,
+    Pin20: 20,
+    Pin21: 21,
+    Pin22: 22,
+    Pin23: 23,
+    Pin24: 24,
+    Pin25: 25,
+    Pin26: 26,
+    Pin27: 27,
+    Pin28: 28,
+    Pin29: 29,
+    Pin30: 30,
+    Pin31: 31,
+}
+
+macro_rules! impl_ir_tx {
+    ($($Pini: ident: $pin_id: literal => $out_en: literal,)+) => {
+        $(
+            impl<MODE> $Pini<MODE> {
+                /// Configures the pin as output of the IR LED driver
+                ///
+                /// The LED driver is a dedicated current driver, not a pad function, so the pad
+                /// is put in analog mode and the driver output is enabled on it.
+                pub fn into_ir_tx(self) -> $Pini<IrTx> {
+                    let glb = unsafe { &*pac::GLB::ptr() };
+                    pad::set_analog($pin_id);
+                    glb.led_driver.modify(|r, w| unsafe { w
+                        .led_din_sel().set_bit() // driven by the IR transmitter
+                        .leddrv_out_en().bits(r.leddrv_out_en().bits() | $out_en)
+                        .pu_leddrv().set_bit()
+                    });
+                    $Pini { _mode: PhantomData }
+                }
+            }
+
+            impl $Pini<IrTx> {
+                /// Disable the LED driver output on this pin and return it as a floating input
+                pub fn release_ir_tx(self) -> $Pini<Input<Floating>> {
+                    let glb = unsafe { &*pac::GLB::ptr() };
+                    glb.led_driver.modify(|r, w| unsafe {
+                        let out_en = r.leddrv_out_en().bits() & !$out_en;
+                        w.leddrv_out_en().bits(out_en).pu_leddrv().bit(out_en != 0)
+                    });
+                    self.into_floating_input()
+                }
+            }
+
+            impl IrTxPin for $Pini<IrTx> {}
+        )+
+    };
+}
+
+// the LED driver outputs on GPIO22 and GPIO23
+impl_ir_tx! {
+    Pin22: 22 => 0b01,
+    Pin23: 23 => 0b10,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decode the pad configuration a typed pin conversion writes, `input` as passed to
+    /// `into_pin_with_mode`
+    fn decode_mode(func: u8, pu: bool, pd: bool, input: bool) -> PadConfig {
+        PadConfig::decode(pad::mode_bits(func, pu, pd), !input, false, false)
+    }
+
+    #[test]
+    fn decode_round_trips_gpio_setters() {
+        // (pu, pd, input, pull) of into_floating_output .. into_pull_down_input
+        let setters = [
+            (false, false, false, Pull::None),
+            (true, false, false, Pull::Up),
+            (false, true, false, Pull::Down),
+            (false, false, true, Pull::None),
+            (true, false, true, Pull::Up),
+            (false, true, true, Pull::Down),
+        ];
+        for (pu, pd, input, pull) in setters {
+            let config = decode_mode(pad::FUNC_SWGPIO, pu, pd, input);
+            assert_eq!(config.function, pad::FUNC_SWGPIO);
+            assert_eq!(config.function_name(), "gpio");
+            assert_eq!(config.pull, pull);
+            assert_eq!(config.drive, DriveStrength::Level0);
+            assert!(config.schmitt);
+            assert!(config.input_enable);
+            assert_eq!(config.output_enable, !input);
+        }
+    }
+
+    #[test]
+    fn decode_round_trips_alternate_functions() {
+        let functions = [
+            (0, false, false, false, "clk_out"),
+            (pad::FUNC_SPI, true, false, true, "spi"),
+            (6, true, false, true, "i2c"),
+            (pad::FUNC_UART, true, false, true, "uart"),
+            (8, false, false, false, "pwm"),
+        ];
+        for (func, pu, pd, input, name) in functions {
+            let config = decode_mode(func, pu, pd, input);
+            assert_eq!(config.function, func);
+            assert_eq!(config.function_name(), name);
+            assert_eq!(config.pull, if pu { Pull::Up } else { Pull::None });
+            assert_eq!(config.drive, DriveStrength::Level0);
+        }
+    }
+
+    #[test]
+    fn decode_round_trips_drive_strength() {
+        let base = pad::mode_bits(pad::FUNC_SWGPIO, false, false);
+        for drive in [
+            DriveStrength::Level0,
+            DriveStrength::Level1,
+            DriveStrength::Level2,
+            DriveStrength::Level3,
+        ] {
+            let config = PadConfig::decode(base | pad::drive_bits(drive), true, false, false);
+            assert_eq!(config.drive, drive);
+            assert_eq!(config.function, pad::FUNC_SWGPIO);
+            assert_eq!(config.pull, Pull::None);
+        }
+    }
+
+    #[test]
+    fn isr_cell_empty() {
+        let cell: IsrCell<u32> = IsrCell::new();
+        assert_eq!(cell.borrow_in_isr(|_| unreachable!()), None::<()>);
+        assert_eq!(cell.take(), None);
+    }
+
+    #[test]
+    fn isr_cell_lend_replaces_value() {
+        let cell = IsrCell::new();
+        assert_eq!(cell.lend(1), None);
+        assert_eq!(cell.lend(2), Some(1));
+        assert_eq!(cell.borrow_in_isr(|value| *value), Some(2));
+    }
+
+    #[test]
+    fn isr_cell_take_after_lend() {
+        let cell = IsrCell::new();
+        cell.lend(1);
+        assert_eq!(cell.borrow_in_isr(|value| *value += 1), Some(()));
+        assert_eq!(cell.take(), Some(2));
+        assert_eq!(cell.take(), None);
+        assert_eq!(cell.borrow_in_isr(|value| *value), None);
+    }
+
+    #[test]
+    fn decode_round_trips_analog() {
+        let config = PadConfig::decode(pad::analog_bits(), false, false, false);
+        assert_eq!(config.function, pad::FUNC_ANALOG);
+        assert_eq!(config.function_name(), "analog");
+        assert_eq!(config.pull, Pull::None);
+        assert!(!config.schmitt);
+        assert!(!config.input_enable);
+    }
 }
@@ -0,0 +1,117 @@
+/*!
+# Hibernate (HBN) GPIO wakeup
+The always-on pads GPIO9 to GPIO13 can wake the chip from HBN and PDS sleep. Arm a pad that is
+configured as an input with [`enable_gpio_wakeup`]; other pins do not implement [`WakeupPin`]
+and are rejected at compile time.
+
+```rust
+  let mut button = parts.pin10.into_pull_up_input();
+  hbn::enable_gpio_wakeup(&mut button, WakeupTrigger::FallingEdge);
+  // enter HBN ...
+
+  // after waking up
+  if hbn::wakeup_pins() & (1 << 10) != 0 {
+      // woken by the button
+  }
+  hbn::clear_wakeup_pins();
+  hbn::disable_gpio_wakeup(&mut button);
+```
+
+The pin keeps its GPIO configuration while armed, so it can be read as a normal input and is
+back to plain GPIO use after [`disable_gpio_wakeup`].
+
+# Pad state in HBN
+The GLB pad configuration is lost in HBN, the always-on pads only keep what the HBN block
+controls. There is no output hold for them, so drive a level through HBN with the pull
+resistor instead and let the always-on domain keep it with [`hold_aon_pulls`]:
+
+```rust
+  let mut enable = parts.pin11.into_pull_down_input();
+  hbn::hold_aon_pulls(true);
+  // enter HBN, pin 11 stays pulled down
+```
+*/
+
+use crate::gpio::{AonPin, Input, Pin10, Pin11, Pin12, Pin13, Pin9};
+use crate::pac;
+
+/// First GPIO number of the always-on pads
+const FIRST_WAKEUP_PIN: u8 = 9;
+/// Asynchronous trigger flag of `hbn_pin_wakeup_mode`, the 32 kHz clock may be off in sleep
+const MODE_ASYNC: u8 = 0b100;
+
+/// Condition that wakes the chip
+///
+/// The trigger is shared by all wakeup pads, arming a pad replaces the trigger of the others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeupTrigger {
+    /// Wake on the falling edge
+    FallingEdge = 0,
+    /// Wake on the rising edge
+    RisingEdge = 1,
+    /// Wake while the pin is low
+    LowLevel = 2,
+    /// Wake while the pin is high
+    HighLevel = 3,
+}
+
+/// Input pins on an always-on pad, which can wake the chip from HBN
+pub trait WakeupPin: AonPin {}
+
+impl<MODE> WakeupPin for Pin9<Input<MODE>> {}
+impl<MODE> WakeupPin for Pin10<Input<MODE>> {}
+impl<MODE> WakeupPin for Pin11<Input<MODE>> {}
+impl<MODE> WakeupPin for Pin12<Input<MODE>> {}
+impl<MODE> WakeupPin for Pin13<Input<MODE>> {}
+
+/// Arm `pin` as a wakeup source for `trigger`
+pub fn enable_gpio_wakeup<P: WakeupPin>(_pin: &mut P, trigger: WakeupTrigger) {
+    let hbn = unsafe { &*pac::HBN::ptr() };
+    let bit = 1 << (P::PIN - FIRST_WAKEUP_PIN);
+    hbn.hbn_irq_mode.modify(|r, w| unsafe {
+        w.hbn_pin_wakeup_mode()
+            .bits(MODE_ASYNC | trigger as u8)
+            // the always-on domain needs its own input buffer and schmitt trigger on the pad
+            .reg_aon_pad_ie_smt()
+            .bits(r.reg_aon_pad_ie_smt().bits() | bit)
+            .hbn_pin_wakeup_mask()
+            .bits(r.hbn_pin_wakeup_mask().bits() & !bit)
+    });
+}
+
+/// Stop `pin` from waking the chip
+pub fn disable_gpio_wakeup<P: WakeupPin>(_pin: &mut P) {
+    let hbn = unsafe { &*pac::HBN::ptr() };
+    let bit = 1 << (P::PIN - FIRST_WAKEUP_PIN);
+    hbn.hbn_irq_mode.modify(|r, w| unsafe {
+        w.hbn_pin_wakeup_mask()
+            .bits(r.hbn_pin_wakeup_mask().bits() | bit)
+            .reg_aon_pad_ie_smt()
+            .bits(r.reg_aon_pad_ie_smt().bits() & !bit)
+    });
+}
+
+/// Keep the pull resistors of the always-on pads in HBN
+///
+/// With `hold` set the HBN block drives the pull-up and pull-down of GPIO9 to GPIO13 from the
+/// values they had in the GLB when the chip entered HBN, otherwise the pads float.
+pub fn hold_aon_pulls(hold: bool) {
+    let hbn = unsafe { &*pac::HBN::ptr() };
+    hbn.hbn_irq_mode.modify(|_, w| w.reg_en_hw_pu_pd().bit(hold));
+}
+
+/// Returns a bitmask of the pins that triggered a wakeup, bit `n` is pin `n`
+///
+/// The flags live in the always-on domain, so they survive the reset that ends HBN.
+pub fn wakeup_pins() -> u32 {
+    let hbn = unsafe { &*pac::HBN::ptr() };
+    (hbn.hbn_irq_stat.read().bits() & 0b1_1111) << FIRST_WAKEUP_PIN
+}
+
+/// Clear the wakeup flags of all pins
+pub fn clear_wakeup_pins() {
+    let hbn = unsafe { &*pac::HBN::ptr() };
+    // the clear bits are level sensitive, release them again like the SDK does
+    hbn.hbn_irq_clr.modify(|r, w| unsafe { w.bits(r.bits() | 0b1_1111) });
+    hbn.hbn_irq_clr.modify(|r, w| unsafe { w.bits(r.bits() & !0b1_1111) });
+}
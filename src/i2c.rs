@@ -0,0 +1,71 @@
+/*!
+# Inter-Integrated Circuit
+Pins are handed to the I2C peripheral with `into_i2c_scl` on even pins and `into_i2c_sda` on odd
+pins. The conversions select the I2C pad function with the internal pull-up enabled; the
+peripheral only ever pulls the lines low. Use external pull-ups for anything but short, slow
+buses.
+
+An I2C driver takes the pins as a `(scl, sda)` tuple, checked with the [`Pins`] trait:
+
+```rust
+  let scl = parts.pin0.into_i2c_scl();
+  let sda = parts.pin1.into_i2c_sda();
+```
+
+The pins can be reclaimed as GPIO with any of the other `into_*` conversions.
+*/
+
+use crate::gpio::I2c;
+use crate::pac;
+
+#[allow(clippy::missing_safety_doc)]
+/// SCL pins - DO NOT IMPLEMENT THIS TRAIT
+pub unsafe trait SclPin<I2C> {}
+
+#[allow(clippy::missing_safety_doc)]
+/// SDA pins - DO NOT IMPLEMENT THIS TRAIT
+pub unsafe trait SdaPin<I2C> {}
+
+#[allow(clippy::missing_safety_doc)]
+/// I2C pins - DO NOT IMPLEMENT THIS TRAIT
+pub unsafe trait Pins<I2C> {}
+
+unsafe impl<I2C, SCL, SDA> Pins<I2C> for (SCL, SDA)
+where
+    SCL: SclPin<I2C>,
+    SDA: SdaPin<I2C>,
+{
+}
+
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin0<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin1<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin2<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin3<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin4<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin5<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin6<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin7<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin8<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin9<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin10<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin11<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin12<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin13<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin14<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin15<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin16<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin17<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin18<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin19<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin20<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin21<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin22<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin23<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin24<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin25<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin26<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin27<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin28<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin29<I2c> {}
+unsafe impl SclPin<pac::I2C> for crate::gpio::Pin30<I2c> {}
+unsafe impl SdaPin<pac::I2C> for crate::gpio::Pin31<I2c> {}
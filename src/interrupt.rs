@@ -0,0 +1,224 @@
+/*!
+# Interrupts
+Peripheral interrupts are routed through the core local interrupt controller (CLIC).
+Each interrupt has its own enable and pending flag here, in addition to the enable/mask bits
+inside the peripheral itself.
+
+With `riscv-rt` every interrupt number above the standard machine interrupts ends up in
+`DefaultHandler`, which can dispatch on [`current`]:
+
+```rust
+  #[export_name = "DefaultHandler"]
+  fn default_handler() {
+      match interrupt::current() {
+          Some(Interrupt::SPI) => hal::spi::on_interrupt(),
+          _ => {}
+      }
+  }
+
+  unsafe { interrupt::enable(Interrupt::SPI) };
+```
+*/
+
+#![allow(non_camel_case_types, clippy::upper_case_acronyms)]
+
+/// Base address of the CLIC of hart 0
+const CLIC_HART0_ADDR: usize = 0x0280_0000;
+/// Offset of the per-interrupt pending bytes
+const CLIC_INTIP: usize = 0x000;
+/// Offset of the per-interrupt enable bytes
+const CLIC_INTIE: usize = 0x400;
+
+/// Interrupt numbers as seen by the CLIC and in `mcause`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Interrupt {
+    /// Machine software interrupt
+    MSIP = 3,
+    /// Machine timer interrupt
+    MTIP = 7,
+    /// Machine external interrupt
+    MEIP = 11,
+    /// BMX error
+    BMX_ERR = 16,
+    /// BMX timeout
+    BMX_TO = 17,
+    /// L1C BMX error
+    L1C_BMX_ERR = 18,
+    /// L1C BMX timeout
+    L1C_BMX_TO = 19,
+    /// SEC BMX error
+    SEC_BMX_ERR = 20,
+    /// RF top interrupt 0
+    RF_TOP_INT0 = 21,
+    /// RF top interrupt 1
+    RF_TOP_INT1 = 22,
+    /// SDIO
+    SDIO = 23,
+    /// DMA BMX error
+    DMA_BMX_ERR = 24,
+    /// Security engine GMAC
+    SEC_GMAC = 25,
+    /// Security engine CDET
+    SEC_CDET = 26,
+    /// Security engine PKA
+    SEC_PKA = 27,
+    /// Security engine TRNG
+    SEC_TRNG = 28,
+    /// Security engine AES
+    SEC_AES = 29,
+    /// Security engine SHA
+    SEC_SHA = 30,
+    /// All DMA channels
+    DMA_ALL = 31,
+    /// MJPEG
+    MJPEG = 32,
+    /// Camera
+    CAM = 33,
+    /// I2S
+    I2S = 34,
+    /// IR transmit
+    IRTX = 35,
+    /// IR receive
+    IRRX = 36,
+    /// USB
+    USB = 37,
+    /// Audio
+    AUDIO = 38,
+    /// Serial flash controller
+    SF_CTRL = 39,
+    /// Ethernet MAC
+    EMAC = 40,
+    /// GPADC DMA
+    GPADC_DMA = 41,
+    /// Efuse
+    EFUSE = 42,
+    /// SPI
+    SPI = 43,
+    /// UART0
+    UART0 = 45,
+    /// UART1
+    UART1 = 46,
+    /// I2C
+    I2C = 47,
+    /// PWM
+    PWM = 49,
+    /// Timer channel 0
+    TIMER_CH0 = 52,
+    /// Timer channel 1
+    TIMER_CH1 = 53,
+    /// Watchdog
+    TIMER_WDT = 54,
+    /// Quadrature decoder 0
+    QDEC0 = 56,
+    /// Quadrature decoder 1
+    QDEC1 = 57,
+    /// Quadrature decoder 2
+    QDEC2 = 58,
+    /// GPIO
+    GPIO_INT0 = 60,
+    /// PDS wakeup
+    PDS_WAKEUP = 64,
+    /// Hibernate out 0
+    HBN_OUT0 = 65,
+    /// Hibernate out 1
+    HBN_OUT1 = 66,
+    /// Brown out reset
+    BOR = 67,
+}
+
+impl Interrupt {
+    /// Look up the interrupt with CLIC number `nr`
+    pub fn from_number(nr: u16) -> Option<Self> {
+        use Interrupt::*;
+        const ALL: [Interrupt; 46] = [
+            MSIP, MTIP, MEIP, BMX_ERR, BMX_TO, L1C_BMX_ERR, L1C_BMX_TO, SEC_BMX_ERR, RF_TOP_INT0,
+            RF_TOP_INT1, SDIO, DMA_BMX_ERR, SEC_GMAC, SEC_CDET, SEC_PKA, SEC_TRNG, SEC_AES,
+            SEC_SHA, DMA_ALL, MJPEG, CAM, I2S, IRTX, IRRX, USB, AUDIO, SF_CTRL, EMAC, GPADC_DMA,
+            EFUSE, SPI, UART0, UART1, I2C, PWM, TIMER_CH0, TIMER_CH1, TIMER_WDT, QDEC0, QDEC1,
+            QDEC2, GPIO_INT0, PDS_WAKEUP, HBN_OUT0, HBN_OUT1, BOR,
+        ];
+        ALL.iter().copied().find(|irq| *irq as u16 == nr)
+    }
+}
+
+/// Returns the interrupt currently being handled, decoded from `mcause`
+pub fn current() -> Option<Interrupt> {
+    let cause = riscv::register::mcause::read();
+    if cause.is_interrupt() {
+        Interrupt::from_number(cause.code() as u16)
+    } else {
+        None
+    }
+}
+
+#[inline]
+fn clic_byte(offset: usize, irq: Interrupt) -> *mut u8 {
+    (CLIC_HART0_ADDR + offset + irq as usize) as *mut u8
+}
+
+/// Enable `irq` in the CLIC
+///
+/// # Safety
+/// This can break critical sections, and the handler for `irq` must be in place
+pub unsafe fn enable(irq: Interrupt) {
+    clic_byte(CLIC_INTIE, irq).write_volatile(1);
+}
+
+/// Disable `irq` in the CLIC
+pub fn disable(irq: Interrupt) {
+    unsafe { clic_byte(CLIC_INTIE, irq).write_volatile(0) };
+}
+
+/// Returns true if `irq` is enabled in the CLIC
+pub fn is_enabled(irq: Interrupt) -> bool {
+    unsafe { clic_byte(CLIC_INTIE, irq).read_volatile() & 1 != 0 }
+}
+
+/// Returns true if `irq` is pending in the CLIC
+pub fn is_pending(irq: Interrupt) -> bool {
+    unsafe { clic_byte(CLIC_INTIP, irq).read_volatile() & 1 != 0 }
+}
+
+/// Set `irq` pending in the CLIC
+pub fn pend(irq: Interrupt) {
+    unsafe { clic_byte(CLIC_INTIP, irq).write_volatile(1) };
+}
+
+/// Clear the CLIC pending flag of `irq`
+pub fn unpend(irq: Interrupt) {
+    unsafe { clic_byte(CLIC_INTIP, irq).write_volatile(0) };
+}
+
+/// Storage for a single task waker, shared between a future and an interrupt handler
+#[cfg(feature = "async")]
+pub(crate) struct WakerSlot {
+    waker: critical_section::Mutex<core::cell::RefCell<Option<core::task::Waker>>>,
+}
+
+#[cfg(feature = "async")]
+impl WakerSlot {
+    pub(crate) const fn new() -> Self {
+        WakerSlot {
+            waker: critical_section::Mutex::new(core::cell::RefCell::new(None)),
+        }
+    }
+
+    /// Store `waker`, replacing the previously registered one
+    pub(crate) fn register(&self, waker: &core::task::Waker) {
+        critical_section::with(|cs| {
+            let mut slot = self.waker.borrow_ref_mut(cs);
+            match slot.as_ref() {
+                Some(old) if old.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        });
+    }
+
+    /// Wake the registered task, if any
+    pub(crate) fn wake(&self) {
+        if let Some(waker) = critical_section::with(|cs| self.waker.borrow_ref_mut(cs).take()) {
+            waker.wake();
+        }
+    }
+}
@@ -35,14 +35,24 @@ pub use bl702_pac as pac;
 
 pub mod clock;
 pub mod delay;
+pub mod dma;
 pub mod gpio;
+pub mod hbn;
+pub mod i2c;
+pub mod interrupt;
 pub mod spi;
+pub mod timer;
 pub mod prelude {
+    pub use crate::dma::DmaExt as _bl702_hal_dma_DmaExt;
     pub use crate::gpio::GlbExt as _bl702_hal_gpio_GlbExt;
+    pub use crate::timer::TimerExt as _bl702_hal_timer_TimerExt;
     pub use embedded_time::rate::Extensions;
 }
 pub mod system;
 pub mod uart;
+/// The UART driver, under the name most HALs use for it
+pub use uart as serial;
+pub mod watchdog;
 
 /// System frequency (constant since we don't have clocks yet)
 pub const SYSFREQ: u32 = 144_000_000;
@@ -1,34 +1,110 @@
 /*!
 # Serial Peripheral Interface
-To construct the SPI instances, use the `Spi::new` function.
+To construct the SPI instances, use the `Spi::with_config` function with a `Config`, or
+`Spi::new` with just the mode and frequency.
 The pin parameter is a tuple containing `(miso, mosi, cs, sck)` which should be configured via `into_spi_miso, into_spi_mosi, into_spi_ss, into_spi_sclk`.
 CS is optional - so you can also pass a tuple containing `(miso, mosi, sck)`
+Only pins converted to the `gpio::Spi` mode are accepted, so passing a pin that is still a
+GPIO, or a pin in the wrong role (pin `n` is MOSI, MISO, SS or SCLK for `n % 4` = 0, 1, 2, 3),
+is a compile error.
+
+For a write-only bus pass `NoMiso` instead of the MISO pin, or just `(mosi, sck)`. For a read-only
+bus pass `NoMosi` instead of the MOSI pin. Operations that need the missing direction return
+`Error::NotSupported`.
+
+## Frame sizes
+The peripheral shifts 8, 16, 24 or 32 bits per FIFO entry. `Spi` implements `SpiBus<u8>`,
+`SpiBus<u16>` and `SpiBus<u32>`; the frame size is switched to match the word type of each
+operation, so 8 bit commands and 16 bit pixel data can be mixed on the same bus.
+24 bit frames can be selected with `Spi::set_frame_size(FrameSize::Bits24)` and are
+transferred using the low three bytes of `u32` words.
+
+Multi-byte frames are shifted out most significant byte first by default, so the `u16` word
+`0x1234` appears on the wire as `0x12, 0x34`, the same as writing `[0x12, 0x34]` with 8 bit frames.
+Use `Spi::set_format` to change the bit and byte order.
+## Modes
+The clock phase bit of the peripheral inverts the phase, so the embedded-hal modes map to:
+
+| mode     | polarity   | phase                       | `cr_spi_sclk_pol` | `cr_spi_sclk_ph` |
+|----------|------------|-----------------------------|-------------------|------------------|
+| `MODE_0` | `IdleLow`  | `CaptureOnFirstTransition`  | 0                 | 1                |
+| `MODE_1` | `IdleLow`  | `CaptureOnSecondTransition` | 0                 | 0                |
+| `MODE_2` | `IdleHigh` | `CaptureOnFirstTransition`  | 1                 | 1                |
+| `MODE_3` | `IdleHigh` | `CaptureOnSecondTransition` | 1                 | 0                |
+
+## Chip select
+When a CS pin is part of the tuple, `SpiDevice::transaction` keeps it asserted across all
+operations of the transaction, including delays. Plain `SpiBus` operations release CS after every
+frame unless `Spi::cs_continuous(true)` is set.
+To use a GPIO as chip select instead, wrap the bus in a `SpiDeviceWithCs`.
+
+Devices with a single bidirectional data line are supported through the `ThreeWire` wrapper.
+
 ## Initialisation example
 ```rust
-  let miso = parts.pin4.into_spi_miso();
-  let mosi = parts.pin5.into_spi_mosi();
+  let miso = parts.pin5.into_spi_miso();
+  let mosi = parts.pin4.into_spi_mosi();
   let ss = parts.pin2.into_spi_ss();
   let sclk = parts.pin3.into_spi_sclk();
-  let mut spi = hal::spi::Spi::new(
-      dp.SPI,
-      (miso, mosi, ss, sclk),
-      embedded_hal::spi::MODE_0,
-      8_000_000u32.Hz(),
-      clocks,
-  );
+  let config = hal::spi::Config::default()
+      .frequency(8_000_000u32.Hz())
+      .mode(embedded_hal::spi::MODE_0);
+  let mut spi = hal::spi::Spi::with_config(dp.SPI, (miso, mosi, ss, sclk), config, clocks)?;
+```
+
+## Throughput
+The blocking `SpiBus` operations keep up to `FIFO_DEPTH` words in flight, so frames follow each
+other with only the inter-frame interval in between instead of waiting for the CPU to read every
+word before sending the next. At 8 MHz an 8 bit frame lasts 1 us, which caps 8 bit transfers just
+below 1 MB/s. This figure is derived from the clock; no before/after throughput has been
+measured yet. `examples/spi_loopback.rs` runs at 8 MHz and prints the cycles taken by a
+pipelined transfer next to the word by word reference the blocking operations used before,
+which gives those numbers on a board.
+
+## DMA
+Large buffers can be moved by the DMA controller instead of the CPU:
+```rust
+  static FRAMEBUFFER: [u16; 240 * 240] = [0; 240 * 240];
+  let dma = dp.DMA.split();
+  let transfer = spi.write_dma(dma.ch0, &FRAMEBUFFER);
+  // do something else while the framebuffer is sent
+  let (_framebuffer, ch0) = transfer.wait();
+```
+
+## Async
+With the `async` feature enabled `Spi` also implements `embedded_hal_async::spi::SpiBus`.
+The futures are woken from the SPI interrupt, so route it to [`on_interrupt`] and enable it:
+```rust
+  #[export_name = "DefaultHandler"]
+  fn default_handler() {
+      if interrupt::current() == Some(Interrupt::SPI) {
+          hal::spi::on_interrupt();
+      }
+  }
+
+  unsafe { interrupt::enable(Interrupt::SPI) };
+  spi.write(&[0x2c]).await?;
 ```
 */
 
+use core::cell::Cell;
+use core::marker::PhantomData;
+
 use bl702_pac::SPI;
+use embedded_dma::{ReadBuffer, WriteBuffer};
 use embedded_hal::delay::DelayNs;
 use embedded_hal::spi::{ErrorKind, ErrorType, Mode, Operation, SpiBus, SpiDevice};
 use embedded_hal_nb::spi::FullDuplex;
+use embedded_time::duration::Microseconds;
 use embedded_time::rate::Hertz;
 
 use crate::pac;
 
-use crate::clock::Clocks;
+use crate::clock::{Clocks, Gate};
 use crate::delay::McycleDelay;
+use crate::gpio;
+use crate::timer::{run_with_timeout, Timeout, TimeoutError};
+use crate::dma::{self, Channel, Direction, Transfer, TransferPayload, Width};
 
 /// SPI error
 #[derive(Debug)]
@@ -42,6 +118,64 @@ pub enum Error {
     TxOverflow,
     /// Tx underflow occurred
     TxUnderflow,
+    /// The operation needs a MISO or MOSI pin that was not configured
+    NotSupported,
+    /// No progress was made within the timeout set with `Spi::set_timeout`
+    Timeout,
+}
+
+/// Number of words the TX and RX FIFOs can hold
+pub const FIFO_DEPTH: usize = 4;
+
+/// SPI interrupt events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// A transfer ended and the bus is idle
+    TransferEnd = 0,
+    /// The TX FIFO holds more free slots than its threshold
+    TxFifoReady = 1,
+    /// The RX FIFO holds more words than its threshold
+    RxFifoReady = 2,
+    /// Slave mode timeout, no SCLK edges within the timeout
+    SlaveTimeout = 3,
+    /// A FIFO overflowed or underflowed
+    FifoError = 5,
+}
+
+/// Bit offsets of the status, mask, clear and enable fields in `spi_int_sts`
+const INT_MASK_OFFSET: u32 = 8;
+const INT_CLR_OFFSET: u32 = 16;
+const INT_EN_OFFSET: u32 = 24;
+
+/// SPI configuration error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SpiConfigError {
+    /// The requested frequency is above the fastest reachable rate
+    FrequencyTooHigh {
+        /// Fastest reachable frequency
+        max: Hertz<u32>,
+    },
+    /// The requested frequency is below the slowest reachable rate
+    FrequencyTooLow {
+        /// Slowest reachable frequency
+        min: Hertz<u32>,
+    },
+    /// A field of [`SpiTiming`] is outside of 1..=256 cycles
+    TimingOutOfRange,
+    /// A FIFO threshold is not below the FIFO depth of 4
+    ThresholdOutOfRange,
+    /// The deglitch filter length is above 15 cycles
+    DeglitchOutOfRange,
+}
+
+impl From<TimeoutError<Error>> for Error {
+    fn from(error: TimeoutError<Error>) -> Self {
+        match error {
+            TimeoutError::Expired => Error::Timeout,
+            TimeoutError::Other(e) => e,
+        }
+    }
 }
 
 impl embedded_hal::spi::Error for Error {
@@ -59,12 +193,18 @@ impl embedded_hal::spi::Error for Error {
             Error::TxUnderflow => {
                 ErrorKind::Other
             }
+            Error::NotSupported => {
+                ErrorKind::Other
+            }
+            Error::Timeout => {
+                ErrorKind::Other
+            }
         }
     }
 }
 
-/// The bit format to send the data in
-#[derive(Debug, Clone, Copy)]
+/// The bit format to send the data in, applies to every byte of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SpiBitFormat {
     /// Least significant bit first
     LsbFirst,
@@ -72,17 +212,106 @@ pub enum SpiBitFormat {
     MsbFirst,
 }
 
+/// The order in which the bytes of a multi-byte frame are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiByteOrder {
+    /// Least significant byte first
+    LsbFirst,
+    /// Most significant byte first
+    MsbFirst,
+}
+
+/// Number of bits transferred per SPI frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSize {
+    /// 8 bit frames
+    Bits8 = 0,
+    /// 16 bit frames
+    Bits16 = 1,
+    /// 24 bit frames
+    Bits24 = 2,
+    /// 32 bit frames
+    Bits32 = 3,
+}
+
+#[allow(clippy::missing_safety_doc)]
+/// Word types that can be transferred - DO NOT IMPLEMENT THIS TRAIT
+pub unsafe trait FrameWord: Copy + Default + 'static {
+    /// Frame size selected when transferring this word type
+    const FRAME_SIZE: FrameSize;
+
+    /// Whether words of this type can be transferred using frames of `size`
+    fn fits(size: FrameSize) -> bool {
+        size == Self::FRAME_SIZE
+    }
+
+    /// Converts an RX FIFO entry into a word
+    fn from_fifo(bits: u32) -> Self;
+
+    /// Converts a word into a TX FIFO entry
+    fn into_fifo(self) -> u32;
+}
+
+unsafe impl FrameWord for u8 {
+    const FRAME_SIZE: FrameSize = FrameSize::Bits8;
+
+    fn from_fifo(bits: u32) -> Self {
+        (bits & 0xff) as u8
+    }
+
+    fn into_fifo(self) -> u32 {
+        self as u32
+    }
+}
+
+unsafe impl FrameWord for u16 {
+    const FRAME_SIZE: FrameSize = FrameSize::Bits16;
+
+    fn from_fifo(bits: u32) -> Self {
+        (bits & 0xffff) as u16
+    }
+
+    fn into_fifo(self) -> u32 {
+        self as u32
+    }
+}
+
+unsafe impl FrameWord for u32 {
+    const FRAME_SIZE: FrameSize = FrameSize::Bits32;
+
+    fn fits(size: FrameSize) -> bool {
+        matches!(size, FrameSize::Bits24 | FrameSize::Bits32)
+    }
+
+    fn from_fifo(bits: u32) -> Self {
+        bits
+    }
+
+    fn into_fifo(self) -> u32 {
+        self
+    }
+}
+
 #[allow(clippy::missing_safety_doc)]
 /// MISO pins - DO NOT IMPLEMENT THIS TRAIT
-pub unsafe trait MisoPin<SPI> {}
+pub unsafe trait MisoPin<SPI> {
+    #[doc(hidden)]
+    const CONNECTED: bool = true;
+}
 
 #[allow(clippy::missing_safety_doc)]
 /// MOSI pins - DO NOT IMPLEMENT THIS TRAIT
-pub unsafe trait MosiPin<SPI> {}
+pub unsafe trait MosiPin<SPI> {
+    #[doc(hidden)]
+    const CONNECTED: bool = true;
+}
 
 #[allow(clippy::missing_safety_doc)]
 /// SS pins - DO NOT IMPLEMENT THIS TRAIT
-pub unsafe trait SsPin<SPI> {}
+pub unsafe trait SsPin<SPI> {
+    #[doc(hidden)]
+    const PIN: u8;
+}
 
 #[allow(clippy::missing_safety_doc)]
 /// SCLK pins - DO NOT IMPLEMENT THIS TRAIT
@@ -90,40 +319,61 @@ pub unsafe trait SclkPin<SPI> {}
 
 #[allow(clippy::missing_safety_doc)]
 /// Spi pins - DO NOT IMPLEMENT THIS TRAIT
-pub unsafe trait Pins<SPI> {}
-
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin0<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin1<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin2<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin3<MODE> {}
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin4<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin5<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin6<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin7<MODE> {}
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin8<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin9<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin10<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin11<MODE> {}
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin12<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin13<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin14<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin15<MODE> {}
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin16<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin17<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin18<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin19<MODE> {}
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin20<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin21<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin22<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin23<MODE> {}
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin24<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin25<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin26<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin27<MODE> {}
-unsafe impl<MODE> MosiPin<pac::SPI> for crate::gpio::Pin28<MODE> {}
-unsafe impl<MODE> MisoPin<pac::SPI> for crate::gpio::Pin29<MODE> {}
-unsafe impl<MODE> SsPin<pac::SPI> for crate::gpio::Pin30<MODE> {}
-unsafe impl<MODE> SclkPin<pac::SPI> for crate::gpio::Pin31<MODE> {}
+pub unsafe trait Pins<SPI> {
+    #[doc(hidden)]
+    const SS: Option<u8> = None;
+    #[doc(hidden)]
+    const MISO: bool;
+    #[doc(hidden)]
+    const MOSI: bool;
+}
+
+/// Placeholder for the MISO pin of a write-only bus
+pub struct NoMiso;
+
+/// Placeholder for the MOSI pin of a read-only bus
+pub struct NoMosi;
+
+unsafe impl MisoPin<pac::SPI> for NoMiso {
+    const CONNECTED: bool = false;
+}
+
+unsafe impl MosiPin<pac::SPI> for NoMosi {
+    const CONNECTED: bool = false;
+}
+
+unsafe impl MosiPin<pac::SPI> for gpio::Pin0<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin1<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin2<gpio::Spi> { const PIN: u8 = 2; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin3<gpio::Spi> {}
+unsafe impl MosiPin<pac::SPI> for gpio::Pin4<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin5<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin6<gpio::Spi> { const PIN: u8 = 6; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin7<gpio::Spi> {}
+unsafe impl MosiPin<pac::SPI> for gpio::Pin8<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin9<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin10<gpio::Spi> { const PIN: u8 = 10; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin11<gpio::Spi> {}
+unsafe impl MosiPin<pac::SPI> for gpio::Pin12<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin13<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin14<gpio::Spi> { const PIN: u8 = 14; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin15<gpio::Spi> {}
+unsafe impl MosiPin<pac::SPI> for gpio::Pin16<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin17<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin18<gpio::Spi> { const PIN: u8 = 18; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin19<gpio::Spi> {}
+unsafe impl MosiPin<pac::SPI> for gpio::Pin20<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin21<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin22<gpio::Spi> { const PIN: u8 = 22; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin23<gpio::Spi> {}
+unsafe impl MosiPin<pac::SPI> for gpio::Pin24<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin25<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin26<gpio::Spi> { const PIN: u8 = 26; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin27<gpio::Spi> {}
+unsafe impl MosiPin<pac::SPI> for gpio::Pin28<gpio::Spi> {}
+unsafe impl MisoPin<pac::SPI> for gpio::Pin29<gpio::Spi> {}
+unsafe impl SsPin<pac::SPI> for gpio::Pin30<gpio::Spi> { const PIN: u8 = 30; }
+unsafe impl SclkPin<pac::SPI> for gpio::Pin31<gpio::Spi> {}
 
 unsafe impl<MISO, MOSI, SS, SCLK> Pins<SPI> for (MISO, MOSI, SS, SCLK)
 where
@@ -131,20 +381,279 @@ where
     MOSI: MosiPin<SPI>,
     SS: SsPin<SPI>,
     SCLK: SclkPin<SPI>,
-{}
+{
+    const SS: Option<u8> = Some(SS::PIN);
+    const MISO: bool = MISO::CONNECTED;
+    const MOSI: bool = MOSI::CONNECTED;
+}
 
 unsafe impl<MISO, MOSI, SCLK> Pins<SPI> for (MISO, MOSI, SCLK)
 where
     MISO: MisoPin<SPI>,
     MOSI: MosiPin<SPI>,
     SCLK: SclkPin<SPI>,
-{}
+{
+    const MISO: bool = MISO::CONNECTED;
+    const MOSI: bool = MOSI::CONNECTED;
+}
+
+unsafe impl<MOSI, SCLK> Pins<SPI> for (MOSI, SCLK)
+where
+    MOSI: MosiPin<SPI>,
+    SCLK: SclkPin<SPI>,
+{
+    const MISO: bool = false;
+    const MOSI: bool = MOSI::CONNECTED;
+}
 
 /// A Serial Peripheral Interface
 pub struct Spi<SPI, PINS> {
     spi: SPI,
     pins: PINS,
-    delay: McycleDelay
+    delay: McycleDelay,
+    frame_size: FrameSize,
+    cs_continuous: bool,
+    cs_held: bool,
+    timeout: Option<u64>,
+}
+
+/// Length of the bus phases of a transfer, in spi clock cycles.
+///
+/// Every field must be between 1 and 256 cycles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiTiming {
+    /// Start condition: from asserting CS to the first SCLK edge
+    pub start: u16,
+    /// Stop condition: from the last SCLK edge to releasing CS
+    pub stop: u16,
+    /// Data phase 0: first half of every SCLK period
+    pub phase0: u16,
+    /// Data phase 1: second half of every SCLK period
+    pub phase1: u16,
+    /// Interval: idle time between two frames
+    pub interval: u16,
+}
+
+impl SpiTiming {
+    /// Use the same number of cycles for every phase
+    pub const fn symmetric(cycles: u16) -> Self {
+        SpiTiming {
+            start: cycles,
+            stop: cycles,
+            phase0: cycles,
+            phase1: cycles,
+            interval: cycles,
+        }
+    }
+
+    /// Symmetric timing reaching `freq` with the shortest inter-frame interval,
+    /// this is what `Spi::new` uses
+    pub fn from_frequency(freq: Hertz<u32>, clocks: &Clocks) -> Result<Self, SpiConfigError> {
+        // length of phase 0 and 1 (i.e. low / high values of SCLK)
+        // needs to be divided by two
+        let spi_clk = clocks.spi_clk().0;
+        let len = spi_clk / freq.0.max(1) / 2;
+        if len == 0 {
+            Err(SpiConfigError::FrequencyTooHigh {
+                max: Hertz(spi_clk / 2),
+            })
+        } else if len > 256 {
+            Err(SpiConfigError::FrequencyTooLow {
+                min: Hertz(spi_clk.div_ceil(512)),
+            })
+        } else {
+            Ok(SpiTiming {
+                interval: 1,
+                ..Self::symmetric(len as u16)
+            })
+        }
+    }
+
+    /// Register values of all fields, in the order of the struct
+    fn register_values(&self) -> Result<[u8; 5], SpiConfigError> {
+        let mut values = [0; 5];
+        let fields = [self.start, self.stop, self.phase0, self.phase1, self.interval];
+        for (value, cycles) in values.iter_mut().zip(fields) {
+            if !(1..=256).contains(&cycles) {
+                return Err(SpiConfigError::TimingOutOfRange);
+            }
+            *value = (cycles - 1) as u8;
+        }
+        Ok(values)
+    }
+}
+
+/// SPI configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// SCLK frequency
+    pub frequency: Hertz<u32>,
+    /// Clock polarity and phase
+    pub mode: Mode,
+    /// Initial frame size, transfers still switch to fit their word type
+    pub frame_size: FrameSize,
+    /// Bit order within each byte
+    pub bit_order: SpiBitFormat,
+    /// Byte order within multi-byte frames
+    pub byte_order: SpiByteOrder,
+    /// See [`Spi::cs_continuous`]
+    pub cs_continuous: bool,
+    /// See [`Spi::swap_mosi_miso`]
+    pub swap_mosi_miso: bool,
+    /// See [`Spi::set_deglitch`]
+    pub deglitch: Option<u8>,
+    /// See [`Spi::set_timeout`]
+    pub timeout: Option<Microseconds<u32>>,
+    /// Individual phase lengths, overrides `frequency` when set
+    pub timing: Option<SpiTiming>,
+}
+
+impl Config {
+    /// Sets the SCLK frequency
+    pub fn frequency(mut self, frequency: impl Into<Hertz<u32>>) -> Self {
+        self.frequency = frequency.into();
+
+        self
+    }
+
+    /// Sets the clock polarity and phase
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+
+        self
+    }
+
+    /// Sets the initial frame size
+    pub fn frame_size(mut self, frame_size: FrameSize) -> Self {
+        self.frame_size = frame_size;
+
+        self
+    }
+
+    /// Sets the bit order within each byte
+    pub fn bit_order(mut self, bit_order: SpiBitFormat) -> Self {
+        self.bit_order = bit_order;
+
+        self
+    }
+
+    /// Sets the byte order within multi-byte frames
+    pub fn byte_order(mut self, byte_order: SpiByteOrder) -> Self {
+        self.byte_order = byte_order;
+
+        self
+    }
+
+    /// Keep CS asserted during multi-word operations
+    pub fn cs_continuous(mut self, cs_continuous: bool) -> Self {
+        self.cs_continuous = cs_continuous;
+
+        self
+    }
+
+    /// Swap the MOSI and MISO functions of the pads
+    pub fn swap_mosi_miso(mut self, swap: bool) -> Self {
+        self.swap_mosi_miso = swap;
+
+        self
+    }
+
+    /// Filter input glitches shorter than `cycles` spi clock cycles
+    ///
+    /// Fails with `SpiConfigError::DeglitchOutOfRange` above 15 cycles.
+    pub fn deglitch(mut self, cycles: Option<u8>) -> Result<Self, SpiConfigError> {
+        if cycles.is_some_and(|cycles| cycles > 15) {
+            return Err(SpiConfigError::DeglitchOutOfRange);
+        }
+        self.deglitch = cycles;
+
+        Ok(self)
+    }
+
+    /// Sets the timeout for blocking operations
+    pub fn timeout(mut self, timeout: Option<Microseconds<u32>>) -> Self {
+        self.timeout = timeout;
+
+        self
+    }
+
+    /// Sets the individual phase lengths instead of deriving them from the frequency
+    ///
+    /// Fails with `SpiConfigError::TimingOutOfRange` if a field is outside of 1..=256 cycles.
+    pub fn timing(mut self, timing: SpiTiming) -> Result<Self, SpiConfigError> {
+        timing.register_values()?;
+        self.timing = Some(timing);
+
+        Ok(self)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            frequency: Hertz(1_000_000),
+            mode: embedded_hal::spi::MODE_0,
+            frame_size: FrameSize::Bits8,
+            bit_order: SpiBitFormat::MsbFirst,
+            byte_order: SpiByteOrder::MsbFirst,
+            cs_continuous: false,
+            swap_mosi_miso: false,
+            deglitch: None,
+            timeout: None,
+            timing: None,
+        }
+    }
+}
+
+/// Values of the `cr_spi_sclk_pol` and `cr_spi_sclk_ph` bits for `mode`.
+///
+/// `cr_spi_sclk_ph` inverts the clock phase, so it is set for `CaptureOnFirstTransition`.
+/// This matches the mode table of the bouffalo_sdk SPI driver.
+fn mode_bits(mode: Mode) -> (bool, bool) {
+    let pol = match mode.polarity {
+        embedded_hal::spi::Polarity::IdleLow => false,
+        embedded_hal::spi::Polarity::IdleHigh => true,
+    };
+    let ph = match mode.phase {
+        embedded_hal::spi::Phase::CaptureOnFirstTransition => true,
+        embedded_hal::spi::Phase::CaptureOnSecondTransition => false,
+    };
+    (pol, ph)
+}
+
+/// Mode for the `cr_spi_sclk_pol` and `cr_spi_sclk_ph` bits, the inverse of [`mode_bits`]
+fn mode_from_bits(pol: bool, ph: bool) -> Mode {
+    Mode {
+        polarity: if pol {
+            embedded_hal::spi::Polarity::IdleHigh
+        } else {
+            embedded_hal::spi::Polarity::IdleLow
+        },
+        phase: if ph {
+            embedded_hal::spi::Phase::CaptureOnFirstTransition
+        } else {
+            embedded_hal::spi::Phase::CaptureOnSecondTransition
+        },
+    }
+}
+
+/// Program the phase lengths of `timing`
+fn write_timing(spi: &SPI, timing: &SpiTiming) -> Result<(), SpiConfigError> {
+    let [start, stop, phase0, phase1, interval] = timing.register_values()?;
+    spi.spi_prd_0.modify(|_r, w| unsafe {
+        w.cr_spi_prd_s()
+            .bits(start)
+            .cr_spi_prd_p()
+            .bits(stop)
+            .cr_spi_prd_d_ph_0()
+            .bits(phase0)
+            .cr_spi_prd_d_ph_1()
+            .bits(phase1)
+    });
+
+    spi.spi_prd_1
+        .modify(|_r, w| unsafe { w.cr_spi_prd_i().bits(interval) });
+    Ok(())
 }
 
 impl<PINS> Spi<pac::SPI, PINS>
@@ -153,69 +662,144 @@ where
 {
     /**
     Constructs an SPI instance in 8bit dataframe mode.
+    The frame size follows the word type of each transfer, see [`Spi::set_frame_size`].
     The pin parameter tuple (miso, mosi, cs, sck) needs to be configured accordingly.
     You can also omit `cs` to have manual control over `cs`.
     The frequency cannot be more than half of the spi clock frequency.
+
+    Panics if the frequency cannot be reached, use [`Spi::try_new`] to handle this instead.
     */
     pub fn new(spi: SPI, pins: PINS, mode: Mode, freq: Hertz<u32>, clocks: Clocks) -> Self
     where
         PINS: Pins<pac::SPI>,
     {
+        match Self::try_new(spi, pins, mode, freq, clocks) {
+            Ok(spi) => spi,
+            Err(_) => panic!("Cannot reach the desired SPI frequency"),
+        }
+    }
+
+    /**
+    Constructs an SPI instance in 8bit dataframe mode, like [`Spi::new`].
+    Returns an error if `freq` is outside of the range reachable from the spi clock:
+    between half of the spi clock and 1/512th of it.
+    */
+    pub fn try_new(
+        spi: SPI,
+        pins: PINS,
+        mode: Mode,
+        freq: Hertz<u32>,
+        clocks: Clocks,
+    ) -> Result<Self, SpiConfigError>
+    where
+        PINS: Pins<pac::SPI>,
+    {
+        Self::with_config(spi, pins, Config::default().mode(mode).frequency(freq), clocks)
+    }
+
+    /**
+    Constructs an SPI instance from `config`.
+    The pin parameter tuple (miso, mosi, cs, sck) needs to be configured accordingly.
+    You can also omit `cs` to have manual control over `cs`.
+
+    The `Config` builder checks its ranges as they are set, the frequency is checked here
+    against the spi clock: it must be between half of the spi clock and 1/512th of it. Nothing
+    is written to the peripheral unless the whole configuration is valid.
+    */
+    pub fn with_config(
+        spi: SPI,
+        pins: PINS,
+        config: Config,
+        clocks: Clocks,
+    ) -> Result<Self, SpiConfigError>
+    where
+        PINS: Pins<pac::SPI>,
+    {
+        debug_assert!(clocks.is_current(), "clocks are stale after a system clock switch");
+        let timing = match config.timing {
+            Some(timing) => timing,
+            None => SpiTiming::from_frequency(config.frequency, &clocks)?,
+        };
+        timing.register_values()?;
+        if config.deglitch.is_some_and(|cycles| cycles > 15) {
+            return Err(SpiConfigError::DeglitchOutOfRange);
+        }
+
+        crate::clock::enable_gate(Gate::Spi);
         let glb = unsafe { &*pac::GLB::ptr() };
 
         glb.glb_parm.modify(|_r, w| {
-            w.reg_spi_0_master_mode().set_bit()
-            //.reg_spi_0_swap()
-            //.set_bit()
-        });
-
-        // length of phase 0 and 1 (i.e. low / high values of SCLK)
-        // needs to be divided by two
-        let len = clocks.spi_clk().0 / freq.0 / 2;
-        if len > 256 || len == 0 {
-            panic!("Cannot reach the desired SPI frequency");
-        }
-
-        let len = (len - 1) as u8;
-        spi.spi_prd_0.modify(|_r, w| unsafe {
-            w.cr_spi_prd_s()
-                .bits(len)
-                .cr_spi_prd_p()
-                .bits(len)
-                .cr_spi_prd_d_ph_0()
-                .bits(len)
-                .cr_spi_prd_d_ph_1()
-                .bits(len)
+            w.reg_spi_0_master_mode()
+                .set_bit()
+                .reg_spi_0_swap()
+                .bit(config.swap_mosi_miso)
         });
 
-        spi.spi_prd_1
-            .modify(|_r, w| unsafe { w.cr_spi_prd_i().bits(len) });
+        write_timing(&spi, &timing)?;
 
         spi.spi_config.modify(|_, w| unsafe {
+            let (pol, ph) = mode_bits(config.mode);
             w.cr_spi_sclk_pol()
-                .bit(match mode.polarity {
-                    embedded_hal::spi::Polarity::IdleLow => false,
-                    embedded_hal::spi::Polarity::IdleHigh => true,
-                })
+                .bit(pol)
                 .cr_spi_sclk_ph()
-                .bit(match mode.phase {
-                    embedded_hal::spi::Phase::CaptureOnFirstTransition => true,
-                    embedded_hal::spi::Phase::CaptureOnSecondTransition => false,
-                })
+                .bit(ph)
                 .cr_spi_m_cont_en()
-                .clear_bit() // disable cont mode
+                .bit(config.cs_continuous)
                 .cr_spi_frame_size()
-                .bits(0) // 8 bit frames
+                .bits(config.frame_size as u8)
+                .cr_spi_bit_inv()
+                .bit(config.bit_order == SpiBitFormat::LsbFirst)
+                .cr_spi_byte_inv()
+                .bit(config.byte_order == SpiByteOrder::MsbFirst)
+                .cr_spi_deg_en()
+                .bit(config.deglitch.is_some())
+                .cr_spi_deg_cnt()
+                .bits(config.deglitch.unwrap_or(0))
                 .cr_spi_s_en()
                 .clear_bit() // not slave
                 .cr_spi_m_en()
                 .set_bit() // master
         });
 
-        Spi { spi, pins, delay: McycleDelay::new(clocks.sysclk().0) }
+        let mut spi = Spi {
+            spi,
+            pins,
+            delay: McycleDelay::new(clocks.sysclk().0),
+            frame_size: config.frame_size,
+            cs_continuous: config.cs_continuous,
+            cs_held: false,
+            timeout: None,
+        };
+        spi.set_timeout(config.timeout);
+        Ok(spi)
     }
 
+    /// Stop the peripheral, return it to its reset state and hand back the peripheral and pins.
+    ///
+    /// Waits for the bus to become idle, disables master mode, clears the FIFOs and pulses the
+    /// SPI software reset in GLB, so a later `Spi::new` starts from power-on defaults. The pins
+    /// keep their SPI function until they are reconfigured with the usual `into_*` methods.
     pub fn release(self) -> (pac::SPI, PINS) {
+        while self.is_busy() {}
+        self.spi
+            .spi_config
+            .modify(|_, w| w.cr_spi_m_en().clear_bit().cr_spi_s_en().clear_bit());
+        self.spi
+            .spi_fifo_config_0
+            .write(|w| w.rx_fifo_clr().set_bit().tx_fifo_clr().set_bit());
+
+        let glb = unsafe { &*pac::GLB::ptr() };
+        glb.glb_parm.modify(|_, w| {
+            w.reg_spi_0_master_mode()
+                .clear_bit()
+                .reg_spi_0_swap()
+                .clear_bit()
+        });
+        // swrst_s1a2 resets the SPI block
+        glb.swrst_cfg1.modify(|_, w| w.swrst_s1a2().clear_bit());
+        glb.swrst_cfg1.modify(|_, w| w.swrst_s1a2().set_bit());
+        glb.swrst_cfg1.modify(|_, w| w.swrst_s1a2().clear_bit());
+
         (self.spi, self.pins)
     }
 
@@ -233,115 +817,772 @@ where
         }
     }
 
-    /// Clear FIFOs
-    pub fn clear_fifo(&mut self) {
+    /// Change the clock polarity and phase, e.g. to talk to devices using different modes.
+    ///
+    /// Waits for the bus to become idle before switching.
+    pub fn set_mode(&mut self, mode: Mode) {
+        let (pol, ph) = mode_bits(mode);
+        while self.is_busy() {}
         self.spi
-            .spi_fifo_config_0
-            .write(|w| w.rx_fifo_clr().set_bit().tx_fifo_clr().set_bit());
+            .spi_config
+            .modify(|_, w| w.cr_spi_sclk_pol().bit(pol).cr_spi_sclk_ph().bit(ph));
     }
-}
 
-impl<PINS> ErrorType for Spi<SPI, PINS> where PINS: Pins<pac::SPI>, { type Error = Error; }
+    /// Returns the current clock polarity and phase
+    pub fn mode(&self) -> Mode {
+        let config = self.spi.spi_config.read();
+        mode_from_bits(
+            config.cr_spi_sclk_pol().bit_is_set(),
+            config.cr_spi_sclk_ph().bit_is_set(),
+        )
+    }
 
-impl<PINS> SpiBus for Spi<pac::SPI, PINS>
-where
-    PINS: Pins<pac::SPI>,
-{
-    fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
-        for word in words {
-            nb::block!(FullDuplex::write(self, 0x00))?;
-            *word = nb::block!(FullDuplex::read(self))?;
+    /// Select the bit order within each byte and the byte order within multi-byte frames.
+    ///
+    /// Waits for the bus to become idle before switching. The `u16` word `0x1234` is sent as:
+    ///
+    /// | bits       | bytes      | on the wire                |
+    /// |------------|------------|----------------------------|
+    /// | `MsbFirst` | `MsbFirst` | `00010010 00110100`        |
+    /// | `MsbFirst` | `LsbFirst` | `00110100 00010010`        |
+    /// | `LsbFirst` | `MsbFirst` | `01001000 00101100`        |
+    /// | `LsbFirst` | `LsbFirst` | `00101100 01001000`        |
+    ///
+    /// The last row is a true LSB first 16 bit frame. The default is `MsbFirst`, `MsbFirst`.
+    ///
+    /// The table is derived from the description of `cr_spi_bit_inv` and `cr_spi_byte_inv` in
+    /// the reference manual. No logic analyzer capture has been recorded for it yet, so check
+    /// the first frames on the bus before relying on the `LsbFirst` rows.
+    pub fn set_format(&mut self, bits: SpiBitFormat, bytes: SpiByteOrder) {
+        while self.is_busy() {}
+        self.spi.spi_config.modify(|_, w| {
+            w.cr_spi_bit_inv()
+                .bit(bits == SpiBitFormat::LsbFirst)
+                .cr_spi_byte_inv()
+                .bit(bytes == SpiByteOrder::MsbFirst)
+        });
+    }
+
+    /// Change the SCLK frequency, e.g. to speed up after initialising an SD card.
+    ///
+    /// Waits for the bus to become idle before switching. Polarity, phase and the interval
+    /// set with [`set_interframe_delay`](Self::set_interframe_delay) are kept. Returns an error
+    /// and leaves the frequency unchanged if `freq` cannot be reached.
+    pub fn set_frequency(
+        &mut self,
+        freq: Hertz<u32>,
+        clocks: &Clocks,
+    ) -> Result<(), SpiConfigError> {
+        let timing = SpiTiming {
+            interval: self.timing().interval,
+            ..SpiTiming::from_frequency(freq, clocks)?
+        };
+        self.set_timing(timing)
+    }
+
+    /// Program the length of every bus phase individually, e.g. for a longer CS setup time.
+    ///
+    /// Waits for the bus to become idle before switching. Returns an error and leaves the
+    /// timing unchanged if a field is out of range.
+    pub fn set_timing(&mut self, timing: SpiTiming) -> Result<(), SpiConfigError> {
+        timing.register_values()?;
+        while self.is_busy() {}
+        write_timing(&self.spi, &timing)
+    }
+
+    /// Set the idle time between two frames to at least `ns` nanoseconds.
+    ///
+    /// The gap is a whole number of spi clock cycles, between 1 and 256. The default spi clock
+    /// is the 72 MHz bus clock divided by 4, 18 MHz, so one cycle is about 56 ns and the gap
+    /// ranges from 56 ns to 14.2 us. These figures are derived from the clock dividers, no gap
+    /// has been measured on the bus yet. By default the shortest gap of one cycle is used,
+    /// changing the frequency keeps the gap. Waits for the bus to become idle.
+    pub fn set_interframe_delay(
+        &mut self,
+        ns: u32,
+        clocks: &Clocks,
+    ) -> Result<(), SpiConfigError> {
+        let cycles = (ns as u64 * clocks.spi_clk().0 as u64).div_ceil(1_000_000_000).max(1);
+        if cycles > 256 {
+            return Err(SpiConfigError::TimingOutOfRange);
         }
-        Ok(())
+        let timing = SpiTiming {
+            interval: cycles as u16,
+            ..self.timing()
+        };
+        self.set_timing(timing)
     }
 
-    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
-        for word in words {
-            nb::block!(FullDuplex::write(self, *word))?;
-            nb::block!(FullDuplex::read(self))?;
+    /// Returns the currently programmed phase lengths
+    pub fn timing(&self) -> SpiTiming {
+        let prd_0 = self.spi.spi_prd_0.read();
+        SpiTiming {
+            start: prd_0.cr_spi_prd_s().bits() as u16 + 1,
+            stop: prd_0.cr_spi_prd_p().bits() as u16 + 1,
+            phase0: prd_0.cr_spi_prd_d_ph_0().bits() as u16 + 1,
+            phase1: prd_0.cr_spi_prd_d_ph_1().bits() as u16 + 1,
+            interval: self.spi.spi_prd_1.read().cr_spi_prd_i().bits() as u16 + 1,
+        }
+    }
+
+    /// Keep CS asserted for the whole of every multi-word `SpiBus` operation.
+    ///
+    /// Without this the hardware releases CS after every frame. This also enables the continuous
+    /// mode of the peripheral, so frames written back to back share one CS assertion.
+    /// `SpiDevice::transaction` always holds CS across all of its operations.
+    /// Only has an effect if a CS pin was passed to the constructor.
+    pub fn cs_continuous(&mut self, enable: bool) {
+        while self.is_busy() {}
+        self.spi
+            .spi_config
+            .modify(|_, w| w.cr_spi_m_cont_en().bit(enable));
+        self.cs_continuous = enable;
+    }
+
+    /// Run `f` with CS held asserted if `hold` is set.
+    ///
+    /// The hardware only keeps CS asserted while the TX FIFO is fed, so the CS pad is driven
+    /// low as a GPIO instead and handed back to the peripheral once the bus is idle, also when
+    /// `f` fails. Nested calls keep the outermost hold.
+    fn with_cs<R>(
+        &mut self,
+        hold: bool,
+        f: impl FnOnce(&mut Self) -> Result<R, Error>,
+    ) -> Result<R, Error> {
+        let pin = match PINS::SS {
+            Some(pin) if hold && !self.cs_held => pin,
+            _ => return f(self),
+        };
+
+        self.wait_idle()?;
+        gpio::pad::set_output(pin, false);
+        gpio::pad::set_output_enable(pin, true);
+        gpio::pad::set_function(pin, gpio::pad::FUNC_SWGPIO);
+        self.cs_held = true;
+
+        let result = f(self);
+        let idle = self.wait_idle();
+
+        gpio::pad::set_function(pin, gpio::pad::FUNC_SPI);
+        gpio::pad::set_output_enable(pin, false);
+        self.cs_held = false;
+        let result = result?;
+        idle.map(|_| result)
+    }
+
+    /// Swap the MOSI and MISO functions of the pads.
+    ///
+    /// This only flips the routing inside the peripheral, the pins still have to be passed
+    /// according to their default function. Waits for the bus to become idle before switching.
+    pub fn swap_mosi_miso(&mut self, swap: bool) {
+        while self.is_busy() {}
+        let glb = unsafe { &*pac::GLB::ptr() };
+        glb.glb_parm.modify(|_, w| w.reg_spi_0_swap().bit(swap));
+    }
+
+    /// Filter glitches shorter than `cycles` spi clock cycles on the inputs, `None` disables the
+    /// filter. At most 15 cycles are supported.
+    pub fn set_deglitch(&mut self, cycles: Option<u8>) -> Result<(), SpiConfigError> {
+        if cycles.is_some_and(|cycles| cycles > 15) {
+            return Err(SpiConfigError::DeglitchOutOfRange);
         }
+        while self.is_busy() {}
+        self.spi.spi_config.modify(|_, w| unsafe {
+            w.cr_spi_deg_en()
+                .bit(cycles.is_some())
+                .cr_spi_deg_cnt()
+                .bits(cycles.unwrap_or(0))
+        });
         Ok(())
     }
 
-    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
-        let read_len = read.len();
-        let write_len = write.len();
-        if read_len == write_len {
-            // Equal read and write length
+    /// Give up blocking operations that make no progress for `timeout`, they return
+    /// `Error::Timeout`. `None` waits forever, which is the default.
+    pub fn set_timeout(&mut self, timeout: Option<Microseconds<u32>>) {
+        let cycles_per_us = self.delay.core_frequency() as u64 / 1_000_000;
+        self.timeout = timeout.map(|timeout| timeout.0 as u64 * cycles_per_us);
+    }
 
-            for idx in 0..read_len {
-                nb::block!(FullDuplex::write(self, write[idx]))?;
-                read[idx] = nb::block!(FullDuplex::read(self))?;
-            }
-        } else if read_len < write_len {
-            // Read buffer is shorter
+    /// Select the number of bits per frame.
+    ///
+    /// Waits for the bus to become idle before switching. Transfers switch the frame size
+    /// automatically when the word type does not fit the current one, so this is only needed
+    /// to select 24 bit frames for `u32` words.
+    /// The byte order of multi-byte frames is selected with [`Spi::set_format`].
+    pub fn set_frame_size(&mut self, size: FrameSize) {
+        while self.is_busy() {}
 
-            for idx in 0..read_len {
-                nb::block!(FullDuplex::write(self, write[idx]))?;
-                read[idx] = nb::block!(FullDuplex::read(self))?;
-            }
-            for idx in read_len..write_len {
-                nb::block!(FullDuplex::write(self, write[idx]))?;
-            }
-        } else {
-            // Write buffer is shorter
-            for idx in 0..write_len {
-                nb::block!(FullDuplex::write(self, write[idx]))?;
-                read[idx] = nb::block!(FullDuplex::read(self))?;
-            }
-            for idx in write_len..read_len {
-                read[idx] = nb::block!(FullDuplex::read(self))?;
-            }
+        self.spi
+            .spi_config
+            .modify(|_, w| unsafe { w.cr_spi_frame_size().bits(size as u8) });
+        self.frame_size = size;
+    }
+
+    /// Returns the currently selected frame size
+    pub fn frame_size(&self) -> FrameSize {
+        self.frame_size
+    }
+
+    /// Switch to the default frame size of `W` if the current one doesn't fit
+    fn select_frame_size<W: FrameWord>(&mut self) {
+        if !W::fits(self.frame_size) {
+            self.set_frame_size(W::FRAME_SIZE);
         }
+    }
 
-        Ok(())
+    /// Start generating an interrupt for `event`
+    pub fn listen(&mut self, event: Event) {
+        let bit = event as u32;
+        self.spi.spi_int_sts.modify(|r, w| unsafe {
+            w.bits((r.bits() | 1 << (bit + INT_EN_OFFSET)) & !(1 << (bit + INT_MASK_OFFSET)))
+        });
     }
 
-    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
-        for word in words.iter_mut() {
-            nb::block!(FullDuplex::write(self, *word))?;
-            *word = nb::block!(FullDuplex::read(self))?;
+    /// Stop generating an interrupt for `event`
+    pub fn unlisten(&mut self, event: Event) {
+        let bit = event as u32;
+        self.spi.spi_int_sts.modify(|r, w| unsafe {
+            w.bits((r.bits() | 1 << (bit + INT_MASK_OFFSET)) & !(1 << (bit + INT_EN_OFFSET)))
+        });
+    }
+
+    /// Returns true if the status flag of `event` is set, regardless of whether it is listened to
+    pub fn is_pending(&self, event: Event) -> bool {
+        self.spi.spi_int_sts.read().bits() & (1 << event as u32) != 0
+    }
+
+    /// Clear the status flag of `event`.
+    ///
+    /// Only `TransferEnd` and `SlaveTimeout` are latched, the FIFO events follow the FIFO
+    /// levels and `FifoError` clears together with the FIFO error flags.
+    pub fn clear_interrupt(&mut self, event: Event) {
+        if let Event::TransferEnd | Event::SlaveTimeout = event {
+            let bit = event as u32;
+            self.spi
+                .spi_int_sts
+                .modify(|r, w| unsafe { w.bits(r.bits() | 1 << (bit + INT_CLR_OFFSET)) });
         }
-        Ok(())
     }
 
-    fn flush(&mut self) -> Result<(), Self::Error> {
-        while self.spi.spi_bus_busy.read().sts_spi_bus_busy().bit_is_set() {}
+    /// Set the FIFO thresholds used by `TxFifoReady`, `RxFifoReady` and by the DMA requests.
+    ///
+    /// `TxFifoReady` is raised while more than `tx` slots are free, `RxFifoReady` while more
+    /// than `rx` words are received. Both must be below the FIFO depth of 4.
+    pub fn set_fifo_thresholds(&mut self, tx: u8, rx: u8) -> Result<(), SpiConfigError> {
+        if tx as usize >= FIFO_DEPTH || rx as usize >= FIFO_DEPTH {
+            return Err(SpiConfigError::ThresholdOutOfRange);
+        }
+        self.spi
+            .spi_fifo_config_1
+            .modify(|_, w| unsafe { w.tx_fifo_th().bits(tx).rx_fifo_th().bits(rx) });
         Ok(())
     }
-}
 
-impl<PINS> SpiDevice for Spi<pac::SPI, PINS>
-where PINS: Pins<SPI> {
-    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
-        for operation in operations {
-            match operation {
-                Operation::Read(read) => {
-                    SpiBus::read(self, read)?
-                }
-                Operation::Write(write) => {
-                    SpiBus::write(self, write)?
-                }
-                Operation::Transfer(read, write) => {
-                    SpiBus::transfer(self, read, write)?
-                }
-                Operation::TransferInPlace(transfer) => {
-                    SpiBus::transfer_in_place(self, transfer)?
+    /// Returns `Error::NotSupported` if the pins for a requested direction are missing
+    fn check_directions(&self, reading: bool, writing: bool) -> Result<(), Error> {
+        if (reading && !PINS::MISO) || (writing && !PINS::MOSI) {
+            Err(Error::NotSupported)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Shift `len` words, sending `tx(idx)` and passing every received word to `rx(idx, word)`.
+    ///
+    /// Up to [`FIFO_DEPTH`] words are kept in flight, so frames go out back to back instead of
+    /// waiting for every word to be received before sending the next one. The RX FIFO is drained
+    /// whenever it holds data; it never holds more than the words in flight, so it can't overflow.
+    fn pipeline<W: FrameWord>(
+        &mut self,
+        len: usize,
+        mut tx: impl FnMut(usize) -> W,
+        mut rx: impl FnMut(usize, W),
+    ) -> Result<(), Error> {
+        let mut sent = 0;
+        let mut received = 0;
+        let mut progress = McycleDelay::get_cycle_count();
+        while received < len {
+            if sent < len && sent - received < FIFO_DEPTH {
+                match FullDuplex::<W>::write(self, tx(sent)) {
+                    Ok(()) => {
+                        sent += 1;
+                        progress = McycleDelay::get_cycle_count();
+                    }
+                    Err(nb::Error::WouldBlock) => {}
+                    Err(nb::Error::Other(e)) => return Err(e),
                 }
-                Operation::DelayNs(delay) => {
-                    self.delay.delay_ns(*delay)
+            }
+            match FullDuplex::<W>::read(self) {
+                Ok(word) => {
+                    rx(received, word);
+                    received += 1;
+                    progress = McycleDelay::get_cycle_count();
                 }
+                Err(nb::Error::WouldBlock) => {}
+                Err(nb::Error::Other(e)) => return Err(e),
+            }
+            if self.timed_out(progress) {
+                // drop the words still in flight so the next operation starts clean
+                self.spi
+                    .spi_fifo_config_0
+                    .modify(|_, w| w.tx_fifo_clr().set_bit().rx_fifo_clr().set_bit());
+                return Err(Error::Timeout);
             }
         }
         Ok(())
     }
-}
 
-impl<PINS> FullDuplex<u8> for Spi<pac::SPI, PINS>
-where
-    PINS: Pins<pac::SPI>,
+    /// Returns true while a frame is being shifted or the TX FIFO holds data to be sent
+    pub fn is_busy(&self) -> bool {
+        self.spi.spi_bus_busy.read().sts_spi_bus_busy().bit_is_set()
+    }
+
+    /// Number of free slots in the TX FIFO
+    pub fn tx_fifo_free(&self) -> u8 {
+        self.spi.spi_fifo_config_1.read().tx_fifo_cnt().bits()
+    }
+
+    /// Number of received words waiting in the RX FIFO
+    pub fn rx_fifo_available(&self) -> u8 {
+        self.spi.spi_fifo_config_1.read().rx_fifo_cnt().bits()
+    }
+
+    /// Push as many words of `data` into the TX FIFO as fit right now, without blocking.
+    /// Returns the number of words written.
+    ///
+    /// Every word written also clocks in a word, drain those with [`Spi::read_available`].
+    pub fn write_available<W: FrameWord>(&mut self, data: &[W]) -> usize {
+        self.select_frame_size::<W>();
+        let count = data.len().min(self.tx_fifo_free() as usize);
+        for word in &data[..count] {
+            self.spi
+                .spi_fifo_wdata
+                .write(|w| unsafe { w.bits(word.into_fifo()) });
+        }
+        count
+    }
+
+    /// Pop as many received words into `buf` as are available right now, without blocking.
+    /// Returns the number of words read.
+    pub fn read_available<W: FrameWord>(&mut self, buf: &mut [W]) -> usize {
+        self.select_frame_size::<W>();
+        let count = buf.len().min(self.rx_fifo_available() as usize);
+        for word in &mut buf[..count] {
+            *word = W::from_fifo(self.spi.spi_fifo_rdata.read().bits());
+        }
+        count
+    }
+
+    /// Non-blocking `flush`, returns `WouldBlock` while the bus is busy.
+    ///
+    /// The transfer-end status can also be checked without enabling its interrupt using
+    /// `is_pending(Event::TransferEnd)` and cleared with `clear_interrupt(Event::TransferEnd)`.
+    pub fn poll_flush(&self) -> nb::Result<(), Error> {
+        if self.is_busy() {
+            Err(nb::Error::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns true if the timeout elapsed since the cycle count `start`
+    fn timed_out(&self, start: u64) -> bool {
+        self.timeout
+            .is_some_and(|timeout| McycleDelay::cycles_since(start) > timeout)
+    }
+
+    /// Wait for the bus to become idle, or until the timeout expires
+    fn wait_idle(&self) -> Result<(), Error> {
+        let timeout = self.timeout.map_or(Timeout::never(), Timeout::from_cycles);
+        Ok(run_with_timeout(&timeout, || self.poll_flush())?)
+    }
+
+    /// Bring the peripheral back to a clean state after an error.
+    ///
+    /// Waits for the bus to become idle, then empties both FIFOs and clears all error flags:
+    /// - the FIFO overflow/underflow flags in `spi_fifo_config_0` are read-only and are cleared
+    ///   by the FIFO clear bits, which also discard the FIFO contents
+    /// - `TransferEnd`, `SlaveTimeout` and TX underrun are latched in `spi_int_sts` and are
+    ///   cleared by writing 1 to their clear bits
+    /// - `FifoError` follows the FIFO flags and needs no separate clear
+    ///
+    /// All blocking `SpiBus` operations do this automatically if an error flag is set when
+    /// they start.
+    pub fn recover(&mut self) {
+        while self.is_busy() {}
+        self.spi
+            .spi_fifo_config_0
+            .modify(|_, w| w.tx_fifo_clr().set_bit().rx_fifo_clr().set_bit());
+        self.spi.spi_int_sts.modify(|_, w| {
+            w.cr_spi_end_clr()
+                .set_bit()
+                .cr_spi_sto_clr()
+                .set_bit()
+                .cr_spi_txu_clr()
+                .set_bit()
+        });
+    }
+
+    /// Call [`Spi::recover`] if a FIFO error flag latched during an earlier operation
+    fn recover_on_error(&mut self) {
+        let r = self.spi.spi_fifo_config_0.read();
+        if r.tx_fifo_overflow().bit_is_set()
+            || r.tx_fifo_underflow().bit_is_set()
+            || r.rx_fifo_overflow().bit_is_set()
+            || r.rx_fifo_underflow().bit_is_set()
+        {
+            self.recover();
+        }
+    }
+
+    /// Clear FIFOs
+    pub fn clear_fifo(&mut self) {
+        self.spi
+            .spi_fifo_config_0
+            .write(|w| w.rx_fifo_clr().set_bit().tx_fifo_clr().set_bit());
+    }
+
+    /// Write `buffer` using the DMA controller.
+    ///
+    /// Received data is discarded. The returned transfer hands back the buffer and the channel
+    /// once the last frame has been shifted out.
+    pub fn write_dma<B, W>(
+        &mut self,
+        channel: Channel,
+        buffer: B,
+    ) -> Transfer<B, SpiTxDma<'_, PINS, W>>
+    where
+        B: ReadBuffer<Word = W>,
+        W: FrameWord,
+    {
+        let (address, len) = unsafe { buffer.read_buffer() };
+
+        self.prepare_dma::<W>();
+        self.spi
+            .spi_fifo_config_0
+            .modify(|_, w| w.spi_dma_tx_en().set_bit());
+
+        let mut payload = SpiTxDma {
+            spi: self,
+            channel,
+            address: address as usize,
+            remaining: len,
+            _word: PhantomData,
+        };
+        payload.start_chunk();
+
+        Transfer { buffer, payload }
+    }
+
+    /// Fill `buffer` using the DMA controller.
+    ///
+    /// `tx_channel` clocks out `W::default()` for every received word, `rx_channel` moves the
+    /// received words into the buffer.
+    pub fn read_dma<B, W>(
+        &mut self,
+        tx_channel: Channel,
+        rx_channel: Channel,
+        mut buffer: B,
+    ) -> Transfer<B, SpiRxDma<'_, PINS, W>>
+    where
+        B: WriteBuffer<Word = W>,
+        W: FrameWord,
+    {
+        let (address, len) = unsafe { buffer.write_buffer() };
+
+        self.prepare_dma::<W>();
+        self.spi
+            .spi_fifo_config_0
+            .modify(|_, w| w.spi_dma_tx_en().set_bit().spi_dma_rx_en().set_bit());
+
+        let mut payload = SpiRxDma {
+            spi: self,
+            tx_channel,
+            rx_channel,
+            address: address as usize,
+            remaining: len,
+            _word: PhantomData,
+        };
+        payload.start_chunk();
+
+        Transfer { buffer, payload }
+    }
+
+    /// Bring the FIFOs into a known state and request DMA for every single FIFO entry
+    fn prepare_dma<W: FrameWord>(&mut self) {
+        self.select_frame_size::<W>();
+        while self.is_busy() {}
+        self.clear_fifo();
+        self.spi
+            .spi_fifo_config_1
+            .modify(|_, w| unsafe { w.tx_fifo_th().bits(0).rx_fifo_th().bits(0) });
+    }
+
+    /// Address of the TX FIFO
+    fn tx_fifo_address(&self) -> usize {
+        &self.spi.spi_fifo_wdata as *const _ as usize
+    }
+
+    /// Address of the RX FIFO
+    fn rx_fifo_address(&self) -> usize {
+        &self.spi.spi_fifo_rdata as *const _ as usize
+    }
+}
+
+/// Source of the dummy words clocked out by [`Spi::read_dma`]
+static DMA_DUMMY_WORD: u32 = 0;
+
+/// SPI side of a DMA write, see [`Spi::write_dma`]
+pub struct SpiTxDma<'a, PINS, W> {
+    spi: &'a mut Spi<pac::SPI, PINS>,
+    channel: Channel,
+    address: usize,
+    remaining: usize,
+    _word: PhantomData<W>,
+}
+
+impl<PINS, W> SpiTxDma<'_, PINS, W>
+where
+    PINS: Pins<pac::SPI>,
+{
+    fn start_chunk(&mut self) {
+        let len = self.remaining.min(dma::MAX_TRANSFER_SIZE);
+        let register = self.spi.tx_fifo_address();
+        self.channel.configure(
+            Direction::MemoryToPeripheral(dma::Request::SpiTx),
+            self.address,
+            register,
+            len,
+            Width::of::<W>(),
+            true,
+        );
+        self.channel.start();
+        self.address += len * core::mem::size_of::<W>();
+        self.remaining -= len;
+    }
+}
+
+unsafe impl<PINS, W> TransferPayload for SpiTxDma<'_, PINS, W>
+where
+    PINS: Pins<pac::SPI>,
+{
+    type Channels = Channel;
+
+    fn poll(&mut self) -> bool {
+        if !self.channel.is_done() {
+            return false;
+        }
+        if self.remaining == 0 {
+            return true;
+        }
+        self.start_chunk();
+        false
+    }
+
+    fn finish(mut self) -> Channel {
+        self.channel.stop();
+        while self.spi.spi.spi_bus_busy.read().sts_spi_bus_busy().bit_is_set() {}
+        self.spi
+            .spi
+            .spi_fifo_config_0
+            .modify(|_, w| w.spi_dma_tx_en().clear_bit());
+        // Drops the words received during the transfer, including the RX overflow they caused
+        self.spi.clear_fifo();
+        self.channel
+    }
+
+    fn abort(&mut self) {
+        self.channel.stop();
+        self.spi
+            .spi
+            .spi_fifo_config_0
+            .modify(|_, w| w.spi_dma_tx_en().clear_bit());
+        while self.spi.spi.spi_bus_busy.read().sts_spi_bus_busy().bit_is_set() {}
+        self.spi.clear_fifo();
+    }
+}
+
+/// SPI side of a DMA read, see [`Spi::read_dma`]
+pub struct SpiRxDma<'a, PINS, W> {
+    spi: &'a mut Spi<pac::SPI, PINS>,
+    tx_channel: Channel,
+    rx_channel: Channel,
+    address: usize,
+    remaining: usize,
+    _word: PhantomData<W>,
+}
+
+impl<PINS, W> SpiRxDma<'_, PINS, W>
+where
+    PINS: Pins<pac::SPI>,
+{
+    fn start_chunk(&mut self) {
+        let len = self.remaining.min(dma::MAX_TRANSFER_SIZE);
+        let rx_register = self.spi.rx_fifo_address();
+        let tx_register = self.spi.tx_fifo_address();
+        self.rx_channel.configure(
+            Direction::PeripheralToMemory(dma::Request::SpiRx),
+            self.address,
+            rx_register,
+            len,
+            Width::of::<W>(),
+            true,
+        );
+        self.tx_channel.configure(
+            Direction::MemoryToPeripheral(dma::Request::SpiTx),
+            &DMA_DUMMY_WORD as *const u32 as usize,
+            tx_register,
+            len,
+            Width::of::<W>(),
+            false,
+        );
+        // Start receiving first so no word can be missed
+        self.rx_channel.start();
+        self.tx_channel.start();
+        self.address += len * core::mem::size_of::<W>();
+        self.remaining -= len;
+    }
+}
+
+unsafe impl<PINS, W> TransferPayload for SpiRxDma<'_, PINS, W>
+where
+    PINS: Pins<pac::SPI>,
 {
-    fn read(&mut self) -> nb::Result<u8, Error> {
+    type Channels = (Channel, Channel);
+
+    fn poll(&mut self) -> bool {
+        if !(self.tx_channel.is_done() && self.rx_channel.is_done()) {
+            return false;
+        }
+        if self.remaining == 0 {
+            return true;
+        }
+        self.start_chunk();
+        false
+    }
+
+    fn finish(mut self) -> (Channel, Channel) {
+        self.tx_channel.stop();
+        self.rx_channel.stop();
+        self.spi
+            .spi
+            .spi_fifo_config_0
+            .modify(|_, w| w.spi_dma_tx_en().clear_bit().spi_dma_rx_en().clear_bit());
+        (self.tx_channel, self.rx_channel)
+    }
+
+    fn abort(&mut self) {
+        self.tx_channel.stop();
+        self.rx_channel.stop();
+        self.spi
+            .spi
+            .spi_fifo_config_0
+            .modify(|_, w| w.spi_dma_tx_en().clear_bit().spi_dma_rx_en().clear_bit());
+        while self.spi.spi.spi_bus_busy.read().sts_spi_bus_busy().bit_is_set() {}
+        self.spi.clear_fifo();
+    }
+}
+
+impl<PINS> ErrorType for Spi<SPI, PINS> where PINS: Pins<pac::SPI>, { type Error = Error; }
+
+impl<PINS, W> SpiBus<W> for Spi<pac::SPI, PINS>
+where
+    PINS: Pins<pac::SPI>,
+    W: FrameWord,
+{
+    fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.check_directions(!words.is_empty(), false)?;
+        let hold = self.cs_continuous && words.len() > 1;
+        self.with_cs(hold, |spi| {
+            spi.recover_on_error();
+            spi.pipeline(words.len(), |_| W::default(), |idx, word| words[idx] = word)
+        })
+    }
+
+    fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
+        self.check_directions(false, !words.is_empty())?;
+        let hold = self.cs_continuous && words.len() > 1;
+        self.with_cs(hold, |spi| {
+            spi.recover_on_error();
+            spi.pipeline(words.len(), |idx| words[idx], |_, _| {})
+        })
+    }
+
+    fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.check_directions(!read.is_empty(), !write.is_empty())?;
+        let hold = self.cs_continuous && read.len().max(write.len()) > 1;
+        self.with_cs(hold, |spi| {
+            spi.recover_on_error();
+            // Clock out the longer of both buffers. Padding words are sent once `write` runs
+            // out and received words are discarded once `read` is full, so the RX FIFO is
+            // drained for every word written.
+            spi.pipeline(
+                read.len().max(write.len()),
+                |idx| write.get(idx).copied().unwrap_or_default(),
+                |idx, word| {
+                    if let Some(slot) = read.get_mut(idx) {
+                        *slot = word;
+                    }
+                },
+            )
+        })
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.check_directions(!words.is_empty(), !words.is_empty())?;
+        let hold = self.cs_continuous && words.len() > 1;
+        self.with_cs(hold, |spi| {
+            spi.recover_on_error();
+            // a word is always sent before the word at the same index is received
+            let words = Cell::from_mut(words).as_slice_of_cells();
+            spi.pipeline(words.len(), |idx| words[idx].get(), |idx, word| words[idx].set(word))
+        })
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.wait_idle()
+    }
+}
+
+impl<PINS, W> SpiDevice<W> for Spi<pac::SPI, PINS>
+where
+    PINS: Pins<SPI>,
+    W: FrameWord,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, W>]) -> Result<(), Self::Error> {
+        self.with_cs(true, |spi| {
+            for operation in operations {
+                match operation {
+                    Operation::Read(read) => {
+                        SpiBus::read(spi, read)?
+                    }
+                    Operation::Write(write) => {
+                        SpiBus::write(spi, write)?
+                    }
+                    Operation::Transfer(read, write) => {
+                        SpiBus::transfer(spi, read, write)?
+                    }
+                    Operation::TransferInPlace(transfer) => {
+                        SpiBus::transfer_in_place(spi, transfer)?
+                    }
+                    Operation::DelayNs(delay) => {
+                        // CS stays asserted during the delay
+                        spi.delay.delay_ns(*delay)
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<PINS, W> FullDuplex<W> for Spi<pac::SPI, PINS>
+where
+    PINS: Pins<pac::SPI>,
+    W: FrameWord,
+{
+    fn read(&mut self) -> nb::Result<W, Error> {
+        self.select_frame_size::<W>();
         let spi_fifo_config_0 = self.spi.spi_fifo_config_0.read();
 
         if spi_fifo_config_0.rx_fifo_overflow().bit_is_set() {
@@ -351,11 +1592,12 @@ where
         } else if self.spi.spi_fifo_config_1.read().rx_fifo_cnt().bits() == 0 {
             Err(nb::Error::WouldBlock)
         } else {
-            Ok((self.spi.spi_fifo_rdata.read().bits() & 0xff) as u8)
+            Ok(W::from_fifo(self.spi.spi_fifo_rdata.read().bits()))
         }
     }
 
-    fn write(&mut self, data: u8) -> nb::Result<(), Self::Error> {
+    fn write(&mut self, data: W) -> nb::Result<(), Self::Error> {
+        self.select_frame_size::<W>();
         let spi_fifo_config_0 = self.spi.spi_fifo_config_0.read();
 
         if spi_fifo_config_0.tx_fifo_overflow().bit_is_set() {
@@ -367,9 +1609,438 @@ where
         } else {
             self.spi
                 .spi_fifo_wdata
-                .write(|w| unsafe { w.bits(data as u32) });
+                .write(|w| unsafe { w.bits(data.into_fifo()) });
 
             Ok(())
         }
     }
-}
\ No newline at end of file
+}
+/// Chip select polarity of a [`SpiDeviceWithCs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsPolarity {
+    /// CS is asserted by driving it low
+    ActiveLow,
+    /// CS is asserted by driving it high
+    ActiveHigh,
+}
+
+/// Error of a [`SpiDeviceWithCs`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceError<BUS, CS> {
+    /// The bus failed
+    Spi(BUS),
+    /// Driving the CS pin failed
+    Cs(CS),
+}
+
+impl<BUS, CS> embedded_hal::spi::Error for DeviceError<BUS, CS>
+where
+    BUS: embedded_hal::spi::Error,
+    CS: core::fmt::Debug,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            DeviceError::Spi(e) => e.kind(),
+            DeviceError::Cs(_) => ErrorKind::ChipSelectFault,
+        }
+    }
+}
+
+/**
+`SpiDevice` using a GPIO as chip select.
+
+The CS pin is asserted before the first operation of a transaction, stays asserted across
+delays and is released after the bus is flushed, also when an operation fails.
+```rust
+  let cs = parts.pin1.into_floating_output();
+  let mut display = hal::spi::SpiDeviceWithCs::new(spi, cs, &clocks);
+  display.write(&[0x2c])?;
+```
+*/
+pub struct SpiDeviceWithCs<BUS, CS> {
+    bus: BUS,
+    cs: CS,
+    polarity: CsPolarity,
+    delay: McycleDelay,
+    timing: Option<SpiTiming>,
+}
+
+/// A bus whose timing can be changed between transactions, see [`SpiDeviceWithCs::set_frequency`]
+pub trait BusTiming {
+    /// Returns the current phase lengths
+    fn timing(&self) -> SpiTiming;
+
+    /// Program new phase lengths once the bus is idle
+    fn set_timing(&mut self, timing: SpiTiming) -> Result<(), SpiConfigError>;
+}
+
+impl<PINS> BusTiming for Spi<pac::SPI, PINS>
+where
+    PINS: Pins<pac::SPI>,
+{
+    fn timing(&self) -> SpiTiming {
+        Spi::timing(self)
+    }
+
+    fn set_timing(&mut self, timing: SpiTiming) -> Result<(), SpiConfigError> {
+        Spi::set_timing(self, timing)
+    }
+}
+
+impl<T: BusTiming> BusTiming for &mut T {
+    fn timing(&self) -> SpiTiming {
+        T::timing(self)
+    }
+
+    fn set_timing(&mut self, timing: SpiTiming) -> Result<(), SpiConfigError> {
+        T::set_timing(self, timing)
+    }
+}
+
+impl<BUS, CS> SpiDeviceWithCs<BUS, CS>
+where
+    CS: embedded_hal::digital::OutputPin,
+{
+    /// Wrap `bus` using `cs` as active low chip select. CS is released right away.
+    pub fn new(bus: BUS, cs: CS, clocks: &Clocks) -> Result<Self, CS::Error> {
+        let mut device = SpiDeviceWithCs {
+            bus,
+            cs,
+            polarity: CsPolarity::ActiveLow,
+            delay: McycleDelay::new(clocks.sysclk().0),
+            timing: None,
+        };
+        device.set_cs(false)?;
+        Ok(device)
+    }
+
+    /// Select the chip select polarity. CS is released right away.
+    pub fn set_polarity(&mut self, polarity: CsPolarity) -> Result<(), CS::Error> {
+        self.polarity = polarity;
+        self.set_cs(false)
+    }
+
+    /// Run the transactions of this device at `freq`, `None` keeps the rate of the bus.
+    ///
+    /// The interval of a timing set before with [`set_timing`](Self::set_timing) is kept,
+    /// otherwise the shortest interval of one cycle is used.
+    ///
+    /// The bus rate is switched before asserting CS and restored after releasing it, also when
+    /// the transaction fails, so devices with different rates can share one bus.
+    pub fn set_frequency(
+        &mut self,
+        freq: Option<Hertz<u32>>,
+        clocks: &Clocks,
+    ) -> Result<(), SpiConfigError> {
+        let interval = self.timing.map(|timing| timing.interval);
+        self.timing = freq
+            .map(|freq| SpiTiming::from_frequency(freq, clocks))
+            .transpose()?
+            .map(|timing| SpiTiming {
+                interval: interval.unwrap_or(timing.interval),
+                ..timing
+            });
+        Ok(())
+    }
+
+    /// Run the transactions of this device with `timing`, `None` keeps the timing of the bus
+    pub fn set_timing(&mut self, timing: Option<SpiTiming>) -> Result<(), SpiConfigError> {
+        if let Some(timing) = timing {
+            timing.register_values()?;
+        }
+        self.timing = timing;
+        Ok(())
+    }
+
+    /// Release the bus and the CS pin
+    pub fn release(self) -> (BUS, CS) {
+        (self.bus, self.cs)
+    }
+
+    fn set_cs(&mut self, asserted: bool) -> Result<(), CS::Error> {
+        if asserted == (self.polarity == CsPolarity::ActiveHigh) {
+            self.cs.set_high()
+        } else {
+            self.cs.set_low()
+        }
+    }
+}
+
+impl<BUS, CS> ErrorType for SpiDeviceWithCs<BUS, CS>
+where
+    BUS: ErrorType,
+    CS: embedded_hal::digital::OutputPin,
+{
+    type Error = DeviceError<BUS::Error, CS::Error>;
+}
+
+impl<BUS, CS, W> SpiDevice<W> for SpiDeviceWithCs<BUS, CS>
+where
+    BUS: SpiBus<W> + BusTiming,
+    CS: embedded_hal::digital::OutputPin,
+    W: Copy + 'static,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, W>]) -> Result<(), Self::Error> {
+        // the timing was validated when it was set, switching can't fail
+        let restore = self.timing.map(|timing| {
+            let previous = self.bus.timing();
+            let _ = self.bus.set_timing(timing);
+            previous
+        });
+
+        if let Err(e) = self.set_cs(true) {
+            if let Some(previous) = restore {
+                let _ = self.bus.set_timing(previous);
+            }
+            return Err(DeviceError::Cs(e));
+        }
+
+        let result = operations.iter_mut().try_for_each(|operation| match operation {
+            Operation::Read(read) => self.bus.read(read),
+            Operation::Write(write) => self.bus.write(write),
+            Operation::Transfer(read, write) => self.bus.transfer(read, write),
+            Operation::TransferInPlace(words) => self.bus.transfer_in_place(words),
+            Operation::DelayNs(delay) => {
+                self.bus.flush()?;
+                self.delay.delay_ns(*delay);
+                Ok(())
+            }
+        });
+        // flush before releasing CS, even if an operation failed
+        let flushed = self.bus.flush();
+        let released = self.set_cs(false);
+        if let Some(previous) = restore {
+            let _ = self.bus.set_timing(previous);
+        }
+
+        result.and(flushed).map_err(DeviceError::Spi)?;
+        released.map_err(DeviceError::Cs)
+    }
+}
+
+/**
+3-wire SPI, using the MOSI pin as a shared bidirectional data line.
+
+Writes drive the line from MOSI as usual. Reads swap the MOSI and MISO functions inside the
+peripheral, so the MOSI pad becomes an input while the dummy words are clocked out.
+The MISO pad is left unused, so build the bus with a write-only pin tuple such as
+`(NoMiso, siso, ss, sclk)` or `(siso, sclk)`.
+```rust
+  let mut sensor = hal::spi::ThreeWire::new(spi);
+  let mut id = [0u8; 1];
+  sensor.write_read(&[0x80 | 0x0f], &mut id)?;
+```
+*/
+pub struct ThreeWire<PINS> {
+    spi: Spi<pac::SPI, PINS>,
+}
+
+impl<PINS> ThreeWire<PINS>
+where
+    PINS: Pins<pac::SPI>,
+{
+    /// Use `spi` in 3-wire mode
+    pub fn new(spi: Spi<pac::SPI, PINS>) -> Self {
+        ThreeWire { spi }
+    }
+
+    /// Hand back the underlying bus
+    pub fn release(self) -> Spi<pac::SPI, PINS> {
+        self.spi
+    }
+
+    /// Drive `words` onto the data line
+    pub fn write<W: FrameWord>(&mut self, words: &[W]) -> Result<(), Error> {
+        SpiBus::write(&mut self.spi, words)
+    }
+
+    /// Read `words` from the data line
+    pub fn read<W: FrameWord>(&mut self, words: &mut [W]) -> Result<(), Error> {
+        let hold = self.spi.cs_continuous && words.len() > 1;
+        self.spi.with_cs(hold, |spi| Self::read_inner(spi, words))
+    }
+
+    /// Write `write`, then turn the line around and read `read`, keeping CS asserted
+    pub fn write_read<W: FrameWord>(
+        &mut self,
+        write: &[W],
+        read: &mut [W],
+    ) -> Result<(), Error> {
+        self.spi.with_cs(true, |spi| {
+            SpiBus::write(spi, write)?;
+            Self::read_inner(spi, read)
+        })
+    }
+
+    fn read_inner<W: FrameWord>(
+        spi: &mut Spi<pac::SPI, PINS>,
+        words: &mut [W],
+    ) -> Result<(), Error> {
+        spi.check_directions(false, !words.is_empty())?;
+        spi.recover_on_error();
+        spi.wait_idle()?;
+
+        let glb = unsafe { &*pac::GLB::ptr() };
+        let swapped = glb.glb_parm.read().reg_spi_0_swap().bit_is_set();
+        glb.glb_parm.modify(|_, w| w.reg_spi_0_swap().bit(!swapped));
+
+        let result = spi
+            .pipeline(words.len(), |_| W::default(), |idx, word| words[idx] = word)
+            .and(spi.wait_idle());
+
+        glb.glb_parm.modify(|_, w| w.reg_spi_0_swap().bit(swapped));
+        result
+    }
+}
+
+#[cfg(feature = "async")]
+static SPI_WAKER: crate::interrupt::WakerSlot = crate::interrupt::WakerSlot::new();
+
+/// Interrupt handler for the async SPI driver.
+///
+/// Call this from the handler of [`Interrupt::SPI`](crate::interrupt::Interrupt::SPI).
+/// It masks the interrupt sources a pending future was waiting on and wakes that future.
+#[cfg(feature = "async")]
+pub fn on_interrupt() {
+    let spi = unsafe { &*pac::SPI::ptr() };
+    spi.spi_int_sts.modify(|_, w| {
+        w.cr_spi_txf_mask()
+            .set_bit()
+            .cr_spi_rxf_mask()
+            .set_bit()
+            .cr_spi_end_mask()
+            .set_bit()
+            .cr_spi_end_clr()
+            .set_bit()
+    });
+    SPI_WAKER.wake();
+}
+
+#[cfg(feature = "async")]
+impl<PINS> Spi<pac::SPI, PINS>
+where
+    PINS: Pins<pac::SPI>,
+{
+    /// Returns true if the condition signalled by `event` currently holds
+    fn event_ready(&self, event: Event) -> bool {
+        match event {
+            Event::TxFifoReady => self.spi.spi_fifo_config_1.read().tx_fifo_cnt().bits() != 0,
+            Event::RxFifoReady => self.spi.spi_fifo_config_1.read().rx_fifo_cnt().bits() != 0,
+            Event::TransferEnd => self.spi.spi_bus_busy.read().sts_spi_bus_busy().bit_is_clear(),
+            _ => self.is_pending(event),
+        }
+    }
+
+    async fn wait_for(&mut self, event: Event) {
+        core::future::poll_fn(|cx| {
+            if self.event_ready(event) {
+                return core::task::Poll::Ready(());
+            }
+            SPI_WAKER.register(cx.waker());
+            self.clear_interrupt(event);
+            self.listen(event);
+            // check again, the event may have happened before the interrupt was unmasked
+            if self.event_ready(event) {
+                core::task::Poll::Ready(())
+            } else {
+                core::task::Poll::Pending
+            }
+        })
+        .await
+    }
+
+    async fn write_word_async<W: FrameWord>(&mut self, word: W) -> Result<(), Error> {
+        loop {
+            match FullDuplex::<W>::write(self, word) {
+                Ok(()) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(e),
+                Err(nb::Error::WouldBlock) => self.wait_for(Event::TxFifoReady).await,
+            }
+        }
+    }
+
+    async fn read_word_async<W: FrameWord>(&mut self) -> Result<W, Error> {
+        loop {
+            match FullDuplex::<W>::read(self) {
+                Ok(word) => return Ok(word),
+                Err(nb::Error::Other(e)) => return Err(e),
+                Err(nb::Error::WouldBlock) => self.wait_for(Event::RxFifoReady).await,
+            }
+        }
+    }
+}
+
+/// Async bus, the SPI interrupt must be enabled and routed to [`on_interrupt`]
+#[cfg(feature = "async")]
+impl<PINS, W> embedded_hal_async::spi::SpiBus<W> for Spi<pac::SPI, PINS>
+where
+    PINS: Pins<pac::SPI>,
+    W: FrameWord,
+{
+    async fn read(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.check_directions(!words.is_empty(), false)?;
+        for word in words {
+            self.write_word_async(W::default()).await?;
+            *word = self.read_word_async().await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self, words: &[W]) -> Result<(), Self::Error> {
+        self.check_directions(false, !words.is_empty())?;
+        for word in words {
+            self.write_word_async(*word).await?;
+            self.read_word_async::<W>().await?;
+        }
+        Ok(())
+    }
+
+    async fn transfer(&mut self, read: &mut [W], write: &[W]) -> Result<(), Self::Error> {
+        self.check_directions(!read.is_empty(), !write.is_empty())?;
+        for idx in 0..read.len().max(write.len()) {
+            self.write_word_async(write.get(idx).copied().unwrap_or_default())
+                .await?;
+            let word = self.read_word_async().await?;
+            if let Some(slot) = read.get_mut(idx) {
+                *slot = word;
+            }
+        }
+        Ok(())
+    }
+
+    async fn transfer_in_place(&mut self, words: &mut [W]) -> Result<(), Self::Error> {
+        self.check_directions(!words.is_empty(), !words.is_empty())?;
+        for word in words {
+            self.write_word_async(*word).await?;
+            *word = self.read_word_async().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.wait_for(Event::TransferEnd).await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::spi::{MODE_0, MODE_1, MODE_2, MODE_3};
+
+    #[test]
+    fn mode_bits_match_the_module_table() {
+        assert_eq!(mode_bits(MODE_0), (false, true));
+        assert_eq!(mode_bits(MODE_1), (false, false));
+        assert_eq!(mode_bits(MODE_2), (true, true));
+        assert_eq!(mode_bits(MODE_3), (true, false));
+    }
+
+    #[test]
+    fn mode_from_bits_inverts_mode_bits() {
+        for mode in [MODE_0, MODE_1, MODE_2, MODE_3] {
+            let (pol, ph) = mode_bits(mode);
+            assert_eq!(mode_from_bits(pol, ph), mode);
+        }
+    }
+}
@@ -58,6 +58,24 @@ pub enum GLB_SYS_CLK_Type {
     GLB_SYS_CLK_DLL144M,
 }
 
+/// GLB flash clock type definition
+#[repr(C)]
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum GLB_SFLASH_CLK_Type {
+    /// Select DLL 144M as flash clock
+    GLB_SFLASH_CLK_144M,
+    /// Select XTAL as flash clock
+    GLB_SFLASH_CLK_XCLK,
+    /// Select DLL 57.6M as flash clock
+    GLB_SFLASH_CLK_57P6M,
+    /// Select DLL 72M as flash clock
+    GLB_SFLASH_CLK_72M,
+    /// Select BCLK as flash clock
+    GLB_SFLASH_CLK_BCLK,
+    /// Select DLL 96M as flash clock
+    GLB_SFLASH_CLK_96M,
+}
+
 /// GLB RTC clock type definition
 #[repr(C)]
 pub enum GLB_MTIMER_CLK_Type {
@@ -132,8 +150,27 @@ pub fn GLB_Set_System_CLK_Div(clkDiv: u8, bclkDiv: u8) -> BL_Err_Type {
     }
 }
 
-pub fn GLB_Set_MTimer_CLK(_enable: u8, _clkSel: GLB_MTIMER_CLK_Type, _div: u8) -> BL_Err_Type {
-    //TODO: impl MTimer_CLK
+// romfunc ((BL_Err_Type(*)(uint8_t enable, GLB_SFLASH_CLK_Type clkSel, uint8_t div))ROM_APITABLE[ROM_API_INDEX_GLB_Set_SF_CLK])
+pub fn GLB_Set_SF_CLK(enable: u8, clkSel: GLB_SFLASH_CLK_Type, div: u8) -> BL_Err_Type {
+    unsafe {
+        core::mem::transmute::<
+            *const (),
+            extern "C" fn(u8, GLB_SFLASH_CLK_Type, u8) -> BL_Err_Type,
+        >(rom_fn_ptr(ROM_API_INDEX_e::ROM_API_INDEX_GLB_Set_SF_CLK))(enable, clkSel, div)
+    }
+}
+
+pub fn GLB_Set_MTimer_CLK(enable: u8, clkSel: GLB_MTIMER_CLK_Type, div: u32) -> BL_Err_Type {
+    let glb = unsafe { ptr() };
+    // disable the counter clock while the divider changes
+    glb.cpu_clk_cfg.modify(|_, w| w.cpu_rtc_en().clear_bit());
+    glb.cpu_clk_cfg.modify(|_, w| unsafe {
+        w.cpu_rtc_sel()
+            .bit(matches!(clkSel, GLB_MTIMER_CLK_Type::GLB_MTIMER_CLK_32K))
+            .cpu_rtc_div()
+            .bits(div)
+    });
+    glb.cpu_clk_cfg.modify(|_, w| w.cpu_rtc_en().bit(enable != 0));
     BL_Err_Type::SUCCESS
 }
 
@@ -173,7 +210,8 @@ pub enum BL_AHB_Slave1_Type {
 }
 
 pub fn peripheral_clock_gate_all() {
-    unsafe {
+    // same critical section as `clock::enable_gate`, so no gate enabled by a handler is lost
+    riscv::interrupt::free(|| unsafe {
         ptr().cgen_cfg1.modify(|_, w| {
             w.tz1().clear_bit();
             w.tz2().clear_bit();
@@ -192,5 +230,5 @@ pub fn peripheral_clock_gate_all() {
             w.mjpeg().clear_bit();
             w
         });
-    }
+    });
 }
@@ -0,0 +1,1476 @@
+/*!
+# Timers
+The TIMER block has two 32 bit counters, each with three compare channels, and the watchdog (see
+[`crate::watchdog`]). Split the block into its counters, pick a clock source per counter if the
+default does not suit, then split a counter into its channels:
+
+```rust
+  let timers = dp.TIMER.split(&clocks);
+  let mut ch = timers.timer0.channels().ch0;
+  ch.set_mode(Mode::Periodic);
+  ch.start(Milliseconds(250u32));
+  loop {
+      nb::block!(ch.wait()).unwrap();
+      led.toggle().unwrap();
+  }
+```
+
+The counters and the watchdog share the clock select (`tccr`) and divider (`tcdr`) registers,
+each handle only changes its own fields of them, with interrupts disabled, so configuring one
+never clobbers another. The watchdog registers are also write protected, see the
+[watchdog module](crate::watchdog).
+
+The counters run freely and wrap at `u32::MAX`. A channel fires when the counter reaches its
+compare value, so the three channels of a counter are independent timeouts on a shared time base.
+
+## Interrupts
+`Channel::listen` enables the compare match interrupt of a channel, `TIMER_CH0` for the channels
+of `timer0` and `TIMER_CH1` for those of `timer1`. Call `Channel::wait` from the handler: it
+clears the match and, in periodic mode, arms the next period.
+
+## Async
+With the `async` feature, [`Channel::delay`] and [`Channel::interval`] sleep until the compare
+match interrupt, which has to be routed to [`on_interrupt`]:
+
+```rust
+  let mut ticker = ch.interval(Milliseconds(100u32));
+  loop {
+      let missed = ticker.next().await;
+      sample(missed);
+  }
+```
+
+## Timestamps
+[`Monotonic`] reads the 64 bit RISC-V `mtime` counter, which `ClockConfig::freeze` sets up to
+count at [`MTIMER_FREQ`](crate::clock::MTIMER_FREQ) independently of the core clock:
+
+```rust
+  let mono = Monotonic::new(&clocks);
+  let start = mono.now();
+  do_work();
+  let took = mono.elapsed_since(start);
+```
+
+## Machine timer
+[`MachineTimer`] owns the single `mtimecmp` comparator and shares it as a queue of alarms, so
+its own alarm, the periodic [`Tick`] (the equivalent of SysTick on Cortex-M) and
+[`MtimeDelay`](crate::delay::MtimeDelay) can run at the same time. The HAL doesn't define the
+`MachineTimer` trap, route it to [`on_machine_timer`].
+
+## Timeouts
+[`Timeout`] bounds a polling loop by time, measured on `mcycle` or `mtime`, and
+[`run_with_timeout`] polls an `nb` operation until it succeeds or the timeout expires:
+
+```rust
+  let timeout = Timeout::mtime(Milliseconds(10u32), &clocks);
+  let word = run_with_timeout(&timeout, || serial.read())?;
+```
+
+## Software PWM
+[`SoftPwm`] generates PWM on pins without a PWM channel from a channel's compare interrupt.
+
+## Pulse measurement
+The counters have no capture input, so [`Capture`] timestamps the edges of a pin from the GPIO
+interrupt by reading a counter.
+*/
+
+use crate::clock::{enable_gate, Clocks, Gate, TimerClkSource, MTIMER_FREQ};
+use crate::delay::McycleDelay;
+use crate::gpio::{irq, pad, write_all, ErasedPin, Input, IsrCell, Output, TriggerMode};
+use crate::pac;
+use crate::watchdog::Wdt;
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
+use embedded_time::duration::Microseconds;
+use embedded_time::fraction::Fraction;
+use embedded_time::rate::Hertz;
+
+/// Address of the 64 bit `mtime` counter in the CLIC
+const MTIME: usize = 0x0200_bff8;
+/// Address of the 64 bit `mtimecmp` register in the CLIC
+const MTIMECMP: usize = 0x0200_4000;
+
+/// First watchdog access key, written to `WFAR`
+const ACCESS_KEY1: u16 = 0xbaba;
+/// Second watchdog access key, written to `WSAR`
+const ACCESS_KEY2: u16 = 0xeb10;
+
+/// Rate the prescaler aims for, giving 1 µs ticks where the source allows it
+const TICK_TARGET: u32 = 1_000_000;
+
+/// Largest prescaler division
+const MAX_PRESCALER: u32 = 256;
+
+/// Extension trait to split the TIMER block into its counters
+pub trait TimerExt {
+    /// Enables the timer clock and splits the block into its counters
+    ///
+    /// Both counters keep their current clock source, see `ClockConfig::timer_clk_source`, and
+    /// are started in free running mode.
+    fn split(self, clocks: &Clocks) -> Timers;
+}
+
+impl TimerExt for pac::TIMER {
+    fn split(self, clocks: &Clocks) -> Timers {
+        enable_gate(Gate::Timer);
+
+        let tccr = self.tccr.read();
+        let mut timer0 = Timer { id: 0, tick: 0 };
+        let mut timer1 = Timer { id: 1, tick: 0 };
+        timer0.set_clock_source(source_from_bits(clock_select(tccr.bits(), 0)), clocks);
+        timer1.set_clock_source(source_from_bits(clock_select(tccr.bits(), 1)), clocks);
+        Timers {
+            timer0,
+            timer1,
+            wdt: Wdt { _private: () },
+        }
+    }
+}
+
+/// The counters and the watchdog of the TIMER block
+pub struct Timers {
+    pub timer0: Timer,
+    pub timer1: Timer,
+    /// Watchdog registers, for `Watchdog::new`
+    pub wdt: Wdt,
+}
+
+/// One of the two 32 bit counters
+pub struct Timer {
+    id: u8,
+    tick: u32,
+}
+
+impl Timer {
+    /// Counter number
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Select the clock of this counter and derive its prescaler
+    ///
+    /// The prescaler divides the source down to 1 MHz where it can, slower sources are counted
+    /// undivided. The counter restarts from 0.
+    pub fn set_clock_source(&mut self, source: TimerClkSource, clocks: &Clocks) {
+        let freq = match source {
+            TimerClkSource::Fclk => clocks.sysclk().0,
+            TimerClkSource::F32k => clocks.f32k().0,
+            TimerClkSource::OneKhz => 1_000,
+            TimerClkSource::Xclk => clocks.xclk().0,
+        };
+        let div = freq.div_ceil(TICK_TARGET).clamp(1, MAX_PRESCALER);
+        self.tick = freq / div;
+
+        let id = self.id;
+        let sel = source as u8;
+        let div = (div - 1) as u8;
+        modify_shared(|timer| {
+            if id == 0 {
+                timer.tcer.modify(|_, w| w.timer2_en().clear_bit());
+            } else {
+                timer.tcer.modify(|_, w| w.timer3_en().clear_bit());
+            }
+            timer.tccr.modify(|r, w| unsafe { w.bits(merge_tccr(r.bits(), id, sel)) });
+            timer.tcdr.modify(|r, w| unsafe { w.bits(merge_tcdr(r.bits(), id, div)) });
+            if id == 0 {
+                timer.tcmr.modify(|_, w| w.timer2_mode().set_bit());
+                timer.tcer.modify(|_, w| w.timer2_en().set_bit());
+            } else {
+                timer.tcmr.modify(|_, w| w.timer3_mode().set_bit());
+                timer.tcer.modify(|_, w| w.timer3_en().set_bit());
+            }
+        });
+    }
+
+    /// Rate the counter advances at, after the prescaler
+    pub fn tick_rate(&self) -> Hertz {
+        Hertz(self.tick)
+    }
+
+    /// Current counter value
+    pub fn count(&self) -> u32 {
+        count(self.id)
+    }
+
+    /// Splits the counter into its compare channels, fixing the clock source
+    pub fn channels(self) -> Channels {
+        let channel = |id| Channel {
+            timer: self.id,
+            id,
+            tick: self.tick,
+            period: 0,
+            mode: Mode::OneShot,
+            running: false,
+        };
+        Channels {
+            ch0: channel(0),
+            ch1: channel(1),
+            ch2: channel(2),
+        }
+    }
+}
+
+/// Compare channels of a counter
+pub struct Channels {
+    pub ch0: Channel,
+    pub ch1: Channel,
+    pub ch2: Channel,
+}
+
+/// What a channel does once its timeout expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Expire once, `wait` keeps returning `Ok` until the channel is started again
+    OneShot,
+    /// Start the next period as soon as one expires
+    ///
+    /// Periods are counted from the previous compare value, so they do not drift with the time
+    /// it takes to call `wait`. `wait` must be called at least once per period.
+    Periodic,
+}
+
+/// A compare channel of a counter
+pub struct Channel {
+    timer: u8,
+    id: u8,
+    tick: u32,
+    period: u32,
+    mode: Mode,
+    running: bool,
+}
+
+impl Channel {
+    /// Counter number this channel compares against
+    pub fn timer(&self) -> u8 {
+        self.timer
+    }
+
+    /// Channel number
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Select one-shot or periodic operation, takes effect with the next `start`
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    /// Longest timeout `start` accepts, `u32::MAX` ticks
+    pub fn max_timeout(&self) -> Microseconds<u64> {
+        Microseconds(u32::MAX as u64 * 1_000_000 / self.tick as u64)
+    }
+
+    /// Start a timeout of `timeout`, rounded up to whole ticks
+    ///
+    /// # Panics
+    /// if `timeout` is longer than [`Channel::max_timeout`]
+    pub fn start<T: Into<Microseconds<u64>>>(&mut self, timeout: T) {
+        let ticks = us_to_ticks(timeout.into(), self.tick).max(1);
+        assert!(ticks <= u32::MAX as u64, "timer timeout too long");
+        self.period = ticks as u32;
+
+        self.clear();
+        set_compare(
+            self.timer,
+            self.id,
+            count(self.timer).wrapping_add(self.period),
+        );
+        self.running = true;
+    }
+
+    /// Stop the channel, `wait` then returns `Ok` right away
+    pub fn cancel(&mut self) {
+        self.running = false;
+        self.clear();
+    }
+
+    /// Returns `Ok` once the timeout has expired
+    ///
+    /// In periodic mode this also arms the next period. Never fails, the error type is only
+    /// there for `nb::block!`.
+    pub fn wait(&mut self) -> nb::Result<(), Infallible> {
+        if !self.running {
+            return Ok(());
+        }
+        if !self.is_pending() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.clear();
+        match self.mode {
+            Mode::OneShot => self.running = false,
+            Mode::Periodic => {
+                let next = compare(self.timer, self.id).wrapping_add(self.period);
+                set_compare(self.timer, self.id, next);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start generating an interrupt when the timeout expires
+    pub fn listen(&mut self) {
+        set_interrupt_enable(self.timer, 1 << self.id, true);
+    }
+
+    /// Stop generating an interrupt when the timeout expires
+    pub fn unlisten(&mut self) {
+        set_interrupt_enable(self.timer, 1 << self.id, false);
+    }
+
+    /// Returns true if the compare value was reached, regardless of whether it is listened to
+    pub fn is_pending(&self) -> bool {
+        let timer = unsafe { &*pac::TIMER::ptr() };
+        let status = if self.timer == 0 {
+            timer.tmsr2.read().bits()
+        } else {
+            timer.tmsr3.read().bits()
+        };
+        status & (1 << self.id) != 0
+    }
+
+    /// Clear the match flag of this channel
+    fn clear(&mut self) {
+        let timer = unsafe { &*pac::TIMER::ptr() };
+        let mask = 1 << self.id;
+        if self.timer == 0 {
+            timer.ticr2.write(|w| unsafe { w.bits(mask) });
+        } else {
+            timer.ticr3.write(|w| unsafe { w.bits(mask) });
+        }
+    }
+}
+
+/// Read-modify-write registers shared by the counters and the watchdog, with interrupts
+/// disabled so an interrupt can't change another field of the same register in between
+pub(crate) fn modify_shared<R>(f: impl FnOnce(&pac::timer::RegisterBlock) -> R) -> R {
+    let timer = unsafe { &*pac::TIMER::ptr() };
+    riscv::interrupt::free(|| f(timer))
+}
+
+/// Owner id of the watchdog fields in `tccr` and `tcdr`, the counters use their own id
+pub(crate) const WDT_ID: u8 = 2;
+
+/// Shift of the 2 bit clock select of `id` in `tccr`: cs_1 at bits 2..3, cs_2 at bits 5..6,
+/// cs_wdt at bits 8..9
+const fn tccr_shift(id: u8) -> u32 {
+    [2, 5, 8][id as usize]
+}
+
+/// Shift of the 8 bit divider of `id` in `tcdr`: tcdr2 at bits 8..15, tcdr3 at bits 16..23,
+/// wcdr at bits 24..31
+const fn tcdr_shift(id: u8) -> u32 {
+    [8, 16, 24][id as usize]
+}
+
+/// Clock select of `id` in the `tccr` value `tccr`
+pub(crate) const fn clock_select(tccr: u32, id: u8) -> u8 {
+    ((tccr >> tccr_shift(id)) & 0b11) as u8
+}
+
+/// `tccr` value with the clock select of `id` replaced by `sel`, the other fields kept
+pub(crate) const fn merge_tccr(tccr: u32, id: u8, sel: u8) -> u32 {
+    let shift = tccr_shift(id);
+    (tccr & !(0b11 << shift)) | ((sel as u32 & 0b11) << shift)
+}
+
+/// Divider of `id` in the `tcdr` value `tcdr`
+pub(crate) const fn divider(tcdr: u32, id: u8) -> u8 {
+    (tcdr >> tcdr_shift(id)) as u8
+}
+
+/// `tcdr` value with the divider of `id` replaced by `div`, the other fields kept
+pub(crate) const fn merge_tcdr(tcdr: u32, id: u8, div: u8) -> u32 {
+    let shift = tcdr_shift(id);
+    (tcdr & !(0xff << shift)) | ((div as u32) << shift)
+}
+
+/// Write a write protected watchdog register
+///
+/// The access keys only allow a single write, so they are written directly before `write`,
+/// with interrupts disabled so nothing can come in between.
+pub(crate) fn write_protected(write: impl FnOnce(&pac::timer::RegisterBlock)) {
+    modify_shared(|timer| {
+        timer.wfar.write(|w| unsafe { w.wfar().bits(ACCESS_KEY1) });
+        timer.wsar.write(|w| unsafe { w.wsar().bits(ACCESS_KEY2) });
+        write(timer);
+    });
+}
+
+/// Set or clear the compare interrupt enables in `mask` of counter `timer`
+fn set_interrupt_enable(timer: u8, mask: u32, enable: bool) {
+    let regs = unsafe { &*pac::TIMER::ptr() };
+    let update = |bits: u32| if enable { bits | mask } else { bits & !mask };
+    riscv::interrupt::free(|| {
+        if timer == 0 {
+            regs.tier2
+                .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+        } else {
+            regs.tier3
+                .modify(|r, w| unsafe { w.bits(update(r.bits())) });
+        }
+    });
+}
+
+/// Clock source encoded in a `tccr` clock select field
+fn source_from_bits(bits: u8) -> TimerClkSource {
+    match bits {
+        0 => TimerClkSource::Fclk,
+        1 => TimerClkSource::F32k,
+        2 => TimerClkSource::OneKhz,
+        _ => TimerClkSource::Xclk,
+    }
+}
+
+/// Current value of counter `timer`
+fn count(timer: u8) -> u32 {
+    let regs = unsafe { &*pac::TIMER::ptr() };
+    if timer == 0 {
+        regs.tcr2.read().bits()
+    } else {
+        regs.tcr3.read().bits()
+    }
+}
+
+/// Compare value of channel `id` of counter `timer`
+fn compare(timer: u8, id: u8) -> u32 {
+    let regs = unsafe { &*pac::TIMER::ptr() };
+    match (timer, id) {
+        (0, 0) => regs.tmr2_0.read().bits(),
+        (0, 1) => regs.tmr2_1.read().bits(),
+        (0, _) => regs.tmr2_2.read().bits(),
+        (_, 0) => regs.tmr3_0.read().bits(),
+        (_, 1) => regs.tmr3_1.read().bits(),
+        (_, _) => regs.tmr3_2.read().bits(),
+    }
+}
+
+/// Set the compare value of channel `id` of counter `timer`
+fn set_compare(timer: u8, id: u8, value: u32) {
+    let regs = unsafe { &*pac::TIMER::ptr() };
+    match (timer, id) {
+        (0, 0) => regs.tmr2_0.write(|w| unsafe { w.bits(value) }),
+        (0, 1) => regs.tmr2_1.write(|w| unsafe { w.bits(value) }),
+        (0, _) => regs.tmr2_2.write(|w| unsafe { w.bits(value) }),
+        (_, 0) => regs.tmr3_0.write(|w| unsafe { w.bits(value) }),
+        (_, 1) => regs.tmr3_1.write(|w| unsafe { w.bits(value) }),
+        (_, _) => regs.tmr3_2.write(|w| unsafe { w.bits(value) }),
+    }
+}
+
+/// Point in time on the [`Monotonic`] clock
+pub type Instant = embedded_time::Instant<Monotonic>;
+
+/// Monotonic 1 MHz clock based on the RISC-V `mtime` counter
+///
+/// The resolution is 1 µs, and the 64 bit count rolls over after about 584 000 years, so it
+/// never does in practice. Reading it only takes a load from `mtime`, so it can be shared with
+/// and used from interrupt handlers without locking.
+#[derive(Debug, Clone, Copy)]
+pub struct Monotonic {
+    _private: (),
+}
+
+impl Monotonic {
+    /// Creates the clock
+    ///
+    /// # Panics
+    /// if `mtime` does not count at [`MTIMER_FREQ`], e.g. after `ClockConfig::mtimer_div` or at a
+    /// bus clock that doesn't divide down to it, see `Clocks::mtimer_clk`
+    pub fn new(clocks: &Clocks) -> Self {
+        assert_eq!(
+            clocks.mtimer_clk().0,
+            MTIMER_FREQ,
+            "mtime does not count at MTIMER_FREQ"
+        );
+        Monotonic { _private: () }
+    }
+
+    /// Current time
+    pub fn now(&self) -> Instant {
+        Instant::new(mtime())
+    }
+
+    /// Time passed since `earlier`, 0 if `earlier` is still in the future
+    pub fn elapsed_since(&self, earlier: Instant) -> Microseconds<u64> {
+        let now = self.now().duration_since_epoch().integer();
+        Microseconds(now.saturating_sub(earlier.duration_since_epoch().integer()))
+    }
+}
+
+impl embedded_time::Clock for Monotonic {
+    type T = u64;
+
+    const SCALING_FACTOR: Fraction = Fraction::new(1, MTIMER_FREQ);
+
+    fn try_now(&self) -> Result<Instant, embedded_time::clock::Error> {
+        Ok(self.now())
+    }
+}
+
+/// Reads the 64 bit `mtime` counter, which the 32 bit core has to do in two halves
+pub(crate) fn mtime() -> u64 {
+    let lo = MTIME as *const u32;
+    let hi = (MTIME + 4) as *const u32;
+    loop {
+        let high = unsafe { hi.read_volatile() };
+        let low = unsafe { lo.read_volatile() };
+        // retry if the low half wrapped between the reads
+        if unsafe { hi.read_volatile() } == high {
+            return (high as u64) << 32 | low as u64;
+        }
+    }
+}
+
+/// Program `mtimecmp`, which also clears a pending timer interrupt if `value` is in the future
+///
+/// The high half is parked at its maximum first, so the comparator never briefly holds a value
+/// below both the old and the new one.
+fn set_mtimecmp(value: u64) {
+    let lo = MTIMECMP as *mut u32;
+    let hi = (MTIMECMP + 4) as *mut u32;
+    unsafe {
+        hi.write_volatile(u32::MAX);
+        lo.write_volatile(value as u32);
+        hi.write_volatile((value >> 32) as u32);
+    }
+}
+
+/// Alarm of the [`MachineTimer`] handle
+const USER_ALARM: usize = 0;
+/// Alarm of the [`Tick`]
+const TICK_ALARM: usize = 1;
+/// Alarm of the `SleepDelay`, which only needs the interrupt to leave `wfi`
+pub(crate) const SLEEP_ALARM: usize = 2;
+/// First alarm of the `MtimeDelay`s, one per slot
+#[cfg(feature = "async")]
+pub(crate) const DELAY_ALARMS: usize = 3;
+#[cfg(feature = "async")]
+const ALARMS: usize = DELAY_ALARMS + crate::delay::MTIME_DELAY_SLOTS;
+#[cfg(not(feature = "async"))]
+const ALARMS: usize = 3;
+
+/// Deadline of every alarm in `mtime` ticks, `u64::MAX` while it is disarmed
+struct AlarmQueue(core::cell::RefCell<[u64; ALARMS]>);
+
+// only accessed with interrupts disabled on the single hart
+unsafe impl Sync for AlarmQueue {}
+
+static ALARM_QUEUE: AlarmQueue = AlarmQueue(core::cell::RefCell::new([u64::MAX; ALARMS]));
+
+/// Set once the [`MachineTimer`] handle has been taken
+static MACHINE_TIMER_TAKEN: AtomicBool = AtomicBool::new(false);
+/// `fn(u64) -> Option<u64>` called when the handle's alarm fires, null if there is none
+static MACHINE_TIMER_HOOK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Arm `alarm` for `deadline`, `u64::MAX` disarms it, and program the comparator with the
+/// earliest deadline of all alarms
+pub(crate) fn set_alarm(alarm: usize, deadline: u64) {
+    riscv::interrupt::free(|| {
+        let mut deadlines = ALARM_QUEUE.0.borrow_mut();
+        deadlines[alarm] = deadline;
+        set_mtimecmp(deadlines.iter().copied().min().unwrap_or(u64::MAX));
+    });
+}
+
+/// The machine timer comparator `mtimecmp`
+///
+/// There is a single comparator, so it is shared through a small queue of alarms: the handle
+/// has one, [`Tick`] and [`SleepDelay`](crate::delay::SleepDelay) have one each and every
+/// [`MtimeDelay`](crate::delay::MtimeDelay) has one, and the
+/// comparator is always programmed with the earliest of them. None of them can push another
+/// one's deadline back.
+///
+/// The `MTIP` interrupt must be enabled and routed to [`on_machine_timer`], from whichever trap
+/// handler the runtime provides:
+///
+/// ```rust
+///   #[export_name = "MachineTimer"]
+///   fn machine_timer() {
+///       hal::timer::on_machine_timer();
+///   }
+///
+///   /// Called 10 ms after it was scheduled, then every 10 ms
+///   fn every_10ms(deadline: u64) -> Option<u64> {
+///       poll_sensors();
+///       Some(deadline + 10_000)
+///   }
+///
+///   let mut mtimer = MachineTimer::take(&clocks).unwrap();
+///   mtimer.set_hook(every_10ms);
+///   mtimer.schedule_after(Milliseconds(10u32));
+///   unsafe { interrupt::enable(Interrupt::MTIP) };
+/// ```
+pub struct MachineTimer {
+    mtimer_clk: u32,
+}
+
+impl MachineTimer {
+    /// Take the handle, `None` if it was taken before
+    pub fn take(clocks: &Clocks) -> Option<Self> {
+        if MACHINE_TIMER_TAKEN.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        Some(MachineTimer {
+            mtimer_clk: clocks.mtimer_clk().0,
+        })
+    }
+
+    /// Current value of `mtime`
+    pub fn now(&self) -> u64 {
+        mtime()
+    }
+
+    /// Fire at `deadline` in `mtime` ticks, right away if it has passed, replacing the previous
+    /// deadline
+    pub fn schedule_at(&mut self, deadline: u64) {
+        set_alarm(USER_ALARM, deadline);
+    }
+
+    /// Fire `after` from now, rounded up to whole `mtime` ticks
+    pub fn schedule_after<T: Into<Microseconds<u64>>>(&mut self, after: T) {
+        let ticks = us_to_ticks(after.into(), self.mtimer_clk);
+        self.schedule_at(mtime().saturating_add(ticks));
+    }
+
+    /// Disarm the alarm if it has not fired yet
+    pub fn cancel(&mut self) {
+        set_alarm(USER_ALARM, u64::MAX);
+    }
+
+    /// Call `hook` from [`on_machine_timer`] when the alarm fires, replacing the previous one
+    ///
+    /// It gets the deadline that fired and may return the next one, which rearms the alarm
+    /// without drift.
+    pub fn set_hook(&mut self, hook: fn(u64) -> Option<u64>) {
+        MACHINE_TIMER_HOOK.store(hook as *mut (), Ordering::Relaxed);
+    }
+
+    /// Stop calling the hook, the alarm still fires
+    pub fn clear_hook(&mut self) {
+        MACHINE_TIMER_HOOK.store(core::ptr::null_mut(), Ordering::Relaxed);
+    }
+
+    /// Disarm the alarm and release the handle, so it can be taken again
+    pub fn free(mut self) {
+        self.cancel();
+        self.clear_hook();
+        MACHINE_TIMER_TAKEN.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Interrupt handler for the machine timer
+///
+/// Call this from the `MachineTimer` trap handler. It disarms the alarms that expired, programs
+/// the comparator for the next one and then runs the expired ones: the [`MachineTimer`] hook,
+/// the [`Tick`] and the `MtimeDelay` wakers.
+pub fn on_machine_timer() {
+    let now = mtime();
+    let mut expired = [u64::MAX; ALARMS];
+    riscv::interrupt::free(|| {
+        let mut deadlines = ALARM_QUEUE.0.borrow_mut();
+        for (deadline, expired) in deadlines.iter_mut().zip(expired.iter_mut()) {
+            if *deadline <= now {
+                *expired = core::mem::replace(deadline, u64::MAX);
+            }
+        }
+        set_mtimecmp(deadlines.iter().copied().min().unwrap_or(u64::MAX));
+    });
+
+    for (alarm, &deadline) in expired.iter().enumerate() {
+        if deadline == u64::MAX {
+            continue;
+        }
+        match alarm {
+            USER_ALARM => {
+                let hook = MACHINE_TIMER_HOOK.load(Ordering::Relaxed);
+                if !hook.is_null() {
+                    // SAFETY: only ever stored from a `fn(u64) -> Option<u64>` in `set_hook`
+                    let hook =
+                        unsafe { core::mem::transmute::<*mut (), fn(u64) -> Option<u64>>(hook) };
+                    if let Some(next) = hook(deadline) {
+                        set_alarm(USER_ALARM, next);
+                    }
+                }
+            }
+            TICK_ALARM => on_tick(deadline),
+            SLEEP_ALARM => {}
+            #[cfg(feature = "async")]
+            _ => crate::delay::wake_slot(alarm - DELAY_ALARMS),
+            #[cfg(not(feature = "async"))]
+            _ => {}
+        }
+    }
+}
+
+/// `mtime` ticks per tick, 0 while the tick is stopped
+static TICK_PERIOD: AtomicU32 = AtomicU32::new(0);
+/// Tick count, split in halves as the core has no 64 bit atomics
+static TICK_COUNT_LO: AtomicU32 = AtomicU32::new(0);
+static TICK_COUNT_HI: AtomicU32 = AtomicU32::new(0);
+/// `fn()` called on every tick, null if there is none
+static TICK_CALLBACK: AtomicPtr<()> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Periodic tick on the machine timer interrupt
+///
+/// It runs on its own alarm of the [`MachineTimer`] queue, so the `MTIP` interrupt must be
+/// enabled and routed to [`on_machine_timer`]:
+///
+/// ```rust
+///   #[export_name = "MachineTimer"]
+///   fn machine_timer() {
+///       hal::timer::on_machine_timer();
+///   }
+///
+///   let tick = Tick::start(1_000u32.Hz(), &clocks);
+///   tick.register_callback(on_tick);
+///   unsafe { interrupt::enable(Interrupt::MTIP) };
+/// ```
+///
+/// Each tick is scheduled a fixed period after the previous one rather than after the interrupt
+/// was handled, so handler latency does not add up to drift.
+#[derive(Debug, Clone, Copy)]
+pub struct Tick {
+    period: u32,
+    mtimer_clk: u32,
+}
+
+impl Tick {
+    /// Start ticking at `freq`, rounded to a whole number of `mtime` ticks, see [`Tick::frequency`]
+    ///
+    /// The tick count restarts from 0.
+    ///
+    /// # Panics
+    /// if `freq` is 0 or faster than `Clocks::mtimer_clk`
+    pub fn start(freq: Hertz<u32>, clocks: &Clocks) -> Self {
+        let mtimer_clk = clocks.mtimer_clk().0;
+        assert!(
+            freq.0 != 0 && freq.0 <= mtimer_clk,
+            "tick frequency out of range"
+        );
+        let period = (mtimer_clk + freq.0 / 2) / freq.0;
+        riscv::interrupt::free(|| {
+            TICK_COUNT_LO.store(0, Ordering::Relaxed);
+            TICK_COUNT_HI.store(0, Ordering::Relaxed);
+            TICK_PERIOD.store(period, Ordering::Relaxed);
+            set_alarm(TICK_ALARM, mtime() + period as u64);
+        });
+        Tick { period, mtimer_clk }
+    }
+
+    /// Actual tick rate
+    pub fn frequency(&self) -> Hertz {
+        Hertz(self.mtimer_clk / self.period)
+    }
+
+    /// Number of ticks since `start`
+    pub fn ticks(&self) -> u64 {
+        riscv::interrupt::free(|| {
+            (TICK_COUNT_HI.load(Ordering::Relaxed) as u64) << 32
+                | TICK_COUNT_LO.load(Ordering::Relaxed) as u64
+        })
+    }
+
+    /// Call `callback` from the interrupt handler on every tick, replacing the previous one
+    pub fn register_callback(&self, callback: fn()) {
+        TICK_CALLBACK.store(callback as *mut (), Ordering::Relaxed);
+    }
+
+    /// Stop calling the callback
+    pub fn clear_callback(&self) {
+        TICK_CALLBACK.store(core::ptr::null_mut(), Ordering::Relaxed);
+    }
+
+    /// Stop ticking
+    pub fn stop(self) {
+        riscv::interrupt::free(|| {
+            TICK_PERIOD.store(0, Ordering::Relaxed);
+            set_alarm(TICK_ALARM, u64::MAX);
+        });
+    }
+}
+
+/// Schedules the next tick a period after `deadline`, counts this one and runs the callback
+fn on_tick(deadline: u64) {
+    let period = TICK_PERIOD.load(Ordering::Relaxed);
+    if period == 0 {
+        return;
+    }
+    set_alarm(TICK_ALARM, deadline + period as u64);
+    let lo = TICK_COUNT_LO.load(Ordering::Relaxed).wrapping_add(1);
+    TICK_COUNT_LO.store(lo, Ordering::Relaxed);
+    if lo == 0 {
+        TICK_COUNT_HI.fetch_add(1, Ordering::Relaxed);
+    }
+
+    let callback = TICK_CALLBACK.load(Ordering::Relaxed);
+    if !callback.is_null() {
+        // SAFETY: only ever stored from a `fn()` in `register_callback`
+        let callback = unsafe { core::mem::transmute::<*mut (), fn()>(callback) };
+        callback();
+    }
+}
+
+/// Edge timestamps of a [`Capture`], extended to 64 bits
+struct CaptureState {
+    pin: u8,
+    last_count: u32,
+    wraps: u32,
+    rise: Option<u64>,
+    width: Option<u64>,
+    period: Option<u64>,
+}
+
+impl CaptureState {
+    /// Extend `count` to 64 bits, counting a wrap if it went backwards since the last call
+    fn extend(&mut self, count: u32) -> u64 {
+        if count < self.last_count {
+            self.wraps = self.wraps.wrapping_add(1);
+        }
+        self.last_count = count;
+        (self.wraps as u64) << 32 | count as u64
+    }
+}
+
+/// State of the capture on each counter
+static CAPTURES: [IsrCell<CaptureState>; 2] = [IsrCell::new(), IsrCell::new()];
+
+/// GPIO callback of the capture on `timer0`
+fn capture_edge0() {
+    capture_edge(0);
+}
+
+/// GPIO callback of the capture on `timer1`
+fn capture_edge1() {
+    capture_edge(1);
+}
+
+/// Timestamp an edge on the pin of the capture on counter `timer`
+fn capture_edge(timer: u8) {
+    let count = count(timer);
+    CAPTURES[timer as usize].borrow_in_isr(|state| {
+        let now = state.extend(count);
+        if pad::input(state.pin) {
+            if let Some(rise) = state.rise {
+                state.period = Some(now - rise);
+            }
+            state.rise = Some(now);
+        } else if let Some(rise) = state.rise {
+            state.width = Some(now - rise);
+        }
+    });
+}
+
+/// Pulse width and period measurement on an input pin
+///
+/// Both edges of the pin are timestamped with a counter from the GPIO interrupt, so route
+/// `GPIO_INT0` to [`gpio::irq::on_interrupt`](crate::gpio::irq::on_interrupt):
+///
+/// ```rust
+///   let timers = dp.TIMER.split(&clocks);
+///   let echo = parts.pin10.into_floating_input().downgrade();
+///   let mut capture = Capture::new(echo, timers.timer0);
+///   unsafe { gpio::unmask_irq() };
+///   // ...
+///   if let Some(width) = capture.pulse_width_us() {
+///       let distance_mm = width.0 * 343 / 2_000;
+///   }
+/// ```
+///
+/// The 32 bit counter is extended to 64 bits in software: a wrap is detected when the count
+/// went backwards since the previous edge or reading, so measurements stay correct as long as
+/// one of them happens at least once per wrap, every 71 minutes at the default 1 MHz tick.
+///
+/// Every edge has to be handled before the next one arrives, so pulses and gaps shorter than
+/// the interrupt latency, a few µs at 144 MHz, are lost; this limits the input to roughly
+/// 100 kHz. The schmitt trigger of the pin is enabled to suppress chatter on slow edges.
+pub struct Capture<MODE> {
+    pin: ErasedPin<Input<MODE>>,
+    timer: Timer,
+}
+
+impl<MODE> Capture<MODE> {
+    /// Start timestamping both edges of `pin` with `timer`
+    pub fn new(pin: ErasedPin<Input<MODE>>, timer: Timer) -> Self {
+        let id = timer.id;
+        let number = pin.pin_number();
+        CAPTURES[id as usize].lend(CaptureState {
+            pin: number,
+            last_count: count(id),
+            wraps: 0,
+            rise: None,
+            width: None,
+            period: None,
+        });
+        pad::set_schmitt(number, true);
+        irq::register_handler(
+            number,
+            if id == 0 {
+                capture_edge0
+            } else {
+                capture_edge1
+            },
+        );
+        pad::enable_interrupt(number, TriggerMode::BothEdges);
+        Capture { pin, timer }
+    }
+
+    /// Rate of the timestamps, see [`Timer::tick_rate`]
+    pub fn tick_rate(&self) -> Hertz {
+        self.timer.tick_rate()
+    }
+
+    /// Time from the last rising edge to the falling edge after it, in ticks
+    ///
+    /// `None` until a full high pulse has been seen.
+    pub fn pulse_width(&mut self) -> Option<u64> {
+        self.update().and_then(|state| state.0)
+    }
+
+    /// Time between the last two rising edges, in ticks
+    ///
+    /// `None` until two rising edges have been seen.
+    pub fn period(&mut self) -> Option<u64> {
+        self.update().and_then(|state| state.1)
+    }
+
+    /// [`pulse_width`](Self::pulse_width) converted to microseconds
+    pub fn pulse_width_us(&mut self) -> Option<Microseconds<u64>> {
+        let width = self.pulse_width()?;
+        Some(self.ticks_to_us(width))
+    }
+
+    /// [`period`](Self::period) converted to microseconds
+    pub fn period_us(&mut self) -> Option<Microseconds<u64>> {
+        let period = self.period()?;
+        Some(self.ticks_to_us(period))
+    }
+
+    /// Forget the measurements so far
+    pub fn reset(&mut self) {
+        CAPTURES[self.timer.id as usize].borrow_in_isr(|state| {
+            state.rise = None;
+            state.width = None;
+            state.period = None;
+        });
+    }
+
+    /// Stop capturing and return the pin and the counter
+    pub fn free(self) -> (ErasedPin<Input<MODE>>, Timer) {
+        let number = self.pin.pin_number();
+        pad::set_interrupt_mask(number, true);
+        irq::unregister_handler(number);
+        CAPTURES[self.timer.id as usize].take();
+        (self.pin, self.timer)
+    }
+
+    /// Note counter wraps since the last edge, then read out width and period
+    fn update(&mut self) -> Option<(Option<u64>, Option<u64>)> {
+        let id = self.timer.id;
+        // read the counter with interrupts disabled, so an edge can't extend a newer count first
+        CAPTURES[id as usize].borrow_in_isr(|state| {
+            state.extend(count(id));
+            (state.width, state.period)
+        })
+    }
+
+    fn ticks_to_us(&self, ticks: u64) -> Microseconds<u64> {
+        Microseconds((ticks as u128 * 1_000_000 / self.timer.tick as u128) as u64)
+    }
+}
+
+/// Software PWM on any output pins, driven by the compare interrupt of a [`Channel`]
+///
+/// At the start of every period the pins with a non-zero duty go high, and each pin goes low
+/// again at its own compare event. All pins change through [`gpio::write_all`](crate::gpio::write_all),
+/// so pins switching on the same event switch together. New duties are latched at the next
+/// period boundary, a period is never cut short or stretched by an update.
+///
+/// The channel interrupt, `TIMER_CH0` for `timer0` and `TIMER_CH1` for `timer1`, has to call
+/// [`SoftPwm::on_interrupt`], so lend the PWM to the handler:
+///
+/// ```rust
+///   static LEDS: IsrCell<SoftPwm<PullUp, 2>> = IsrCell::new();
+///
+///   // TIMER_CH0 handler
+///   LEDS.borrow_in_isr(|pwm| pwm.on_interrupt());
+///
+///   let ch = timers.timer0.channels().ch0;
+///   let pins = [
+///       parts.pin17.into_pull_up_output().downgrade(),
+///       parts.pin18.into_pull_up_output().downgrade(),
+///   ];
+///   LEDS.lend(SoftPwm::new(ch, pins, Microseconds(1_000u32)));
+///   unsafe { interrupt::enable(Interrupt::TIMER_CH0) };
+///   LEDS.borrow_in_isr(|pwm| pwm.set_duty(0, pwm.max_duty() / 4));
+/// ```
+///
+/// # Frequency limit
+/// Every period takes up to `N + 1` interrupts. An interrupt through the riscv-rt trap entry
+/// costs in the order of 300 core cycles, about 2 µs at 144 MHz and 9 µs at 32 MHz. Keeping the
+/// interrupt load below half of the core gives a maximum PWM frequency of roughly
+/// `sysclk / (600 * (N + 1))`: 120 kHz for one pin and 30 kHz for 7 pins at 144 MHz, 27 kHz and
+/// 6.7 kHz at 32 MHz. The duty resolution is one counter tick, so at the default 1 MHz tick a
+/// 1 kHz PWM has 1000 steps. Edges closer together than one interrupt are merged into the later
+/// one.
+pub struct SoftPwm<MODE, const N: usize> {
+    channel: Channel,
+    pins: [ErasedPin<Output<MODE>>; N],
+    mask: u32,
+    /// Period in counter ticks
+    period: u32,
+    /// Duties in counter ticks, latched at the next period boundary
+    duty: [u32; N],
+    /// Duties of the current period
+    active: [u32; N],
+    /// Counter value the current period started at
+    start: u32,
+}
+
+impl<MODE, const N: usize> SoftPwm<MODE, N> {
+    /// Start a PWM with `period` on `pins`, all at 0 duty
+    ///
+    /// # Panics
+    /// if `period` is shorter than 2 ticks or longer than [`Channel::max_timeout`]
+    pub fn new<T: Into<Microseconds<u64>>>(
+        mut channel: Channel,
+        pins: [ErasedPin<Output<MODE>>; N],
+        period: T,
+    ) -> Self {
+        let ticks = us_to_ticks(period.into(), channel.tick);
+        assert!(
+            (2..=u32::MAX as u64).contains(&ticks),
+            "soft PWM period out of range"
+        );
+        let mask = pins
+            .iter()
+            .fold(0, |mask, pin| mask | (1 << pin.pin_number()));
+        write_all(mask, 0);
+
+        channel.running = false;
+        channel.clear();
+        let start = count(channel.timer).wrapping_add(1);
+        set_compare(channel.timer, channel.id, start.wrapping_add(ticks as u32));
+        channel.listen();
+        SoftPwm {
+            channel,
+            pins,
+            mask,
+            period: ticks as u32,
+            duty: [0; N],
+            active: [0; N],
+            start,
+        }
+    }
+
+    /// Duty that keeps a pin high for the whole period
+    pub fn max_duty(&self) -> u16 {
+        self.period.min(u16::MAX as u32) as u16
+    }
+
+    /// Set the duty of `pins[index]`, from 0 to [`SoftPwm::max_duty`], from the next period on
+    ///
+    /// # Panics
+    /// if `index` is out of range
+    pub fn set_duty(&mut self, index: usize, duty: u16) {
+        let max = self.max_duty() as u64;
+        let duty = (duty as u64).min(max);
+        self.duty[index] = (duty * self.period as u64 / max) as u32;
+    }
+
+    /// Duty of `pins[index]` as last set
+    ///
+    /// # Panics
+    /// if `index` is out of range
+    pub fn duty(&self, index: usize) -> u16 {
+        (self.duty[index] as u64 * self.max_duty() as u64 / self.period as u64) as u16
+    }
+
+    /// Drive the pins for the compare event that passed, call from the channel interrupt
+    pub fn on_interrupt(&mut self) {
+        if !self.channel.is_pending() {
+            return;
+        }
+        self.channel.clear();
+        let mut offset = compare(self.channel.timer, self.channel.id).wrapping_sub(self.start);
+        loop {
+            if offset >= self.period {
+                self.start = self.start.wrapping_add(self.period);
+                self.active = self.duty;
+                offset = 0;
+            }
+            let high = self
+                .pins
+                .iter()
+                .zip(self.active.iter())
+                .filter(|(_, &duty)| duty > offset)
+                .fold(0, |bits, (pin, _)| bits | (1 << pin.pin_number()));
+            write_all(self.mask, high);
+
+            let next = self
+                .active
+                .iter()
+                .copied()
+                .filter(|&duty| duty > offset && duty < self.period)
+                .min()
+                .unwrap_or(self.period);
+            set_compare(
+                self.channel.timer,
+                self.channel.id,
+                self.start.wrapping_add(next),
+            );
+            // an event that already passed would only match after a counter wrap, handle it now
+            if count(self.channel.timer).wrapping_sub(self.start) < next {
+                break;
+            }
+            self.channel.clear();
+            offset = next;
+        }
+    }
+
+    /// Stop the PWM, drive the pins low and return the channel and the pins
+    pub fn free(mut self) -> (Channel, [ErasedPin<Output<MODE>>; N]) {
+        self.channel.unlisten();
+        self.channel.clear();
+        write_all(self.mask, 0);
+        (self.channel, self.pins)
+    }
+}
+
+/// Counter a [`Timeout`] is measured on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimeoutCounter {
+    /// The core cycle counter, stops while the core sleeps in `wfi`
+    Mcycle,
+    /// The machine timer, keeps counting while the core sleeps
+    Mtime,
+    /// No counter, the timeout never expires
+    Never,
+}
+
+/// Deadline for a polling loop
+#[derive(Debug, Clone, Copy)]
+pub struct Timeout {
+    counter: TimeoutCounter,
+    start: u64,
+    ticks: u64,
+}
+
+impl Timeout {
+    /// Expire `timeout` from now, counted in core cycles at `Clocks::sysclk`
+    pub fn mcycle<T: Into<Microseconds<u64>>>(timeout: T, clocks: &Clocks) -> Self {
+        Self::from_cycles(us_to_ticks(timeout.into(), clocks.sysclk().0))
+    }
+
+    /// Expire `timeout` from now, counted on `mtime` at `Clocks::mtimer_clk`
+    ///
+    /// Unlike `mcycle`, this keeps counting while the core waits in `wfi`.
+    pub fn mtime<T: Into<Microseconds<u64>>>(timeout: T, clocks: &Clocks) -> Self {
+        Timeout {
+            counter: TimeoutCounter::Mtime,
+            start: mtime(),
+            ticks: us_to_ticks(timeout.into(), clocks.mtimer_clk().0),
+        }
+    }
+
+    /// A timeout that never expires, for callers that let the user disable the timeout
+    pub const fn never() -> Self {
+        Timeout {
+            counter: TimeoutCounter::Never,
+            start: 0,
+            ticks: 0,
+        }
+    }
+
+    /// Expire `cycles` core cycles from now
+    pub(crate) fn from_cycles(cycles: u64) -> Self {
+        Timeout {
+            counter: TimeoutCounter::Mcycle,
+            start: McycleDelay::get_cycle_count(),
+            ticks: cycles,
+        }
+    }
+
+    /// Start counting again from now, e.g. after a polling loop made progress
+    pub fn restart(&mut self) {
+        self.start = self.now();
+    }
+
+    /// Returns true once the timeout has passed
+    pub fn expired(&self) -> bool {
+        self.counter != TimeoutCounter::Never && self.now().wrapping_sub(self.start) > self.ticks
+    }
+
+    /// `Err(TimeoutExpired)` once the timeout has passed, for use with `?`
+    pub fn check(&self) -> Result<(), TimeoutExpired> {
+        if self.expired() {
+            Err(TimeoutExpired)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn now(&self) -> u64 {
+        match self.counter {
+            TimeoutCounter::Mcycle => McycleDelay::get_cycle_count(),
+            TimeoutCounter::Mtime => mtime(),
+            TimeoutCounter::Never => 0,
+        }
+    }
+}
+
+/// A [`Timeout`] passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeoutExpired;
+
+/// Error of [`run_with_timeout`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutError<E> {
+    /// The timeout passed before the operation completed
+    Expired,
+    /// The operation failed
+    Other(E),
+}
+
+impl<E> From<TimeoutExpired> for TimeoutError<E> {
+    fn from(_: TimeoutExpired) -> Self {
+        TimeoutError::Expired
+    }
+}
+
+/// Poll `op` until it completes or fails, or `timeout` expires
+///
+/// `op` is polled at least once, even if `timeout` already expired.
+pub fn run_with_timeout<T, E>(
+    timeout: &Timeout,
+    mut op: impl FnMut() -> nb::Result<T, E>,
+) -> Result<T, TimeoutError<E>> {
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(nb::Error::Other(e)) => return Err(TimeoutError::Other(e)),
+            Err(nb::Error::WouldBlock) => timeout.check()?,
+        }
+    }
+}
+
+/// Ticks at `freq` Hz covering at least `timeout`
+fn us_to_ticks(timeout: Microseconds<u64>, freq: u32) -> u64 {
+    let ticks = (timeout.0 as u128 * freq as u128).div_ceil(1_000_000);
+    ticks.min(u64::MAX as u128) as u64
+}
+
+#[cfg(feature = "async")]
+static CHANNEL_WAKERS: [crate::interrupt::WakerSlot; 6] =
+    [const { crate::interrupt::WakerSlot::new() }; 6];
+
+/// Interrupt handler for the async channel methods
+///
+/// Call this from the handlers of both `TIMER_CH0` and `TIMER_CH1`. It masks the channels that
+/// matched and wakes the tasks waiting on them, the match flags are left for the futures.
+#[cfg(feature = "async")]
+pub fn on_interrupt() {
+    let regs = unsafe { &*pac::TIMER::ptr() };
+    for timer in 0..2u8 {
+        let (status, enabled) = if timer == 0 {
+            (regs.tmsr2.read().bits(), regs.tier2.read().bits())
+        } else {
+            (regs.tmsr3.read().bits(), regs.tier3.read().bits())
+        };
+        let matched = status & enabled & 0b111;
+        set_interrupt_enable(timer, matched, false);
+        for id in (0..3).filter(|id| matched & (1 << id) != 0) {
+            CHANNEL_WAKERS[timer as usize * 3 + id].wake();
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl Channel {
+    /// Wait until the compare match flag is set
+    async fn wait_match(&mut self) {
+        use core::task::Poll;
+
+        /// Masks the interrupt again if the future is dropped before the match
+        struct Disarm(u8, u8);
+
+        impl Drop for Disarm {
+            fn drop(&mut self) {
+                set_interrupt_enable(self.0, 1 << self.1, false);
+            }
+        }
+
+        let _disarm = Disarm(self.timer, self.id);
+        let slot = self.timer as usize * 3 + self.id as usize;
+        core::future::poll_fn(|cx| {
+            if self.is_pending() {
+                return Poll::Ready(());
+            }
+            CHANNEL_WAKERS[slot].register(cx.waker());
+            self.listen();
+            // check again, the match may have happened before the interrupt was enabled
+            if self.is_pending() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Sleep for `duration`, rounded up to whole ticks
+    ///
+    /// # Panics
+    /// if `duration` is longer than [`Channel::max_timeout`]
+    pub async fn delay<T: Into<Microseconds<u64>>>(&mut self, duration: T) {
+        self.mode = Mode::OneShot;
+        self.start(duration);
+        self.wait_match().await;
+        self.cancel();
+    }
+
+    /// Start a periodic timer, see [`Interval::next`]
+    ///
+    /// # Panics
+    /// if `period` is longer than [`Channel::max_timeout`]
+    pub fn interval<T: Into<Microseconds<u64>>>(&mut self, period: T) -> Interval<'_> {
+        self.mode = Mode::Periodic;
+        self.start(period);
+        Interval { channel: self }
+    }
+}
+
+/// Async delay on a channel, the interrupt must be routed to [`on_interrupt`]
+///
+/// Delays are rounded up to whole ticks, 1 µs at the default prescaler. Delays longer than
+/// [`Channel::max_timeout`] are split up.
+#[cfg(feature = "async")]
+impl embedded_hal_async::delay::DelayNs for Channel {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.delay_long((ns as u64).div_ceil(1_000)).await
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        self.delay_long(us as u64).await
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.delay_long(ms as u64 * 1_000).await
+    }
+}
+
+#[cfg(feature = "async")]
+impl Channel {
+    /// Sleep for `us` microseconds, in steps of at most `max_timeout`
+    async fn delay_long(&mut self, mut us: u64) {
+        let max = self.max_timeout().0;
+        while us > 0 {
+            let step = us.min(max);
+            self.delay(Microseconds(step)).await;
+            us -= step;
+        }
+    }
+}
+
+/// Periodic async timer on a [`Channel`], from [`Channel::interval`]
+#[cfg(feature = "async")]
+pub struct Interval<'a> {
+    channel: &'a mut Channel,
+}
+
+#[cfg(feature = "async")]
+impl Interval<'_> {
+    /// Wait for the next period to start
+    ///
+    /// Returns the number of periods that passed unnoticed because the task did not call `next`
+    /// in time, 0 normally. Those periods are skipped, the next one starts on the regular
+    /// schedule after the current time.
+    pub async fn next(&mut self) -> u32 {
+        let channel = &mut *self.channel;
+        channel.wait_match().await;
+        channel.clear();
+        let deadline = compare(channel.timer, channel.id);
+        let late = count(channel.timer).wrapping_sub(deadline);
+        let missed = late / channel.period;
+        let next = deadline.wrapping_add(channel.period.wrapping_mul(missed + 1));
+        set_compare(channel.timer, channel.id, next);
+        missed
+    }
+}
+
+#[cfg(feature = "async")]
+impl Drop for Interval<'_> {
+    fn drop(&mut self) {
+        self.channel.cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply a clock select and divider write of `id` to the register values
+    fn configure(regs: &mut (u32, u32), id: u8, sel: u8, div: u8) {
+        regs.0 = merge_tccr(regs.0, id, sel);
+        regs.1 = merge_tcdr(regs.1, id, div);
+    }
+
+    #[test]
+    fn configuring_keeps_other_fields() {
+        // timer0, timer1 and the watchdog set up in every order, as split and Watchdog::new do
+        let setups = [(0, 3, 31), (1, 2, 0), (WDT_ID, 1, 255)];
+        let orders = [[0, 1, 2], [0, 2, 1], [1, 0, 2], [1, 2, 0], [2, 0, 1], [2, 1, 0]];
+        for order in orders {
+            // bits outside the three fields of each register are kept as well
+            let mut regs = (0xffff_fc93, 0x0000_00a5);
+            for index in order {
+                let (id, sel, div) = setups[index];
+                configure(&mut regs, id, sel, div);
+            }
+            for (id, sel, div) in setups {
+                assert_eq!(clock_select(regs.0, id), sel);
+                assert_eq!(divider(regs.1, id), div);
+            }
+            let fields = (0b11 << 2) | (0b11 << 5) | (0b11 << 8);
+            assert_eq!(regs.0 & !fields, 0xffff_fc93 & !fields);
+            assert_eq!(regs.1 & 0xff, 0xa5);
+        }
+    }
+
+    #[test]
+    fn reconfiguring_keeps_other_fields() {
+        let mut regs = (0, 0);
+        configure(&mut regs, 0, 1, 7);
+        configure(&mut regs, 1, 2, 15);
+        configure(&mut regs, WDT_ID, 1, 127);
+        // the watchdog timeout and timer0 clock change later
+        configure(&mut regs, WDT_ID, 1, 3);
+        configure(&mut regs, 0, 3, 0);
+        assert_eq!((clock_select(regs.0, 0), divider(regs.1, 0)), (3, 0));
+        assert_eq!((clock_select(regs.0, 1), divider(regs.1, 1)), (2, 15));
+        assert_eq!((clock_select(regs.0, WDT_ID), divider(regs.1, WDT_ID)), (1, 3));
+    }
+
+    #[test]
+    fn us_to_ticks_does_not_wrap() {
+        assert_eq!(us_to_ticks(Microseconds(1), 1_000_000), 1);
+        assert_eq!(us_to_ticks(Microseconds(1), 32_768), 1);
+        assert_eq!(us_to_ticks(Microseconds(1_000_000), 32_768), 32_768);
+        // us * tick wraps u64 here, the result must stay above u32::MAX so `start` rejects it
+        let wrapping = Microseconds(u64::MAX / 1_000_000 + 1);
+        assert!(us_to_ticks(wrapping, 1_000_000) > u32::MAX as u64);
+        assert_eq!(us_to_ticks(Microseconds(u64::MAX), u32::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn split_reads_the_clock_select() {
+        for (sel, source) in [
+            (0, TimerClkSource::Fclk),
+            (1, TimerClkSource::F32k),
+            (2, TimerClkSource::OneKhz),
+            (3, TimerClkSource::Xclk),
+        ] {
+            let tccr = merge_tccr(merge_tccr(0, 0, sel), 1, 3 - sel);
+            assert_eq!(source_from_bits(clock_select(tccr, 0)), source);
+            assert_eq!(source as u8, sel);
+        }
+    }
+}
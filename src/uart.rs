@@ -1,15 +1,66 @@
 //! UART driver
 //!
-//! Only supports UART0. Only supports 2MBaud.
-use crate::clock::Clocks;
+//! The baud rate is derived from `Clocks::uart_clk`:
+//!
+//! ```rust
+//!   let tx = parts.pin14.into_uart0_tx(parts.uart_mux6);
+//!   let rx = parts.pin15.into_uart0_rx(parts.uart_mux7);
+//!   let config = Config::default().baudrate(115_200.Bd());
+//!   let mut serial = Serial::new(dp.UART, (tx, rx), config, &clocks)?;
+//!   nb::block!(serial.write(b'>'))?;
+//!   let byte = nb::block!(serial.read())?;
+//! ```
+//!
+//! Hardware flow control is enabled by passing RTS and CTS pins, use [`NoRts`] or [`NoCts`] for
+//! a missing one:
+//!
+//! ```rust
+//!   let cts = parts.pin16.into_uart0_cts(parts.uart_mux0);
+//!   let serial = Serial::new(dp.UART, (tx, rx, NoRts, cts), config, &clocks)?;
+//! ```
+//!
+//! With CTS, the transmitter pauses while CTS is high. Writes and flushes block for as long as
+//! that takes, they have no timeout that could fire spuriously.
+//!
+//! [`Serial::split`] separates the two directions, so the receiver can be moved into an
+//! interrupt handler while the main loop keeps transmitting.
+//!
+//! With [`Serial::into_buffered_rx`] the UART interrupt moves received bytes into a
+//! [`RxBuffer`], so they aren't lost while the main loop is busy.
+//!
+//! [`Serial::write_dma`] hands long writes to the DMA controller.
+//!
+//! Both UARTs are supported, `UART0` comes from the PAC and [`UART1`] from this module, and
+//! each has its own pin conversions, `into_uart1_tx` etc. for UART1.
+//!
+//! For printf style debugging, [`uprint!`](crate::uprint) and [`uprintln!`](crate::uprintln)
+//! write to a transmitter, or to the one installed with [`install_global`]:
+//!
+//! ```rust
+//!   let (mut tx, rx) = serial.split();
+//!   uprintln!(tx, "boot {}", count);
+//!   serial::install_global(tx);
+//!   uprintln!("from anywhere, also from interrupt handlers");
+//! ```
+//!
+//! The hardware flags parity errors and RX FIFO overruns, which are reported by the next read.
+//! It has no framing or noise detection, so [`Error::Framing`] and [`Error::Noise`] are not
+//! returned by this driver.
+use crate::clock::{self, Clocks, Gate};
+use crate::dma::{self, Channel, Direction, Transfer, TransferPayload, Width};
+use crate::interrupt::Interrupt;
 use crate::{pac, uart};
 
 use core::fmt;
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU8, AtomicUsize, Ordering};
 use embedded_time::rate::{Baud, Extensions};
 
 #[cfg(feature = "print_serial")]
 use core::convert::Infallible;
-use bl702_pac::UART;
+use embedded_dma::ReadBuffer;
 use embedded_io::{ErrorKind, Write};
 
 /// UART error
@@ -24,6 +75,8 @@ pub enum Error {
     Overrun,
     /// Parity check error
     Parity,
+    /// The baud rate can't be derived from the UART clock, the bit period is out of range
+    Baudrate,
 }
 
 impl embedded_io::Error for uart::Error {
@@ -41,10 +94,55 @@ impl embedded_io::Error for uart::Error {
             Error::Parity => {
                 ErrorKind::InvalidData
             }
+            Error::Baudrate => {
+                ErrorKind::InvalidInput
+            }
         }
     }
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::Framing => "UART framing error",
+            Error::Noise => "noise on the UART line",
+            Error::Overrun => "UART RX FIFO overrun, received bytes were lost",
+            Error::Parity => "UART parity error",
+            Error::Baudrate => "UART baud rate out of range of the UART clock",
+        })
+    }
+}
+
+impl core::error::Error for Error {}
+
+impl embedded_hal_nb::serial::Error for Error {
+    fn kind(&self) -> embedded_hal_nb::serial::ErrorKind {
+        use embedded_hal_nb::serial::ErrorKind;
+        match self {
+            Error::Framing => ErrorKind::FrameFormat,
+            Error::Noise => ErrorKind::Noise,
+            Error::Overrun => ErrorKind::Overrun,
+            Error::Parity => ErrorKind::Parity,
+            Error::Baudrate => ErrorKind::Other,
+        }
+    }
+}
+
+/// Depth of the TX and RX FIFOs
+const FIFO_DEPTH: u8 = 128;
+
+/// Bit period register value for `baudrate` at `uart_clk`, rounded to the nearest divisor
+fn bit_period(uart_clk: u32, baudrate: u32) -> Result<u16, Error> {
+    if baudrate == 0 {
+        return Err(Error::Baudrate);
+    }
+    let divisor = (uart_clk + baudrate / 2) / baudrate;
+    if divisor == 0 || divisor > u16::MAX as u32 + 1 {
+        return Err(Error::Baudrate);
+    }
+    Ok((divisor - 1) as u16)
+}
+
 /// Serial configuration
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Config {
@@ -90,6 +188,13 @@ impl Config {
 
         self
     }
+
+    /// Sets the number of data bits
+    pub fn wordlength(mut self, wordlength: WordLength) -> Self {
+        self.wordlength = wordlength;
+
+        self
+    }
 }
 
 impl Default for Config {
@@ -166,23 +271,124 @@ pub enum Event {
     TxTransferEnd,
 }
 
+/// UART0, the PAC names it `UART`
+pub use pac::UART as UART0;
+
+/// Base address of the UART1 registers
+const UART1_BASE: usize = 0x4000_a100;
+
+/// Set once [`UART1::take`] handed out the token
+static UART1_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// UART1
+///
+/// The PAC only describes UART0, UART1 has the same register layout 0x100 above it. Take the
+/// token once, like the PAC peripherals:
+///
+/// ```rust
+///   let uart1 = UART1::take().unwrap();
+///   let gps = Serial::new(uart1, (tx, rx), Config::default().baudrate(9600.Bd()), &clocks)?;
+/// ```
+pub struct UART1 {
+    _marker: PhantomData<*const ()>,
+}
+
+unsafe impl Send for UART1 {}
+
+impl UART1 {
+    /// Pointer to the register block
+    pub const PTR: *const pac::uart::RegisterBlock = UART1_BASE as *const _;
+
+    /// Take the token, `None` if it was taken before
+    pub fn take() -> Option<Self> {
+        if UART1_TAKEN.swap(true, Ordering::Relaxed) {
+            return None;
+        }
+        Some(UART1 {
+            _marker: PhantomData,
+        })
+    }
+
+    /// Create the token regardless of whether it was taken
+    ///
+    /// # Safety
+    /// the registers must not be used by two drivers at once
+    pub unsafe fn steal() -> Self {
+        UART1_TAKEN.store(true, Ordering::Relaxed);
+        UART1 {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Deref for UART1 {
+    type Target = pac::uart::RegisterBlock;
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*Self::PTR }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+impl sealed::Sealed for UART0 {}
+impl sealed::Sealed for UART1 {}
+
+/// A UART peripheral, [`UART0`] or [`UART1`]
+pub trait Instance: sealed::Sealed + Deref<Target = pac::uart::RegisterBlock> {
+    /// Clock gate of the UART
+    const GATE: Gate;
+    /// DMA request line of the TX FIFO
+    const DMA_TX: dma::Request;
+    /// Interrupt of the UART, for [`RxBuffer::on_interrupt`]
+    const INTERRUPT: Interrupt;
+
+    /// Pointer to the register block
+    fn ptr() -> *const pac::uart::RegisterBlock;
+}
+
+impl Instance for UART0 {
+    const GATE: Gate = Gate::Uart0;
+    const DMA_TX: dma::Request = dma::Request::Uart0Tx;
+    const INTERRUPT: Interrupt = Interrupt::UART0;
+
+    fn ptr() -> *const pac::uart::RegisterBlock {
+        UART0::PTR
+    }
+}
+
+impl Instance for UART1 {
+    const GATE: Gate = Gate::Uart1;
+    const DMA_TX: dma::Request = dma::Request::Uart1Tx;
+    const INTERRUPT: Interrupt = Interrupt::UART1;
+
+    fn ptr() -> *const pac::uart::RegisterBlock {
+        UART1::PTR
+    }
+}
+
 /// Serial abstraction
 pub struct Serial<UART, PINS> {
     uart: UART,
     pins: PINS,
 }
 
-impl<PINS> Serial<pac::UART, PINS>
+impl<UART, PINS> Serial<UART, PINS>
 where
-    PINS: Pins<pac::UART>,
+    UART: Instance,
+    PINS: Pins<UART>,
 {
-    // todo: there is UART0 and UART1
-    // todo: use clocks
-    pub fn uart0(uart: pac::UART, config: Config, pins: PINS, _clocks: Clocks) -> Self {
-        // Initialize clocks and baudrate
-        // let uart_clk = clocks.uart_clk();
-        // let mut baud = config.baudrate.0;
-        let divisor = 48; // bring uart down from 96MHz to 2Mhz
+    /// Configures `uart` on `pins`
+    ///
+    /// Fails with `Error::Baudrate` if `config.baudrate` can't be derived from
+    /// `Clocks::uart_clk`.
+    pub fn new(uart: UART, pins: PINS, config: Config, clocks: &Clocks) -> Result<Self, Error> {
+        debug_assert!(clocks.is_current(), "clocks are stale after a system clock switch");
+        let bit_prd = bit_period(clocks.uart_clk().0, config.baudrate.0)?;
+
+        clock::enable_gate(UART::GATE);
 
         // Disable uart first
         uart.utx_config.modify(|_, w| w.cr_utx_en().clear_bit());
@@ -190,9 +396,9 @@ where
 
         uart.uart_bit_prd.write(|w| unsafe {
             w.cr_urx_bit_prd()
-                .bits(divisor - 1)
+                .bits(bit_prd)
                 .cr_utx_bit_prd()
-                .bits(divisor - 1)
+                .bits(bit_prd)
         });
 
         // Bit inverse configuration; MsbFirst => 1, LsbFirst => 0
@@ -229,7 +435,7 @@ where
             w.cr_utx_bit_cnt_d().bits(data_bits_cfg);
             w.cr_utx_bit_cnt_p().bits(stop_bits_cfg);
             w.cr_utx_frm_en().set_bit(); // [!] freerun on // todo
-            w.cr_utx_cts_en().bit(false); // force CTS off for testing
+            w.cr_utx_cts_en().bit(PINS::HAS_CTS); // hold back TX while CTS is high
             w
         });
         uart.utx_config
@@ -242,8 +448,6 @@ where
             w.cr_urx_bit_cnt_d().bits(data_bits_cfg);
             w.cr_urx_deg_en().clear_bit();
             // no rx input de-glitch // todo
-            // .cr_urx_rts_sw_mode()
-            // .clear_bit() // no RTS // todo
             w.cr_urx_en().bit(PINS::HAS_RX);
             w
         });
@@ -251,74 +455,185 @@ where
         uart.urx_config
             .modify(|_, w| unsafe { w.cr_urx_deg_cnt().bits(15) });
 
-        Serial { uart, pins }
+        // RTS follows the RX FIFO in hardware, instead of the software value
+        uart.uart_sw_mode
+            .modify(|_, w| w.cr_urx_rts_sw_mode().bit(!PINS::HAS_RTS));
+
+        Ok(Serial { uart, pins })
     }
 
-    pub fn free(self) -> (pac::UART, PINS) {
+    pub fn free(self) -> (UART, PINS) {
         // todo!
         (self.uart, self.pins)
     }
 }
 
-impl<PINS> embedded_io::Write for Serial<pac::UART, PINS> {
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        if buf.len() == 0 {
-            return Ok(0);
+impl<PINS> Serial<UART0, PINS>
+where
+    PINS: Pins<UART0>,
+{
+    /// Like [`Serial::new`], with the arguments in the order of earlier releases
+    ///
+    /// # Panics
+    /// if `config.baudrate` can't be derived from `Clocks::uart_clk`
+    pub fn uart0(uart: UART0, config: Config, pins: PINS, clocks: Clocks) -> Self {
+        Self::new(uart, pins, config, &clocks).expect("baud rate out of range")
+    }
+}
+
+/// Returns the error the receiver flagged, and clears it
+///
+/// An overrun also drops what is left in the RX FIFO, as the hardware clears the flag together
+/// with the FIFO.
+fn take_rx_error(uart: &pac::uart::RegisterBlock) -> Result<(), Error> {
+    if uart.uart_fifo_config_0.read().rx_fifo_overflow().bit() {
+        // the register also holds TX bits, which the other half may be changing
+        riscv::interrupt::free(|| {
+            uart.uart_fifo_config_0
+                .modify(|_, w| w.rx_fifo_clr().set_bit())
+        });
+        return Err(Error::Overrun);
+    }
+    if uart.uart_int_sts.read().urx_pce_int().bit() {
+        uart.uart_int_clear.write(|w| w.cr_urx_pce_clr().set_bit());
+        return Err(Error::Parity);
+    }
+    Ok(())
+}
+
+/// Bytes waiting in the RX FIFO
+fn rx_count(uart: &pac::uart::RegisterBlock) -> usize {
+    uart.uart_fifo_config_1.read().rx_fifo_cnt().bits() as usize
+}
+
+/// Free entries in the TX FIFO
+fn tx_space(uart: &pac::uart::RegisterBlock) -> usize {
+    uart.uart_fifo_config_1.read().tx_fifo_cnt().bits() as usize
+}
+
+/// Returns true once the TX FIFO is empty and the last stop bit has been sent
+fn tx_idle(uart: &pac::uart::RegisterBlock) -> bool {
+    tx_space(uart) == FIFO_DEPTH as usize && !uart.uart_status.read().sts_utx_bus_busy().bit()
+}
+
+/// Take one byte from the RX FIFO
+fn read_nb(uart: &pac::uart::RegisterBlock) -> nb::Result<u8, Error> {
+    take_rx_error(uart)?;
+    if rx_count(uart) == 0 {
+        return Err(nb::Error::WouldBlock);
+    }
+    Ok(uart.uart_fifo_rdata.read().uart_fifo_rdata().bits())
+}
+
+/// Put one byte into the TX FIFO
+fn write_nb(uart: &pac::uart::RegisterBlock, word: u8) -> nb::Result<(), Error> {
+    if tx_space(uart) == 0 {
+        return Err(nb::Error::WouldBlock);
+    }
+    uart.uart_fifo_wdata.write(|w| unsafe { w.bits(word as u32) });
+    Ok(())
+}
+
+/// Complete once the transmitter is idle
+fn flush_nb(uart: &pac::uart::RegisterBlock) -> nb::Result<(), Error> {
+    if !tx_idle(uart) {
+        return Err(nb::Error::WouldBlock);
+    }
+    Ok(())
+}
+
+/// Block until the RX FIFO holds a byte, then move as many as fit into `buf`
+fn read_blocking(uart: &pac::uart::RegisterBlock, buf: &mut [u8]) -> Result<usize, Error> {
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    let available = loop {
+        take_rx_error(uart)?;
+        let available = rx_count(uart);
+        if available != 0 {
+            break available;
         }
-        while self.uart.uart_fifo_config_1.read().tx_fifo_cnt().bits() == 0 {}
+    };
+    let n = available.min(buf.len());
+    for byte in &mut buf[..n] {
+        *byte = uart.uart_fifo_rdata.read().uart_fifo_rdata().bits();
+    }
+    Ok(n)
+}
 
-        let mut idx = 0;
-        while self.uart.uart_fifo_config_1.read().tx_fifo_cnt().bits() > 0 {
-            self.uart
-                .uart_fifo_wdata
-                .write(|w| unsafe { w.bits(buf[idx] as u32) });
-            idx += 1;
+/// Block until the TX FIFO has room, then push as much of `buf` as it takes
+fn write_blocking(uart: &pac::uart::RegisterBlock, buf: &[u8]) -> usize {
+    if buf.is_empty() {
+        return 0;
+    }
+    let space = loop {
+        let space = tx_space(uart);
+        if space != 0 {
+            break space;
         }
+    };
+    let n = space.min(buf.len());
+    for &byte in &buf[..n] {
+        uart.uart_fifo_wdata.write(|w| unsafe { w.bits(byte as u32) });
+    }
+    n
+}
+
+impl<UART: Instance, PINS> embedded_hal_nb::serial::ErrorType for Serial<UART, PINS> {
+    type Error = Error;
+}
 
-        Ok(idx)
+impl<UART: Instance, PINS> embedded_hal_nb::serial::Read<u8> for Serial<UART, PINS> {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        read_nb(&self.uart)
+    }
+}
+
+impl<UART: Instance, PINS> embedded_hal_nb::serial::Write<u8> for Serial<UART, PINS> {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        write_nb(&self.uart, word)
     }
 
+    /// Completes once the TX FIFO is empty and the last stop bit has been sent
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        flush_nb(&self.uart)
+    }
+}
+
+impl<UART: Instance, PINS> embedded_io::ErrorType for Serial<UART, PINS> {
+    type Error = Error;
+}
+
+impl<UART: Instance, PINS> embedded_io::Write for Serial<UART, PINS> {
+    /// Blocks until the TX FIFO has room, then pushes as much of `buf` as it takes
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(write_blocking(&self.uart, buf))
+    }
+
+    /// Blocks until the TX FIFO is empty and the last stop bit has been sent
     fn flush(&mut self) -> Result<(), Self::Error> {
-        while self.uart.uart_fifo_config_1.read().tx_fifo_cnt() != 128 {}
+        while !tx_idle(&self.uart) {}
         Ok(())
     }
 }
 
-impl<PINS> embedded_io::ErrorType for Serial<UART, PINS> { type Error = Error; }
+impl<UART: Instance, PINS> embedded_io::WriteReady for Serial<UART, PINS> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(tx_space(&self.uart) != 0)
+    }
+}
 
-impl<PINS> embedded_io::ReadReady for Serial<pac::UART, PINS> {
+impl<UART: Instance, PINS> embedded_io::ReadReady for Serial<UART, PINS> {
     fn read_ready(&mut self) -> Result<bool, Self::Error> {
-        Ok(self.uart.uart_fifo_config_1.read().rx_fifo_cnt().bits() != 0)
+        take_rx_error(&self.uart)?;
+        Ok(rx_count(&self.uart) != 0)
     }
 }
 
-// impl<PINS> embedded_io::Write for Serial<pac::UART, PINS> {
-//
-//     fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
-//         WriteOne::write(self, word)
-//     }
-//
-//     fn flush(&mut self) -> nb::Result<(), Self::Error> {
-//         WriteOne::flush(self)
-//     }
-// }
-
-impl<PINS> embedded_io::Read for Serial<pac::UART, PINS> {
+impl<UART: Instance, PINS> embedded_io::Read for Serial<UART, PINS> {
+    /// Blocks until a byte arrives, then drains the RX FIFO up to the size of `buf`
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        if buf.len() == 0 {
-            return Ok(0);
-        }
-        while self.uart.uart_fifo_config_1.read().rx_fifo_cnt().bits() == 0 {}
-
-        let mut idx = 0;
-        while self.uart.uart_fifo_config_1.read().rx_fifo_cnt().bits() > 0 && idx < buf.len() {
-            buf[idx] = self.uart
-                .uart_fifo_rdata
-                .read().uart_fifo_rdata().bits();
-            idx += 1;
-        }
-
-        Ok(idx)
+        read_blocking(&self.uart, buf)
     }
 }
 
@@ -327,7 +642,61 @@ where
     Serial<UART, PINS>: embedded_io::Write,
 {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        match self.write(s.as_bytes()) {
+        match self.write_all(s.as_bytes()) {
+            Ok(_) => {
+                fmt::Result::Ok(())
+            }
+            Err(_) => {
+                fmt::Result::Err(fmt::Error)
+            }
+        }
+    }
+}
+
+impl<UART: Instance, PINS> Serial<UART, PINS> {
+    /// Split into a transmitter and a receiver that can be used independently, e.g. the
+    /// receiver in an interrupt handler and the transmitter in the main loop
+    ///
+    /// Each half only touches the FIFO and status bits of its own direction. The transmitter
+    /// keeps the UART and the pins, [`Serial::reunite`] puts them back together.
+    pub fn split(self) -> (TxHalf<UART, PINS>, RxHalf<UART>) {
+        (
+            TxHalf {
+                uart: self.uart,
+                pins: self.pins,
+            },
+            RxHalf {
+                _uart: PhantomData,
+            },
+        )
+    }
+
+    /// Join the halves of [`Serial::split`] again, e.g. to reconfigure the UART
+    ///
+    /// Both halves are typed by their UART instance, so halves of different instances are
+    /// rejected at compile time.
+    pub fn reunite(tx: TxHalf<UART, PINS>, rx: RxHalf<UART>) -> Self {
+        let RxHalf { _uart } = rx;
+        Serial {
+            uart: tx.uart,
+            pins: tx.pins,
+        }
+    }
+}
+
+/// Transmitting half of a [`Serial`]
+pub struct TxHalf<UART, PINS> {
+    uart: UART,
+    pins: PINS,
+}
+
+impl<UART, PINS> fmt::Write for TxHalf<UART, PINS>
+where
+    TxHalf<UART, PINS>: embedded_io::Write,
+{
+    /// Blocks until all of `s` is in the TX FIFO
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.write_all(s.as_bytes()) {
             Ok(_) => {
                 fmt::Result::Ok(())
             }
@@ -338,6 +707,604 @@ where
     }
 }
 
+/// Registers of the transmitter installed by [`install_global`], null if there is none
+static GLOBAL_TX: AtomicPtr<pac::uart::RegisterBlock> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Make `tx` the target of [`uprint!`](crate::uprint) and [`uprintln!`](crate::uprintln)
+/// without a transmitter argument
+///
+/// The transmitter stays installed for the rest of the program, a second call replaces it.
+/// Printing before the first call does nothing.
+pub fn install_global<UART: Instance, PINS>(tx: TxHalf<UART, PINS>) {
+    GLOBAL_TX.store(UART::ptr() as *mut _, Ordering::Release);
+    // the pins stay configured for the UART
+    core::mem::forget(tx);
+}
+
+/// Write `args` to the transmitter of [`install_global`], for the print macros
+///
+/// Formats and writes with interrupts disabled, so prints from interrupt handlers don't land
+/// in the middle of another print.
+#[doc(hidden)]
+pub fn write_global(args: fmt::Arguments) {
+    struct GlobalTx<'a>(&'a pac::uart::RegisterBlock);
+
+    impl fmt::Write for GlobalTx<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let mut buf = s.as_bytes();
+            while !buf.is_empty() {
+                buf = &buf[write_blocking(self.0, buf)..];
+            }
+            Ok(())
+        }
+    }
+
+    let uart = GLOBAL_TX.load(Ordering::Acquire);
+    if uart.is_null() {
+        return;
+    }
+    riscv::interrupt::free(|| {
+        let _ = fmt::write(&mut GlobalTx(unsafe { &*uart }), args);
+    });
+}
+
+/// Print to a serial transmitter, or the global one of [`install_global`]
+///
+/// `uprint!(tx, "{}", x)` writes to `tx`, any [`core::fmt::Write`] such as a [`TxHalf`], pass
+/// `*tx` if `tx` is a `&mut` reference.
+/// `uprint!("{}", x)`, with the format string first, writes to the global transmitter.
+/// Blocks until everything is in the TX FIFO, errors are ignored.
+#[macro_export]
+macro_rules! uprint {
+    ($fmt:literal $($arg:tt)*) => {
+        $crate::uart::write_global(format_args!($fmt $($arg)*))
+    };
+    ($tx:expr, $($arg:tt)*) => {{
+        let _ = ::core::fmt::Write::write_fmt(&mut $tx, format_args!($($arg)*));
+    }};
+}
+
+/// Like [`uprint!`](crate::uprint), with a newline appended
+#[macro_export]
+macro_rules! uprintln {
+    () => {
+        $crate::uart::write_global(format_args!("\n"))
+    };
+    ($fmt:literal $($arg:tt)*) => {
+        $crate::uart::write_global(format_args!("{}\n", format_args!($fmt $($arg)*)))
+    };
+    ($tx:expr) => {{
+        let _ = ::core::fmt::Write::write_str(&mut $tx, "\n");
+    }};
+    ($tx:expr, $fmt:literal $($arg:tt)*) => {{
+        let _ = ::core::fmt::Write::write_fmt(&mut $tx, format_args!("{}\n", format_args!($fmt $($arg)*)));
+    }};
+}
+
+/// Receiving half of a [`Serial`]
+pub struct RxHalf<UART> {
+    _uart: PhantomData<UART>,
+}
+
+impl<UART: Instance> RxHalf<UART> {
+    fn regs(&self) -> &pac::uart::RegisterBlock {
+        // only reads the RX FIFO and the RX error flags, which the transmitter doesn't touch
+        unsafe { &*UART::ptr() }
+    }
+}
+
+impl<UART: Instance, PINS> embedded_hal_nb::serial::ErrorType for TxHalf<UART, PINS> {
+    type Error = Error;
+}
+
+impl<UART: Instance, PINS> embedded_hal_nb::serial::Write<u8> for TxHalf<UART, PINS> {
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        write_nb(&self.uart, word)
+    }
+
+    /// Completes once the TX FIFO is empty and the last stop bit has been sent
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        flush_nb(&self.uart)
+    }
+}
+
+impl<UART: Instance, PINS> embedded_io::ErrorType for TxHalf<UART, PINS> {
+    type Error = Error;
+}
+
+impl<UART: Instance, PINS> embedded_io::Write for TxHalf<UART, PINS> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(write_blocking(&self.uart, buf))
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        while !tx_idle(&self.uart) {}
+        Ok(())
+    }
+}
+
+impl<UART: Instance, PINS> embedded_io::WriteReady for TxHalf<UART, PINS> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(tx_space(&self.uart) != 0)
+    }
+}
+
+impl<UART: Instance> embedded_hal_nb::serial::ErrorType for RxHalf<UART> {
+    type Error = Error;
+}
+
+impl<UART: Instance> embedded_hal_nb::serial::Read<u8> for RxHalf<UART> {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        read_nb(self.regs())
+    }
+}
+
+impl<UART: Instance> embedded_io::ErrorType for RxHalf<UART> {
+    type Error = Error;
+}
+
+impl<UART: Instance> embedded_io::Read for RxHalf<UART> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        read_blocking(self.regs(), buf)
+    }
+}
+
+impl<UART: Instance> embedded_io::ReadReady for RxHalf<UART> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        let uart = self.regs();
+        take_rx_error(uart)?;
+        Ok(rx_count(uart) != 0)
+    }
+}
+
+impl<UART: Instance, PINS> Serial<UART, PINS> {
+    /// Write `buffer` using the DMA controller
+    ///
+    /// The transfer completes once the last stop bit has left the shifter, not when the DMA
+    /// finished filling the FIFO, so the line is idle when the buffer is handed back. Poll
+    /// `Transfer::is_done`, or enable the DMA interrupt, which fires at the end of every chunk
+    /// of up to [`dma::MAX_TRANSFER_SIZE`] bytes, and poll from there.
+    ///
+    /// Each transfer owns its buffer until it completes. Queueing a second buffer behind a
+    /// running one (double buffering) would hand it to the payload, which already reprograms
+    /// the channel between chunks.
+    pub fn write_dma<B>(&mut self, channel: Channel, buffer: B) -> Transfer<B, UartTxDma<'_>>
+    where
+        B: ReadBuffer<Word = u8>,
+    {
+        start_tx_dma(&self.uart, UART::DMA_TX, channel, buffer)
+    }
+}
+
+impl<UART: Instance, PINS> TxHalf<UART, PINS> {
+    /// Write `buffer` using the DMA controller, see [`Serial::write_dma`]
+    pub fn write_dma<B>(&mut self, channel: Channel, buffer: B) -> Transfer<B, UartTxDma<'_>>
+    where
+        B: ReadBuffer<Word = u8>,
+    {
+        start_tx_dma(&self.uart, UART::DMA_TX, channel, buffer)
+    }
+}
+
+/// Start moving `buffer` into the TX FIFO of `uart` with `channel`
+fn start_tx_dma<B>(
+    uart: &pac::uart::RegisterBlock,
+    request: dma::Request,
+    channel: Channel,
+    buffer: B,
+) -> Transfer<B, UartTxDma<'_>>
+where
+    B: ReadBuffer<Word = u8>,
+{
+    let (address, len) = unsafe { buffer.read_buffer() };
+
+    // request DMA for every single free FIFO entry; both registers also hold RX fields, which
+    // the other half may be changing
+    riscv::interrupt::free(|| {
+        uart.uart_fifo_config_1
+            .modify(|_, w| unsafe { w.tx_fifo_th().bits(0) });
+        uart.uart_fifo_config_0
+            .modify(|_, w| w.uart_dma_tx_en().set_bit())
+    });
+
+    let mut payload = UartTxDma {
+        uart,
+        request,
+        channel,
+        address: address as usize,
+        remaining: len,
+    };
+    payload.start_chunk();
+
+    Transfer { buffer, payload }
+}
+
+/// UART side of a DMA write, see [`Serial::write_dma`]
+pub struct UartTxDma<'a> {
+    uart: &'a pac::uart::RegisterBlock,
+    request: dma::Request,
+    channel: Channel,
+    address: usize,
+    remaining: usize,
+}
+
+impl UartTxDma<'_> {
+    fn start_chunk(&mut self) {
+        let len = self.remaining.min(dma::MAX_TRANSFER_SIZE);
+        let register = &self.uart.uart_fifo_wdata as *const _ as usize;
+        self.channel.configure(
+            Direction::MemoryToPeripheral(self.request),
+            self.address,
+            register,
+            len,
+            Width::Byte,
+            true,
+        );
+        self.channel.start();
+        self.address += len;
+        self.remaining -= len;
+    }
+}
+
+unsafe impl TransferPayload for UartTxDma<'_> {
+    type Channels = Channel;
+
+    fn poll(&mut self) -> bool {
+        if !self.channel.is_done() {
+            return false;
+        }
+        if self.remaining == 0 {
+            // the DMA is done once the FIFO took the last byte, the wire is done later
+            return tx_idle(self.uart);
+        }
+        self.start_chunk();
+        false
+    }
+
+    fn finish(mut self) -> Channel {
+        self.abort();
+        self.channel
+    }
+
+    fn abort(&mut self) {
+        self.channel.stop();
+        riscv::interrupt::free(|| {
+            self.uart
+                .uart_fifo_config_0
+                .modify(|_, w| w.uart_dma_tx_en().clear_bit())
+        });
+    }
+}
+
+/// RX FIFO level above which the buffered receiver is interrupted
+const RX_FIFO_THRESHOLD: u8 = 16;
+/// Idle bit times after the last byte before the buffered receiver is interrupted anyway
+const RX_TIMEOUT_BITS: u8 = 40;
+
+/// Ring buffer filled from the UART interrupt, see [`Serial::into_buffered_rx`]
+///
+/// A single producer, the interrupt handler, and a single consumer, the [`BufferedRx`], share it
+/// without locking. Bytes that arrive while it is full are dropped and counted.
+///
+/// `N` must be a power of two, so the slot of an index stays the same when the free running
+/// indices wrap around.
+pub struct RxBuffer<const N: usize> {
+    buf: UnsafeCell<[u8; N]>,
+    /// Bytes ever written, only advanced by the interrupt handler
+    head: AtomicUsize,
+    /// Bytes ever read, only advanced by the `BufferedRx`
+    tail: AtomicUsize,
+    /// Bytes lost to a full buffer or a hardware FIFO overrun
+    dropped: AtomicU32,
+    /// First receive error since the reader last took it, see `error_code`, 0 for none
+    error: AtomicU8,
+    /// Registers of the UART feeding the buffer, null while it is not attached
+    uart: AtomicPtr<pac::uart::RegisterBlock>,
+}
+
+// the indices hand each slot over between the single producer and the single consumer
+unsafe impl<const N: usize> Sync for RxBuffer<N> {}
+
+impl<const N: usize> RxBuffer<N> {
+    /// Create an empty buffer
+    pub const fn new() -> Self {
+        const { assert!(N.is_power_of_two(), "RxBuffer size must be a power of two") };
+        RxBuffer {
+            buf: UnsafeCell::new([0; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU32::new(0),
+            error: AtomicU8::new(0),
+            uart: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Interrupt handler, call it from the handler of the UART the buffer is attached to
+    ///
+    /// Drains the RX FIFO into the buffer. Does nothing while no receiver is attached.
+    pub fn on_interrupt(&self) {
+        let uart = self.uart.load(Ordering::Acquire);
+        if uart.is_null() {
+            return;
+        }
+        let uart = unsafe { &*uart };
+        for _ in 0..rx_count(uart) {
+            let byte = uart.uart_fifo_rdata.read().uart_fifo_rdata().bits();
+            self.push(byte);
+        }
+        // clearing an overrun also clears the FIFO, so only do it once the bytes are saved
+        if let Err(error) = take_rx_error(uart) {
+            if let Error::Overrun = error {
+                // the hardware doesn't say how many, at least one byte was lost
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            self.latch_error(error);
+        }
+        uart.uart_int_clear.write(|w| w.cr_urx_rto_clr().set_bit());
+    }
+
+    /// Keep `error` for the reader, unless an earlier one was not taken yet
+    fn latch_error(&self, error: Error) {
+        let code = error_code(&error);
+        let _ = self.error.compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    /// Take the latched error
+    fn take_error(&self) -> Option<Error> {
+        error_from_code(self.error.swap(0, Ordering::Relaxed))
+    }
+
+    fn push(&self, byte: u8) {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) == N {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        unsafe { (*self.buf.get())[head % N] = byte };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+    }
+
+    fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let byte = unsafe { (*self.buf.get())[tail % N] };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+
+    fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Relaxed);
+        self.head.load(Ordering::Acquire).wrapping_sub(tail)
+    }
+}
+
+/// Code of `error` in `RxBuffer::error`
+fn error_code(error: &Error) -> u8 {
+    match error {
+        Error::Framing => 1,
+        Error::Noise => 2,
+        Error::Overrun => 3,
+        Error::Parity => 4,
+        Error::Baudrate => 5,
+    }
+}
+
+/// Error of an `RxBuffer::error` code, `None` for 0
+fn error_from_code(code: u8) -> Option<Error> {
+    match code {
+        1 => Some(Error::Framing),
+        2 => Some(Error::Noise),
+        3 => Some(Error::Overrun),
+        4 => Some(Error::Parity),
+        5 => Some(Error::Baudrate),
+        _ => None,
+    }
+}
+
+impl<const N: usize> Default for RxBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<UART: Instance, PINS> Serial<UART, PINS> {
+    /// Split off the receiver and let the UART interrupt fill `buffer`
+    ///
+    /// The interrupt fires once the RX FIFO holds more than 16 bytes, or when the line has
+    /// been idle for 40 bit times with bytes left in it. Enable the interrupt of the UART,
+    /// [`Instance::INTERRUPT`], in the CLIC and call [`RxBuffer::on_interrupt`] from its handler.
+    /// Each UART needs its own buffer:
+    ///
+    /// ```rust
+    ///   static RX: RxBuffer<256> = RxBuffer::new();
+    ///
+    ///   // UART0 handler
+    ///   RX.on_interrupt();
+    ///
+    ///   let (mut tx, mut rx) = serial.into_buffered_rx(&RX);
+    ///   unsafe { interrupt::enable(UART0::INTERRUPT) };
+    ///   while let Some(byte) = rx.read() {
+    ///       handle(byte);
+    ///   }
+    ///   if rx.overflowed() {
+    ///       log_lost(rx.dropped());
+    ///   }
+    ///   if let Some(error) = rx.take_error() {
+    ///       log_error(error);
+    ///   }
+    /// ```
+    ///
+    /// # Panics
+    /// if `buffer` is already attached to a receiver
+    pub fn into_buffered_rx<const N: usize>(
+        self,
+        buffer: &'static RxBuffer<N>,
+    ) -> (TxHalf<UART, PINS>, BufferedRx<UART, N>) {
+        let (tx, rx) = self.split();
+        (tx, rx.into_buffered(buffer))
+    }
+}
+
+impl<UART: Instance> RxHalf<UART> {
+    /// Let the UART interrupt fill `buffer`, see [`Serial::into_buffered_rx`]
+    ///
+    /// # Panics
+    /// if `buffer` is already attached to a receiver
+    pub fn into_buffered<const N: usize>(
+        self,
+        buffer: &'static RxBuffer<N>,
+    ) -> BufferedRx<UART, N> {
+        let uart = self.regs();
+        let attached = buffer.uart.compare_exchange(
+            core::ptr::null_mut(),
+            uart as *const _ as *mut _,
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+        );
+        assert!(attached.is_ok(), "RX buffer is already in use");
+
+        // the register also holds the TX threshold, which the other half may be changing
+        riscv::interrupt::free(|| {
+            uart.uart_fifo_config_1
+                .modify(|_, w| unsafe { w.rx_fifo_th().bits(RX_FIFO_THRESHOLD) })
+        });
+        uart.urx_rto_timer
+            .write(|w| unsafe { w.cr_urx_rto_value().bits(RX_TIMEOUT_BITS) });
+        set_rx_interrupts(uart, true);
+        BufferedRx { rx: self, buffer }
+    }
+}
+
+/// Enable or disable the RX FIFO threshold and RX timeout interrupts
+fn set_rx_interrupts(uart: &pac::uart::RegisterBlock, enable: bool) {
+    // shared with the TX interrupt bits
+    riscv::interrupt::free(|| {
+        uart.uart_int_clear.write(|w| w.cr_urx_rto_clr().set_bit());
+        uart.uart_int_en
+            .modify(|_, w| w.cr_urx_fifo_en().bit(enable).cr_urx_rto_en().bit(enable));
+        uart.uart_int_mask.modify(|_, w| {
+            w.cr_urx_fifo_mask()
+                .bit(!enable)
+                .cr_urx_rto_mask()
+                .bit(!enable)
+        });
+    });
+}
+
+/// Interrupt driven receiver, from [`Serial::into_buffered_rx`]
+pub struct BufferedRx<UART, const N: usize> {
+    rx: RxHalf<UART>,
+    buffer: &'static RxBuffer<N>,
+}
+
+impl<UART: Instance, const N: usize> BufferedRx<UART, N> {
+    /// Take the oldest received byte, `None` if there is none
+    pub fn read(&mut self) -> Option<u8> {
+        self.buffer.pop()
+    }
+
+    /// Number of bytes waiting in the buffer
+    pub fn available(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns true if bytes were dropped since the last [`BufferedRx::clear_overflow`]
+    pub fn overflowed(&self) -> bool {
+        self.dropped() != 0
+    }
+
+    /// Number of bytes dropped since the last [`BufferedRx::clear_overflow`]
+    ///
+    /// Counts bytes that arrived while the buffer was full, and one per hardware RX FIFO
+    /// overrun, which the interrupt handler was too late to prevent.
+    pub fn dropped(&self) -> u32 {
+        self.buffer.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Reset the dropped byte count
+    pub fn clear_overflow(&mut self) {
+        self.buffer.dropped.store(0, Ordering::Relaxed);
+    }
+
+    /// Take the first framing, parity or overrun error the interrupt handler saw since the last
+    /// call, `None` if there was none
+    ///
+    /// The bytes received around the error stay in the buffer. The `embedded-hal-nb` and
+    /// `embedded-io` reads return the error once before the next byte.
+    pub fn take_error(&mut self) -> Option<Error> {
+        self.buffer.take_error()
+    }
+
+    /// Stop the interrupts, detach the buffer and return the plain receiver
+    ///
+    /// Bytes still in the buffer are discarded.
+    pub fn free(self) -> RxHalf<UART> {
+        set_rx_interrupts(self.rx.regs(), false);
+        self.buffer.uart.store(core::ptr::null_mut(), Ordering::Release);
+        let head = self.buffer.head.load(Ordering::Acquire);
+        self.buffer.tail.store(head, Ordering::Release);
+        self.rx
+    }
+}
+
+impl<UART: Instance, const N: usize> embedded_hal_nb::serial::ErrorType for BufferedRx<UART, N> {
+    type Error = Error;
+}
+
+impl<UART: Instance, const N: usize> embedded_hal_nb::serial::Read<u8> for BufferedRx<UART, N> {
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        if let Some(error) = self.buffer.take_error() {
+            return Err(nb::Error::Other(error));
+        }
+        self.buffer.pop().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<UART: Instance, const N: usize> embedded_io::ErrorType for BufferedRx<UART, N> {
+    type Error = Error;
+}
+
+impl<UART: Instance, const N: usize> embedded_io::Read for BufferedRx<UART, N> {
+    /// Blocks until a byte is buffered, then takes up to the size of `buf`
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.buffer.len() == 0 && self.buffer.error.load(Ordering::Relaxed) == 0 {}
+        if let Some(error) = self.buffer.take_error() {
+            return Err(error);
+        }
+        let mut n = 0;
+        for slot in buf.iter_mut() {
+            match self.buffer.pop() {
+                Some(byte) => *slot = byte,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+}
+
+impl<UART: Instance, const N: usize> embedded_io::ReadReady for BufferedRx<UART, N> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.buffer.len() != 0)
+    }
+}
+
+// halves are meant to be moved into interrupt handlers
+const _: () = {
+    const fn assert_send<T: Send>() {}
+    assert_send::<RxHalf<UART0>>();
+    assert_send::<TxHalf<UART0, ()>>();
+    assert_send::<RxHalf<UART1>>();
+    assert_send::<TxHalf<UART1, ()>>();
+};
+
 // TODO: make these sealed instead of unsafe
 /// Serial transmit pins - DO NOT IMPLEMENT THIS TRAIT
 ///
@@ -353,35 +1320,58 @@ pub unsafe trait RxPin<UART> {}
 ///
 /// # Safety
 /// DO NOT IMPLEMENT THIS TRAIT
-pub unsafe trait RtsPin<UART> {}
+pub unsafe trait RtsPin<UART> {
+    /// False for [`NoRts`]
+    const PRESENT: bool = true;
+}
 /// Serial cts pins - DO NOT IMPLEMENT THIS TRAIT
 ///
 /// # Safety
 /// DO NOT IMPLEMENT THIS TRAIT
-pub unsafe trait CtsPin<UART> {}
+pub unsafe trait CtsPin<UART> {
+    /// False for [`NoCts`]
+    const PRESENT: bool = true;
+}
+
+/// Placeholder for the RTS pin of a UART used with CTS only
+pub struct NoRts;
+/// Placeholder for the CTS pin of a UART used with RTS only
+pub struct NoCts;
+
+unsafe impl<UART> RtsPin<UART> for NoRts {
+    const PRESENT: bool = false;
+}
+unsafe impl<UART> CtsPin<UART> for NoCts {
+    const PRESENT: bool = false;
+}
 
 macro_rules! impl_uart_pin {
-    ($(($UartSigi: ident, $UartMuxi: ident),)+) => {
-        use crate::gpio::*;
+    ($UART: ident, $Tx: ident, $Rx: ident, $Rts: ident, $Cts: ident) => {
+        impl_uart_pin!(
+            $UART, $Tx, $Rx, $Rts, $Cts;
+            (UartSig0, UartMux0),
+            (UartSig1, UartMux1),
+            (UartSig2, UartMux2),
+            (UartSig3, UartMux3),
+            (UartSig4, UartMux4),
+            (UartSig5, UartMux5),
+            (UartSig6, UartMux6),
+            (UartSig7, UartMux7),
+        );
+    };
+    ($UART: ident, $Tx: ident, $Rx: ident, $Rts: ident, $Cts: ident; $(($UartSigi: ident, $UartMuxi: ident),)+) => {
         $(
-        unsafe impl<PIN: UartPin<$UartSigi>> TxPin<pac::UART> for (PIN, $UartMuxi<Uart0Tx>) {}
-        unsafe impl<PIN: UartPin<$UartSigi>> RxPin<pac::UART> for (PIN, $UartMuxi<Uart0Rx>) {}
-        unsafe impl<PIN: UartPin<$UartSigi>> RtsPin<pac::UART> for (PIN, $UartMuxi<Uart0Rts>) {}
-        unsafe impl<PIN: UartPin<$UartSigi>> CtsPin<pac::UART> for (PIN, $UartMuxi<Uart0Cts>) {}
+        unsafe impl<PIN: UartPin<$UartSigi>> TxPin<$UART> for (PIN, $UartMuxi<$Tx>) {}
+        unsafe impl<PIN: UartPin<$UartSigi>> RxPin<$UART> for (PIN, $UartMuxi<$Rx>) {}
+        unsafe impl<PIN: UartPin<$UartSigi>> RtsPin<$UART> for (PIN, $UartMuxi<$Rts>) {}
+        unsafe impl<PIN: UartPin<$UartSigi>> CtsPin<$UART> for (PIN, $UartMuxi<$Cts>) {}
         )+
     };
 }
 
-impl_uart_pin!(
-    (UartSig0, UartMux0),
-    (UartSig1, UartMux1),
-    (UartSig2, UartMux2),
-    (UartSig3, UartMux3),
-    (UartSig4, UartMux4),
-    (UartSig5, UartMux5),
-    (UartSig6, UartMux6),
-    (UartSig7, UartMux7),
-);
+use crate::gpio::{uart_sig::*, UartPin};
+impl_uart_pin!(UART0, Uart0Tx, Uart0Rx, Uart0Rts, Uart0Cts);
+impl_uart_pin!(UART1, Uart1Tx, Uart1Rx, Uart1Rts, Uart1Cts);
 
 /// Serial pins - DO NOT IMPLEMENT THIS TRAIT
 // TODO: make these sealed instead of unsafe
@@ -410,13 +1400,13 @@ unsafe impl<UART, TX, RX, RTS, CTS> Pins<UART> for (TX, RX, RTS, CTS)
 where
     TX: TxPin<UART>,
     RX: RxPin<UART>,
-    RTS: RxPin<UART>,
-    CTS: RxPin<UART>,
+    RTS: RtsPin<UART>,
+    CTS: CtsPin<UART>,
 {
     const HAS_TX: bool = true;
     const HAS_RX: bool = true;
-    const HAS_RTS: bool = true;
-    const HAS_CTS: bool = true;
+    const HAS_RTS: bool = RTS::PRESENT;
+    const HAS_CTS: bool = CTS::PRESENT;
 }
 
 #[cfg(feature = "print_serial")]
@@ -518,3 +1508,72 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     });
     loop {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rx_buffer_keeps_order() {
+        let buffer = RxBuffer::<4>::new();
+        assert_eq!(buffer.pop(), None);
+        buffer.push(1);
+        buffer.push(2);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop(), Some(1));
+        buffer.push(3);
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn rx_buffer_latches_the_first_error() {
+        let buffer = RxBuffer::<4>::new();
+        assert!(buffer.take_error().is_none());
+        buffer.latch_error(Error::Parity);
+        buffer.latch_error(Error::Framing);
+        assert!(matches!(buffer.take_error(), Some(Error::Parity)));
+        assert!(buffer.take_error().is_none());
+        for error in [Error::Framing, Error::Noise, Error::Overrun, Error::Baudrate] {
+            let code = error_code(&error);
+            buffer.latch_error(error);
+            assert_eq!(buffer.take_error().map(|taken| error_code(&taken)), Some(code));
+        }
+    }
+
+    #[test]
+    fn rx_buffer_drops_when_full() {
+        let buffer = RxBuffer::<4>::new();
+        for byte in 0..6 {
+            buffer.push(byte);
+        }
+        assert_eq!(buffer.len(), 4);
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 2);
+        for byte in 0..4 {
+            assert_eq!(buffer.pop(), Some(byte));
+        }
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn rx_buffer_survives_index_wrap() {
+        let buffer = RxBuffer::<8>::new();
+        let start = usize::MAX - 2;
+        buffer.head.store(start, Ordering::Relaxed);
+        buffer.tail.store(start, Ordering::Relaxed);
+        for byte in 0..8 {
+            buffer.push(byte);
+        }
+        // full across the wrap, nothing written over
+        buffer.push(0xff);
+        assert_eq!(buffer.dropped.load(Ordering::Relaxed), 1);
+        assert_eq!(buffer.len(), 8);
+        for byte in 0..8 {
+            assert_eq!(buffer.pop(), Some(byte));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert!(buffer.head.load(Ordering::Relaxed) < start);
+    }
+}
@@ -0,0 +1,215 @@
+/*!
+# Watchdog
+The watchdog lives in the TIMER block, so its registers are handed out by `TimerExt::split`.
+It counts the 32 kHz clock, which keeps it independent of the core clock configuration:
+
+```rust
+  if watchdog::was_reset_by_watchdog() {
+      // log the reboot
+      watchdog::clear_reset_status();
+  }
+  let timers = dp.TIMER.split(&clocks);
+  let mut wdt = Watchdog::new(timers.wdt, Milliseconds(500u32), &clocks);
+  loop {
+      do_work();
+      wdt.feed();
+  }
+```
+
+Every write to a watchdog register has to be preceded by writing the two access keys to `WFAR`
+and `WSAR`, the driver does that, with interrupts disabled so the sequence can't be split. The
+clock select and divider registers are shared with the counters, only the watchdog fields of
+them are changed.
+
+## Early warning
+With [`Watchdog::listen`] an expiry raises `TIMER_WDT` instead of resetting, which leaves time
+to save diagnostics. The handler then calls [`escalate`], which switches back to reset mode with
+a short deadline, so the chip still resets if the handler itself hangs:
+
+```rust
+  /// 4 KiB of HBN RAM, retained across resets
+  const CRASH_LOG: *mut u32 = 0x4001_0000 as *mut u32;
+
+  #[export_name = "DefaultHandler"]
+  fn default_handler() {
+      if interrupt::current() == Some(Interrupt::TIMER_WDT) {
+          watchdog::escalate(Milliseconds(10u32));
+          unsafe { CRASH_LOG.write_volatile(riscv::register::mepc::read() as u32) };
+          // the reset follows within 10 ms
+          loop {}
+      }
+  }
+
+  let mut wdt = Watchdog::new(timers.wdt, Milliseconds(500u32), &clocks);
+  unsafe { wdt.listen() };
+```
+*/
+
+use crate::clock::{enable_gate, Clocks, Gate};
+use crate::interrupt::{self, Interrupt};
+use crate::pac;
+use crate::timer::{divider, merge_tccr, merge_tcdr, modify_shared, write_protected, WDT_ID};
+use embedded_time::duration::Microseconds;
+
+/// Largest prescaler division
+const MAX_PRESCALER: u64 = 256;
+
+/// `tccr` clock select of the 32 kHz clock
+const CS_F32K: u8 = 1;
+
+/// Watchdog registers of the TIMER block
+pub struct Wdt {
+    pub(crate) _private: (),
+}
+
+/// What happens when the watchdog is not fed in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Reset the chip
+    Reset,
+    /// Raise the `TIMER_WDT` interrupt, which has to call `Watchdog::clear_interrupt`
+    Interrupt,
+}
+
+/// Watchdog timer
+pub struct Watchdog {
+    wdt: Wdt,
+    f32k: u32,
+}
+
+impl Watchdog {
+    /// Arms the watchdog in reset mode with `timeout`
+    ///
+    /// # Panics
+    /// if `timeout` is longer than [`Watchdog::max_timeout`]
+    pub fn new<T: Into<Microseconds<u64>>>(wdt: Wdt, timeout: T, clocks: &Clocks) -> Self {
+        let mut watchdog = Watchdog {
+            wdt,
+            f32k: clocks.f32k().0,
+        };
+        watchdog.set_mode(Mode::Reset);
+        watchdog.set_timeout(timeout);
+        watchdog.enable();
+        watchdog
+    }
+
+    /// Longest timeout the watchdog supports, 16 bits of the fully divided 32 kHz clock
+    pub fn max_timeout(&self) -> Microseconds<u64> {
+        Microseconds(u16::MAX as u64 * MAX_PRESCALER * 1_000_000 / self.f32k as u64)
+    }
+
+    /// Change the timeout, rounded up to whole ticks, and restart the count
+    ///
+    /// # Panics
+    /// if `timeout` is longer than [`Watchdog::max_timeout`]
+    pub fn set_timeout<T: Into<Microseconds<u64>>>(&mut self, timeout: T) {
+        let ticks = (timeout.into().0 * self.f32k as u64)
+            .div_ceil(1_000_000)
+            .max(1);
+        let div = ticks.div_ceil(u16::MAX as u64);
+        assert!(div <= MAX_PRESCALER, "watchdog timeout too long");
+        let compare = ticks.div_ceil(div) as u16;
+
+        modify_shared(|timer| {
+            timer
+                .tccr
+                .modify(|r, w| unsafe { w.bits(merge_tccr(r.bits(), WDT_ID, CS_F32K)) });
+            timer
+                .tcdr
+                .modify(|r, w| unsafe { w.bits(merge_tcdr(r.bits(), WDT_ID, (div - 1) as u8)) });
+            write_protected(|timer| timer.wmr.write(|w| unsafe { w.wmr().bits(compare) }));
+            write_protected(|timer| timer.wcr.write(|w| w.wcr().set_bit()));
+        });
+    }
+
+    /// Select whether expiry resets the chip or raises an interrupt
+    pub fn set_mode(&mut self, mode: Mode) {
+        write_protected(|timer| timer.wmer.modify(|_, w| w.wrie().bit(mode == Mode::Reset)));
+    }
+
+    /// Restart the count
+    pub fn feed(&mut self) {
+        write_protected(|timer| timer.wcr.write(|w| w.wcr().set_bit()));
+    }
+
+    /// Start counting, from 0
+    pub fn enable(&mut self) {
+        self.feed();
+        write_protected(|timer| timer.wmer.modify(|_, w| w.we().set_bit()));
+    }
+
+    /// Stop the watchdog
+    pub fn disable(&mut self) {
+        write_protected(|timer| timer.wmer.modify(|_, w| w.we().clear_bit()));
+    }
+
+    /// Raise `TIMER_WDT` on expiry instead of resetting, and enable it in the CLIC
+    ///
+    /// See the [module documentation](self) for a handler.
+    ///
+    /// # Safety
+    /// This can break critical sections, and the handler for `TIMER_WDT` must be in place
+    pub unsafe fn listen(&mut self) {
+        self.clear_interrupt();
+        self.set_mode(Mode::Interrupt);
+        interrupt::enable(Interrupt::TIMER_WDT);
+    }
+
+    /// Reset on expiry again, and disable `TIMER_WDT` in the CLIC
+    pub fn unlisten(&mut self) {
+        self.set_mode(Mode::Reset);
+        interrupt::disable(Interrupt::TIMER_WDT);
+        self.clear_interrupt();
+    }
+
+    /// Clear the expiry interrupt in `Mode::Interrupt`, this also restarts the count
+    pub fn clear_interrupt(&mut self) {
+        write_protected(|timer| timer.wicr.write(|w| w.wiclr().set_bit()));
+        self.feed();
+    }
+
+    /// Stops the watchdog and returns its registers
+    pub fn free(mut self) -> Wdt {
+        self.disable();
+        self.wdt
+    }
+}
+
+/// Switch the watchdog to reset mode with a deadline of `after` from now
+///
+/// Meant for the `TIMER_WDT` handler, which has no access to the [`Watchdog`]: it clears the
+/// interrupt and keeps the prescaler, so `after` is limited to the 16 bit compare value and
+/// longer deadlines are cut to it, 2 s for watchdog timeouts of up to 2 s.
+pub fn escalate<T: Into<Microseconds<u64>>>(after: T) {
+    let timer = unsafe { &*pac::TIMER::ptr() };
+    let div = divider(timer.tcdr.read().bits(), WDT_ID) as u64 + 1;
+    let f32k = crate::clock::hbn::frequency().0 as u64;
+    let ticks = (after.into().0 * f32k).div_ceil(1_000_000 * div);
+    let compare = ticks.clamp(1, u16::MAX as u64) as u16;
+    modify_shared(|_| {
+        write_protected(|timer| timer.wmr.write(|w| unsafe { w.wmr().bits(compare) }));
+        write_protected(|timer| timer.wcr.write(|w| w.wcr().set_bit()));
+        write_protected(|timer| timer.wmer.modify(|_, w| w.wrie().set_bit()));
+        write_protected(|timer| timer.wicr.write(|w| w.wiclr().set_bit()));
+    });
+}
+
+/// Returns true if the last reset was caused by the watchdog
+///
+/// The flag stays set until [`clear_reset_status`] is called.
+pub fn was_reset_by_watchdog() -> bool {
+    enable_clock();
+    let timer = unsafe { &*pac::TIMER::ptr() };
+    timer.wsr.read().wts().bit()
+}
+
+/// Clear the watchdog reset flag, so the next boot reports its own cause
+pub fn clear_reset_status() {
+    enable_clock();
+    write_protected(|timer| timer.wsr.write(|w| w.wts().clear_bit()));
+}
+
+/// Enable the timer clock, so the status can be read before `TimerExt::split`
+fn enable_clock() {
+    enable_gate(Gate::Timer);
+}